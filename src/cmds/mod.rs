@@ -251,6 +251,21 @@ fn papyrus_cmdr<D>(
         .add_action("mut", "Begin a mutable block of code", |_, _| {
             CommandResult::BeginMutBlock
         })
+        .add_action(
+            "clean",
+            "Remove compiled artifacts to reclaim disk space",
+            |_, _| CommandResult::repl_data_fn(|data, _| clean_priv(data)),
+        )
+        .add_action(
+            "code",
+            "Show the files generated for compilation",
+            |_, _| CommandResult::repl_data_fn(|data, _| code_priv(data)),
+        )
+        .add_action(
+            "type",
+            "Show the type of an expression without evaluating it. args: expr",
+            |wtr, args| type_of_priv(args, wtr),
+        )
         .begin_class("edit", "Edit previous input")
         .begin_class("stmt", "Edit previous statements")
         .add_action(
@@ -275,6 +290,34 @@ fn papyrus_cmdr<D>(
         .into_commander()
 }
 
+fn clean_priv<D>(data: &mut ReplData<D>) -> String {
+    match data.clean_compilation_dir() {
+        Ok(bytes) => format!("freed {:.1} MB", bytes as f64 / 1_000_000.0),
+        Err(e) => format!("failed to clean compilation directory: {}", e),
+    }
+}
+
+fn code_priv<D>(data: &mut ReplData<D>) -> String {
+    data.project_files()
+        .into_iter()
+        .map(|(path, contents)| format!("--- {}\n{}", path.display(), contents))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn type_of_priv<D, W: Write>(args: &[&str], mut wtr: W) -> CommandResult<D> {
+    if args.is_empty() {
+        writeln!(wtr, "type expects an expression").ok();
+        return CommandResult::Empty;
+    }
+
+    let expr = args.join(" ");
+    CommandResult::repl_data_fn(move |data, _| match data.type_of(&expr) {
+        Ok(ty) => ty,
+        Err(e) => e.to_string(),
+    })
+}
+
 fn switch_module_priv<D, W: Write>(args: &[&str], mut wtr: W) -> CommandResult<D> {
     if let Some(path) = args.get(0) {
         if let Some(path) = make_path(path) {
@@ -418,3 +461,35 @@ fn make_all_parents_test() {
         vec![PathBuf::from("test"), PathBuf::from("test/inner")]
     );
 }
+
+#[test]
+fn custom_cmd_test() {
+    use crate as papyrus;
+    use crate::repl::ReadResult;
+
+    let mut repl = repl!(String);
+    repl.data
+        .with_cmdtree_builder(
+            Builder::new("papyrus")
+                .add_action("echo", "append args to app_data", |_, args| {
+                    let args = args.join(",");
+                    CommandResult::app_data_fn(move |app_data: &mut String, _, _| {
+                        app_data.push_str(&args);
+                        String::new()
+                    })
+                })
+                .unwrap(),
+        )
+        .unwrap();
+
+    let mut app_data = String::new();
+
+    repl.line_input(":echo hi there");
+    let eval = match repl.read() {
+        ReadResult::Eval(eval) => eval,
+        ReadResult::Read(_) => panic!("expecting eval"),
+    };
+    eval.eval(&mut app_data);
+
+    assert_eq!(&app_data, "hi,there");
+}
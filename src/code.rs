@@ -66,6 +66,8 @@ pub struct Input {
     pub stmts: Vec<Statement>,
     /// The referenced crates.
     pub crates: Vec<CrateType>,
+    /// Compile with optimisations, set by a `//! papyrus: release` directive.
+    pub release: bool,
 }
 
 /// The flattened representation of source code.
@@ -89,6 +91,73 @@ impl SourceCode {
             crates: Vec::new(),
         }
     }
+
+    /// Merge `other` onto the end of this source code.
+    ///
+    /// Items and statements are appended in order. Crates are unioned, a crate with the same
+    /// source line as an existing crate is not repeated, only its features are added.
+    ///
+    /// This can be used to compose a prelude with user code.
+    pub fn merge(mut self, other: SourceCode) -> SourceCode {
+        let SourceCode {
+            items,
+            stmts,
+            crates,
+        } = other;
+
+        self.items.extend(items);
+        self.stmts.extend(stmts);
+
+        for c in crates {
+            match self.crates.iter_mut().find(|x| x.src_line == c.src_line) {
+                Some(existing) => {
+                    for f in c.features {
+                        if !existing.features.contains(&f) {
+                            existing.features.push(f);
+                        }
+                    }
+                }
+                None => self.crates.push(c),
+            }
+        }
+
+        self
+    }
+
+    /// A fingerprint of the source code, for use as a cache key or to detect changes.
+    ///
+    /// The items and statements are hashed in order, whereas the crates (and their features) are
+    /// order-independent. The hash does not use a random seed, so the fingerprint is stable across
+    /// runs for identical source code.
+    pub fn fingerprint(&self) -> u64 {
+        use std::hash::{Hash, Hasher};
+
+        let mut hasher = fxhash::FxHasher64::default();
+
+        self.items.hash(&mut hasher);
+
+        for grp in &self.stmts {
+            grp.0.len().hash(&mut hasher);
+            for stmt in &grp.0 {
+                stmt.expr.hash(&mut hasher);
+                stmt.semi.hash(&mut hasher);
+            }
+        }
+
+        let mut crates = self
+            .crates
+            .iter()
+            .map(|c| {
+                let mut features = c.features.iter().collect::<Vec<_>>();
+                features.sort();
+                (&c.src_line, &c.cargo_name, &c.version, &c.source, features)
+            })
+            .collect::<Vec<_>>();
+        crates.sort();
+        crates.hash(&mut hasher);
+
+        hasher.finish()
+    }
 }
 
 /// Group of statements that result in an expression to evaulate.
@@ -360,6 +429,11 @@ fn append_buffer<S: AsRef<str>>(
         buf.push('\n');
     }
 
+    // inject prelude
+    if let Some(prelude) = &linking_config.prelude {
+        prelude.construct_uses(buf);
+    }
+
     // wrap stmts
     buf.push_str("#[no_mangle]\npub extern \"C\" fn "); // 31 len
     eval_fn_name(mod_path, buf);
@@ -367,6 +441,10 @@ fn append_buffer<S: AsRef<str>>(
     linking_config.construct_fn_args(buf);
     buf.push_str(") -> kserd::Kserd<'static> {\n"); // 29 len
 
+    let try_op = uses_try_operator(src_code);
+    let (open, close) = stmts_wrapping(linking_config.runtime, try_op);
+    buf.push_str(open);
+
     // add stmts
     let c = src_code.stmts.len();
     if c >= 1 {
@@ -375,12 +453,19 @@ fn append_buffer<S: AsRef<str>>(
             x.assign_let_binding(i, buf);
             buf.push('\n');
         });
+        if try_op {
+            buf.push_str(OK_START);
+        }
         buf.push_str("kserd::ToKserd::into_kserd(out");
         buf.push_str(&c.saturating_sub(1).to_string());
         buf.push_str(").unwrap().to_owned()\n");
+        if try_op {
+            buf.push_str(")\n");
+        }
     } else {
         buf.push_str("kserd::Kserd::new_str(\"no statements\")\n");
     }
+    buf.push_str(close);
     buf.push_str("}\n");
 
     // add items
@@ -407,9 +492,18 @@ fn append_buffer_length<S: AsRef<str>>(
         cap += linking_config.persistent_module_code.len() + 1;
     }
 
+    // prelude
+    if let Some(prelude) = &linking_config.prelude {
+        cap += prelude.construct_uses_length();
+    }
+
     // wrap stmts
     cap += 31 + eval_fn_name_length(mod_path) + 1 + linking_config.construct_fn_args_length() + 29;
 
+    let try_op = uses_try_operator(src_code);
+    let (open, close) = stmts_wrapping(linking_config.runtime, try_op);
+    cap += open.len();
+
     // add stmts
     let c = src_code.stmts.len();
     let (add, rng) = if c >= 1 {
@@ -422,16 +516,17 @@ fn append_buffer_length<S: AsRef<str>>(
         let return_str = 30 // kserd::ToKserd::into_kserd(out
             + c.saturating_sub(1).to_string().len()
             + 22; // ).unwrap().to_owned()\n
+        let (ok_start, ok_end) = if try_op { (OK_START.len(), 2) } else { (0, 0) };
 
         (
-            stmts + return_str,
-            cap + stmts..cap + stmts + return_str - 1,
+            stmts + ok_start + return_str + ok_end,
+            cap + stmts + ok_start..cap + stmts + ok_start + return_str - 1,
         )
     } else {
         // kserd::Kserd::new_str("no statements")\n
         (39, cap..cap + 38)
     };
-    cap += add + 2; // }\n
+    cap += add + close.len() + 2; // }\n
 
     // add items
     cap += src_code
@@ -444,6 +539,62 @@ fn append_buffer_length<S: AsRef<str>>(
     (cap, rng)
 }
 
+/// Opens the `Ok` wrapping of the return value when the statements use the `?` operator.
+const OK_START: &str = "Ok::<kserd::Kserd<'static>, Box<dyn std::error::Error>>(\n";
+
+/// The identity of the data returned by the evaluation function when a `?` operator returns an
+/// error. The value is the error message.
+pub(crate) const EVAL_ERROR_ID: &str = "papyrus::EvalError";
+
+/// The code which opens and closes the statements in the evaluation function.
+///
+/// An async runtime runs the statements in an `async` block. If the statements use the `?`
+/// operator, they are wrapped in a closure (or the `async` block) returning a `Result`, an error is
+/// returned as its message with the [`EVAL_ERROR_ID`] identity.
+fn stmts_wrapping(
+    runtime: Option<linking::AsyncRuntime>,
+    try_op: bool,
+) -> (&'static str, &'static str) {
+    // the identity is EVAL_ERROR_ID
+    macro_rules! on_err {
+        ($close:literal) => {
+            concat!(
+                $close,
+                ".unwrap_or_else(|e| kserd::Kserd::with_id(\"papyrus::EvalError\", ",
+                "kserd::Value::new_string(e.to_string())).unwrap())\n"
+            )
+        };
+    }
+
+    match (runtime, try_op) {
+        (Some(rt), false) => (rt.block_on_start(), "})\n"),
+        (Some(rt), true) => (rt.block_on_start(), on_err!("})")),
+        (None, true) => (
+            "(|| -> Result<kserd::Kserd<'static>, Box<dyn std::error::Error>> {\n",
+            on_err!("})()"),
+        ),
+        (None, false) => ("", ""),
+    }
+}
+
+/// The statements use the `?` operator. String literals are not checked.
+fn uses_try_operator(src_code: &SourceCode) -> bool {
+    fn has_question(stream: proc_macro2::TokenStream) -> bool {
+        stream.into_iter().any(|tt| match tt {
+            proc_macro2::TokenTree::Punct(p) => p.as_char() == '?',
+            proc_macro2::TokenTree::Group(g) => has_question(g.stream()),
+            _ => false,
+        })
+    }
+
+    src_code
+        .stmts
+        .iter()
+        .flat_map(|grp| grp.0.iter())
+        .filter_map(|stmt| stmt.expr.parse::<proc_macro2::TokenStream>().ok())
+        .any(has_question)
+}
+
 /// A single item.
 ///
 /// Wraps as `(content, top_placement)`.
@@ -481,6 +632,36 @@ pub struct CrateType {
     /// The name to use in cargo.
     /// Usually `crate_name` will turn into `crate-name`. The default behaviour is to replace `_` with a dash (`-`).
     pub cargo_name: String,
+    /// The crate features to enable.
+    ///
+    /// These can be set inline with a leading `//! papyrus: feature "crate_name/feature"`
+    /// directive on the input.
+    pub features: Vec<String>,
+    /// The version requirement, ie `0.7`. Defaults to `None`, which is any version (`*`).
+    ///
+    /// This can be set inline with a leading `//! papyrus: crate NAME = "VERSION"` directive on the
+    /// input, or in a script header, see [`parse_header`](crate::input::parse_header).
+    pub version: Option<String>,
+    /// Where the crate is fetched from. Defaults to [`CrateSource::Registry`].
+    pub source: CrateSource,
+}
+
+/// Where a crate is fetched from.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Default)]
+pub enum CrateSource {
+    /// `crates.io`.
+    #[default]
+    Registry,
+    /// A local directory. A relative path is resolved against the current directory when the
+    /// directive is parsed, not the compilation directory.
+    Path(PathBuf),
+    /// A git repository.
+    Git {
+        /// The repository url.
+        url: String,
+        /// The revision to check out, the default branch if `None`.
+        rev: Option<String>,
+    },
 }
 
 impl CrateType {
@@ -502,17 +683,163 @@ impl CrateType {
                     .nth(2)
                     .expect("should always have trailing item")
                     .to_string(),
+                features: Vec::new(),
+                version: None,
+                source: CrateSource::Registry,
             })
         } else {
             Err("line needs `extern crate NAME;`")
         }
     }
+
+    /// The crates referenced in the source code, by `extern crate` lines.
+    ///
+    /// `extern crate foo as bar;` keeps the alias in the [`src_line`](CrateType::src_line). The
+    /// built in crates (`std`, `core`, `alloc`, and `proc_macro`) are skipped, and each crate is
+    /// only returned once.
+    ///
+    /// With `uses`, the first segment of top-level `use` paths are also treated as crates, so
+    /// `use rand::Rng;` adds `extern crate rand;`. Modules declared in the source are not crates.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use papyrus::code::CrateType;
+    /// let src = "extern crate rand;\nextern crate std;\nuse fxhash::hash64;";
+    /// let crates = CrateType::scan_source(src, true);
+    /// assert_eq!(crates[0].cargo_name, "rand");
+    /// assert_eq!(crates[1].src_line, "extern crate fxhash;");
+    /// ```
+    pub fn scan_source(src: &str, uses: bool) -> Vec<Self> {
+        const BUILT_IN: &[&str] = &["std", "core", "alloc", "proc_macro"];
+        const PATH_ROOTS: &[&str] = &["crate", "self", "super"];
+
+        let ident = |s: &str| -> String {
+            s.chars()
+                .take_while(|c| c.is_alphanumeric() || *c == '_')
+                .collect()
+        };
+
+        let mods = src
+            .lines()
+            .filter_map(|line| line.strip_prefix("mod ").map(ident))
+            .collect::<Vec<_>>();
+
+        let mut crates: Vec<CrateType> = Vec::new();
+        for line in src.lines() {
+            let trimmed = line.trim();
+            let used = if let Some(rest) = trimmed.strip_prefix("extern crate ") {
+                let src_line = format!("extern crate {};", rest.trim_end_matches(';').trim());
+                Some((ident(rest), src_line))
+            } else if let Some(rest) = line.strip_prefix("use ").filter(|_| uses) {
+                let name = ident(rest.trim_start_matches("::"));
+                let src_line = format!("extern crate {};", name);
+                Some((name, src_line)).filter(|(name, _)| {
+                    !PATH_ROOTS.contains(&name.as_str()) && !mods.contains(name)
+                })
+            } else {
+                None
+            };
+
+            let (name, src_line) = match used {
+                Some(x) => x,
+                None => continue,
+            };
+            if name.is_empty() || BUILT_IN.contains(&name.as_str()) {
+                continue;
+            }
+
+            if let Ok(c) = CrateType::parse_str(&src_line) {
+                if !crates.iter().any(|x| x.cargo_name == c.cargo_name) {
+                    crates.push(c);
+                }
+            }
+        }
+
+        crates
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn crate_type_scan_source_test() {
+        let src = "extern crate rand;
+extern crate std;
+extern crate kserd_derive as kd;
+extern crate rand;
+mod local;
+use local::Thing;
+use ::fxhash::hash64;
+use std::io;
+use self::local::Other;
+fn a() {
+    use inner::x;
+}";
+        let names = |crates: Vec<CrateType>| {
+            crates
+                .into_iter()
+                .map(|c| (c.cargo_name, c.src_line))
+                .collect::<Vec<_>>()
+        };
+        let s = |a: &str, b: &str| (a.to_string(), b.to_string());
+
+        assert_eq!(
+            names(CrateType::scan_source(src, false)),
+            vec![
+                s("rand", "extern crate rand;"),
+                s("kserd-derive", "extern crate kserd_derive as kd;"),
+            ]
+        );
+        assert_eq!(
+            names(CrateType::scan_source(src, true)),
+            vec![
+                s("rand", "extern crate rand;"),
+                s("kserd-derive", "extern crate kserd_derive as kd;"),
+                s("fxhash", "extern crate fxhash;"),
+            ]
+        );
+    }
+
+    #[test]
+    fn fingerprint_test() {
+        let a_crate = |s| CrateType::parse_str(s).unwrap();
+        let src = |crates| {
+            let mut src = SourceCode::new();
+            src.items.push(("fn a() {}".to_string(), false));
+            src.stmts.push(StmtGrp(vec![Statement {
+                expr: "a()".to_string(),
+                semi: false,
+            }]));
+            src.crates = crates;
+            src
+        };
+
+        let a = src(vec![
+            a_crate("extern crate rand;"),
+            a_crate("extern crate kserd;"),
+        ]);
+        let b = src(vec![
+            a_crate("extern crate kserd;"),
+            a_crate("extern crate rand;"),
+        ]);
+        assert_eq!(a.fingerprint(), b.fingerprint());
+        assert_eq!(a.fingerprint(), a.clone().fingerprint());
+
+        let mut c = b.clone();
+        c.items[0].0 = "fn b() {}".to_string();
+        assert_ne!(a.fingerprint(), c.fingerprint());
+
+        let mut c = b.clone();
+        c.stmts[0].0[0].semi = true;
+        assert_ne!(a.fingerprint(), c.fingerprint());
+
+        let mut c = b.clone();
+        c.crates[0].features.push("std".to_string());
+        assert_ne!(a.fingerprint(), c.fingerprint());
+    }
+
     #[test]
     fn file_map_with_lvls_test() {
         let map = vec![
@@ -533,6 +860,59 @@ mod tests {
         assert_eq!(i.next(), None);
     }
 
+    #[test]
+    fn source_code_merge_test() {
+        let stmt = |expr: &str| {
+            StmtGrp(vec![Statement {
+                expr: expr.to_string(),
+                semi: false,
+            }])
+        };
+
+        let mut prelude = SourceCode::new();
+        prelude.items.push(("fn a() {}".to_string(), false));
+        prelude.stmts.push(stmt("a()"));
+        prelude
+            .crates
+            .push(CrateType::parse_str("extern crate rand;").unwrap());
+
+        let mut body = SourceCode::new();
+        body.items.push(("fn b() {}".to_string(), false));
+        body.stmts.push(stmt("b()"));
+        let mut rand = CrateType::parse_str("extern crate rand;").unwrap();
+        rand.features.push("std".to_string());
+        body.crates.push(rand);
+        body.crates
+            .push(CrateType::parse_str("extern crate regex;").unwrap());
+
+        let merged = prelude.merge(body);
+
+        assert_eq!(
+            merged.items,
+            vec![
+                ("fn a() {}".to_string(), false),
+                ("fn b() {}".to_string(), false)
+            ]
+        );
+        assert_eq!(
+            merged
+                .stmts
+                .iter()
+                .map(|x| x.src_line())
+                .collect::<Vec<_>>(),
+            vec!["a()".to_string(), "b()".to_string()]
+        );
+        assert_eq!(
+            merged
+                .crates
+                .iter()
+                .map(|c| c.cargo_name.as_str())
+                .collect::<Vec<_>>(),
+            vec!["rand", "regex"]
+        );
+        assert_eq!(merged.crates[0].features, vec!["std".to_string()]);
+    }
+
     #[test]
     fn test_parse_crate() {
         let err = Err("line needs `extern crate NAME;`");
@@ -552,6 +932,9 @@ mod tests {
             Ok(CrateType {
                 src_line: s,
                 cargo_name: String::from("somelib"),
+                features: Vec::new(),
+                version: None,
+                source: CrateSource::Registry,
             })
         );
 
@@ -562,6 +945,9 @@ mod tests {
             Ok(CrateType {
                 src_line: s,
                 cargo_name: String::from("some-lib"),
+                features: Vec::new(),
+                version: None,
+                source: CrateSource::Registry,
             })
         );
 
@@ -572,6 +958,9 @@ mod tests {
             Ok(CrateType {
                 src_line: s,
                 cargo_name: String::from("some"),
+                features: Vec::new(),
+                version: None,
+                source: CrateSource::Registry,
             })
         );
 
@@ -582,6 +971,9 @@ mod tests {
             Ok(CrateType {
                 src_line: s,
                 cargo_name: String::from("some-lib"),
+                features: Vec::new(),
+                version: None,
+                source: CrateSource::Registry,
             })
         );
     }
@@ -766,6 +1158,44 @@ fn b() {}
         );
     }
 
+    #[test]
+    fn prelude_test() {
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "a".to_string(),
+            semi: false,
+        }]));
+        let mods_map = vec![("lib".into(), src_code)].into_iter().collect();
+
+        let mut linking_config = linking::LinkingConfiguration {
+            persistent_module_code: "// persistent".to_string(),
+            ..Default::default()
+        };
+
+        let (s, _) = construct_source_code(&mods_map, &linking_config);
+        assert!(!s.contains("use "));
+
+        linking_config.prelude = Some(linking::PreludeConfig::default());
+        let (s, _) = construct_source_code(&mods_map, &linking_config);
+        assert!(s.starts_with(
+            "// persistent\nuse std::collections::*;\nuse std::io::Write;\n#[no_mangle]\n"
+        ));
+
+        linking_config.prelude = Some(
+            linking::PreludeConfig::new(vec!["std::rc::Rc", " std::fmt::{self, Write} "]).unwrap(),
+        );
+        let (s, _) = construct_source_code(&mods_map, &linking_config);
+        assert!(s.starts_with(
+            "// persistent\nuse std::rc::Rc;\nuse std::fmt::{self, Write};\n#[no_mangle]\n"
+        ));
+        assert_eq!(s.matches("use ").count(), 2);
+
+        assert_eq!(
+            linking::PreludeConfig::new(vec!["std::rc::Rc", "not a path"]),
+            Err("`not a path` is not a valid use path".to_string())
+        );
+    }
+
     #[test]
     fn construct_src_test() {
         // purely tests module adding
@@ -1,13 +1,22 @@
-use super::LIBRARY_NAME;
-use std::io::{self, BufRead, BufReader};
+use super::{json_diagnostics, BuildConfig, Diagnostic, Level};
+use crossbeam_channel::{Receiver, RecvTimeoutError};
+use std::ffi::OsStr;
+use std::io::{self, BufRead, BufReader, Read};
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
-use std::{error, fmt};
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use std::{env, error, fmt, fs, thread};
 
 /// Run `rustc` in the given compilation directory.
+///
+/// If the [`BuildConfig::cache`] has the library of the same project, it is used without invoking
+/// `cargo`, and the callback is not called.
 pub fn compile<P, F>(
     compile_dir: P,
     linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
     mut stderr_line_cb: F,
 ) -> Result<PathBuf, CompilationError>
 where
@@ -15,58 +24,830 @@ where
     F: FnMut(&str),
 {
     let compile_dir = compile_dir.as_ref();
-    let lib_file = compile_dir.join("target/debug/");
-    let lib_file = if cfg!(windows) {
-        lib_file.join(format!("{}.dll", LIBRARY_NAME))
-    } else {
-        lib_file.join(format!("lib{}.so", LIBRARY_NAME))
+    let rustc_args = rustc_args(linking_config, build_config);
+    let mut build = || {
+        let lib_file = build_config.lib_path(compile_dir);
+        let mut args = rustc_args.clone();
+        output_args(compile_dir, build_config, &mut args)?;
+
+        cargo(compile_dir, build_config, &args, &mut stderr_line_cb)?;
+        verify_output(build_config, lib_file)
     };
 
-    let mut args = vec!["rustc".to_owned(), "--".to_owned(), "-Awarnings".to_owned()];
+    match &build_config.cache {
+        Some(cache) => cache.get_or_compile(compile_dir, &rustc_args, build_config, build),
+        None => build(),
+    }
+}
 
-    for external in linking_config.external_libs.iter() {
-        args.push("-L".to_owned());
-        args.push(format!("dependency={}", external.deps_path().display()));
-        args.push("--extern".to_owned());
-        args.push(format!(
-            "{}={}",
-            external.lib_name(),
-            external.lib_path().display()
-        ));
+/// Run `rustc` in the given compilation directory on a separate thread.
+///
+/// This is the same as [`compile`] but returns immediately, so a front-end can keep rendering
+/// while `cargo` runs. The result is polled from the [`CompileHandle`], which can also cancel the
+/// compilation. The callback is called on the compilation thread.
+pub fn compile_async<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    mut stderr_line_cb: F,
+) -> CompileHandle
+where
+    P: AsRef<Path>,
+    F: FnMut(&str) + Send + 'static,
+{
+    let compile_dir = compile_dir.as_ref().to_path_buf();
+    let build_config = build_config.clone();
+    let rustc_args = rustc_args(linking_config, &build_config);
+
+    let cancel = Arc::new(AtomicBool::new(false));
+    let (tx, rx) = crossbeam_channel::bounded(1);
+
+    let cancelled = Arc::clone(&cancel);
+    thread::spawn(move || {
+        let mut build = || {
+            let lib_file = build_config.lib_path(&compile_dir);
+            let mut args = rustc_args.clone();
+            output_args(&compile_dir, &build_config, &mut args)?;
+
+            let cb = &mut stderr_line_cb;
+            cargo_cancellable(&compile_dir, &build_config, &args, cb, Some(&cancelled))?;
+            verify_output(&build_config, lib_file)
+        };
+        let r = match &build_config.cache {
+            Some(cache) => cache.get_or_compile(&compile_dir, &rustc_args, &build_config, build),
+            None => build(),
+        };
+        tx.send(r).ok();
+    });
+
+    CompileHandle { rx, cancel }
+}
+
+/// A compilation running on a separate thread, started with [`compile_async`].
+#[derive(Debug)]
+pub struct CompileHandle {
+    rx: Receiver<Result<PathBuf, CompilationError>>,
+    cancel: Arc<AtomicBool>,
+}
+
+impl CompileHandle {
+    /// The result of the compilation if it has finished. The result is only returned once,
+    /// subsequent polls return `None`.
+    pub fn poll(&self) -> Option<Result<PathBuf, CompilationError>> {
+        self.rx.try_recv().ok()
     }
 
-    let mut child = Command::new("cargo")
+    /// Block until the compilation finishes.
+    pub fn wait(self) -> Result<PathBuf, CompilationError> {
+        self.rx
+            .recv()
+            .expect("compilation thread should send a result")
+    }
+
+    /// Cancel the compilation, killing `cargo` and, on unix, the `rustc` and build script
+    /// processes it spawned.
+    ///
+    /// This does not block, the result is [`CompilationError::Cancelled`] once the processes are
+    /// killed, unless the compilation had already finished.
+    pub fn cancel(&self) {
+        self.cancel.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Run `rustc` in the given compilation directory, reporting warnings rather than suppressing them.
+///
+/// The warnings are returned as [`Diagnostic`]s along with the library path, parsed from the JSON
+/// messages of `--message-format=json`. The warnings are _not_ passed to the callback, so it only
+/// receives errors and progress, separating the warnings for machine consumption from the human
+/// view. The errors are passed once the build fails.
+pub fn compile_with_warnings<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    mut stderr_line_cb: F,
+) -> Result<(PathBuf, Vec<Diagnostic>), CompilationError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&str),
+{
+    let compile_dir = compile_dir.as_ref();
+    let lib_file = build_config.lib_path(compile_dir);
+
+    // the compiler messages are written to stdout, leaving the progress on stderr
+    let mut args = rustc_args(linking_config, build_config);
+    args.retain(|arg| arg != "-Awarnings" && arg != JSON_MESSAGE_FORMAT);
+    args.insert(1, JSON_MESSAGE_FORMAT.to_owned());
+    output_args(compile_dir, build_config, &mut args)?;
+
+    let output = run_cargo(
+        compile_dir,
+        build_config,
+        &args,
+        &[],
+        &mut stderr_line_cb,
+        None,
+    )?;
+    let diagnostics = json_diagnostics(&output.stdout);
+
+    if !output.status.success() {
+        let errors = rendered_errors(&output.stdout);
+        errors.lines().for_each(&mut stderr_line_cb);
+        if build_config.json_diagnostics && !diagnostics.is_empty() {
+            return Err(CompilationError::Diagnostics(diagnostics));
+        }
+        return Err(CompilationError::CompileError(errors + &output.stderr));
+    }
+
+    let warnings = diagnostics
+        .into_iter()
+        .filter(|d| d.level == Level::Warning)
+        .collect();
+    verify_output(build_config, lib_file).map(|lib_file| (lib_file, warnings))
+}
+
+/// The rendered text of the error messages in the JSON messages `cargo` writes to stdout, as it
+/// would write them to stderr.
+fn rendered_errors(stdout: &str) -> String {
+    use serde_json::Value;
+
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|msg| msg["reason"] == "compiler-message" && msg["message"]["level"] == "error")
+        .filter_map(|msg| msg["message"]["rendered"].as_str().map(String::from))
+        .collect()
+}
+
+/// Arguments to write the library to the [`BuildConfig::output_path`], if set. The output
+/// directory is created.
+///
+/// An output path only applies to a single crate type, so only the dynamic library is built.
+fn output_args(
+    compile_dir: &Path,
+    build_config: &BuildConfig,
+    args: &mut Vec<String>,
+) -> Result<(), CompilationError> {
+    let path = match &build_config.output_path {
+        Some(p) => p,
+        None => return Ok(()),
+    };
+
+    if let Some(parent) = compile_dir.join(path).parent() {
+        fs::create_dir_all(parent).map_err(CompilationError::IOError)?;
+    }
+
+    // rustc is run in the compile directory, so a relative path is relative to it
+    let sep = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    args.splice(sep..sep, ["--crate-type".to_owned(), "cdylib".to_owned()]);
+    args.push(format!("--emit=link={}", path.display()));
+
+    Ok(())
+}
+
+/// Check the library was written to the [`BuildConfig::output_path`], if set.
+fn verify_output(
+    build_config: &BuildConfig,
+    lib_file: PathBuf,
+) -> Result<PathBuf, CompilationError> {
+    if build_config.output_path.is_some() && !lib_file.exists() {
+        return Err(CompilationError::IOError(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("library was not written to {}", lib_file.display()),
+        )));
+    }
+    Ok(lib_file)
+}
+
+/// The type of output to emit when using [`emit`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Emit {
+    /// Assembly code, as a `.s` file.
+    Asm,
+    /// LLVM IR, as a `.ll` file.
+    LlvmIr,
+    /// Mid-level IR, as a `.mir` file.
+    Mir,
+}
+
+impl Emit {
+    fn rustc_arg(self) -> &'static str {
+        match self {
+            Emit::Asm => "asm",
+            Emit::LlvmIr => "llvm-ir",
+            Emit::Mir => "mir",
+        }
+    }
+
+    /// The file extension of the emitted file.
+    pub fn extension(self) -> &'static str {
+        match self {
+            Emit::Asm => "s",
+            Emit::LlvmIr => "ll",
+            Emit::Mir => "mir",
+        }
+    }
+}
+
+/// Run `rustc` in the given compilation directory, emitting the requested output rather than a
+/// library.
+///
+/// Returns the path to the emitted file, which is placed in the `target` directory and named after
+/// [`BuildConfig::lib_name`].
+pub fn emit<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    emit: Emit,
+    mut stderr_line_cb: F,
+) -> Result<PathBuf, CompilationError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&str),
+{
+    let compile_dir = compile_dir.as_ref();
+    // rustc is run in the compile directory, so the emit path is relative to it
+    let emit_file =
+        Path::new("target").join(format!("{}.{}", build_config.lib_name, emit.extension()));
+
+    let mut args = rustc_args(linking_config, build_config);
+    args.push("--emit".to_owned());
+    args.push(format!("{}={}", emit.rustc_arg(), emit_file.display()));
+
+    cargo(compile_dir, build_config, &args, &mut stderr_line_cb)
+        .map(|_| compile_dir.join(emit_file))
+}
+
+/// Run `cargo check` in the given compilation directory, without building a library.
+///
+/// Type and borrow errors are reported as with [`compile`], but code generation is skipped so
+/// this is much faster, making it suitable for checking input as it is typed. The same linking
+/// arguments are used, a release build is checked as a debug build.
+pub fn check<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    mut stderr_line_cb: F,
+) -> Result<(), CompilationError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&str),
+{
+    let mut args = rustc_args(linking_config, build_config);
+    // the check profile conflicts with --release
+    let sep = args
+        .iter()
+        .position(|arg| arg == "--")
+        .unwrap_or(args.len());
+    if let Some(idx) = args[..sep].iter().position(|arg| arg == "--release") {
+        args.remove(idx);
+    }
+    args.insert(1, "--profile=check".to_owned());
+
+    cargo(
+        compile_dir.as_ref(),
+        build_config,
+        &args,
+        &mut stderr_line_cb,
+    )
+    .map(|_| ())
+}
+
+/// How often a cancellable build checks if it has been cancelled.
+const POLL: Duration = Duration::from_millis(50);
+
+/// Run `cargo` with the arguments, passing each line of stderr to the callback.
+/// Returns the stderr output.
+///
+/// With [`BuildConfig::json_diagnostics`] the compiler messages of a failed build are parsed from
+/// stdout into [`CompilationError::Diagnostics`], falling back to the stderr output if there are
+/// none.
+fn cargo<F: FnMut(&str)>(
+    compile_dir: &Path,
+    build_config: &BuildConfig,
+    args: &[String],
+    stderr_line_cb: &mut F,
+) -> Result<String, CompilationError> {
+    cargo_cancellable(compile_dir, build_config, args, stderr_line_cb, None)
+}
+
+/// [`cargo`], stopping once the `cancel` flag is set.
+fn cargo_cancellable<F: FnMut(&str)>(
+    compile_dir: &Path,
+    build_config: &BuildConfig,
+    args: &[String],
+    stderr_line_cb: &mut F,
+    cancel: Option<&AtomicBool>,
+) -> Result<String, CompilationError> {
+    let output = run_cargo(compile_dir, build_config, args, &[], stderr_line_cb, cancel)?;
+    if output.status.success() {
+        return Ok(output.stderr);
+    }
+    if build_config.json_diagnostics {
+        let diagnostics = json_diagnostics(&output.stdout);
+        if !diagnostics.is_empty() {
+            return Err(CompilationError::Diagnostics(diagnostics));
+        }
+    }
+    Err(CompilationError::CompileError(output.stderr))
+}
+
+/// The output of a `cargo` process which exited.
+struct CargoOutput {
+    stdout: String,
+    stderr: String,
+    status: ExitStatus,
+}
+
+/// Run `cargo` with the arguments and the extra environment variables `envs`, passing each line
+/// of stderr to the callback.
+///
+/// The [`BuildConfig::env`] is set before `envs`, and the [`BuildConfig::timeout`] applies. An
+/// error is returned if `cargo` could not be run, or was stopped before it exited.
+fn run_cargo<F: FnMut(&str)>(
+    compile_dir: &Path,
+    build_config: &BuildConfig,
+    args: &[String],
+    envs: &[(&str, String)],
+    stderr_line_cb: &mut F,
+    cancel: Option<&AtomicBool>,
+) -> Result<CargoOutput, CompilationError> {
+    let mut cmd = Command::new(build_config.cargo_program());
+
+    // a new process group lets the rustc and build script processes be killed when stopping, but
+    // it no longer receives a Ctrl+C from the terminal, so it is only used when needed
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::CommandExt;
+        if build_config.timeout.is_some() || cancel.is_some() {
+            cmd.process_group(0);
+        }
+    }
+
+    if build_config.incremental {
+        cmd.env("CARGO_INCREMENTAL", "1");
+    }
+    cmd.envs(build_config.env.iter().map(|(k, v)| (k, v)));
+    cmd.envs(envs.iter().map(|(k, v)| (k, v)));
+
+    let mut child = cmd
         .current_dir(compile_dir)
-        .args(&args)
+        .args(args)
         .stdout(Stdio::piped())
         .stderr(Stdio::piped())
         .spawn()
-        .map_err(|_| CompilationError::NoBuildCommand)?;
+        .map_err(|e| no_build_command(build_config.cargo_program(), e))?;
 
-    let stderr = {
-        let rdr = BufReader::new(child.stderr.as_mut().expect("stderr should be piped"));
+    // stderr is read on a separate thread so the deadline can be checked while waiting for lines
+    let mut stdout = child.stdout.take().expect("stdout should be piped");
+    let stdout = thread::spawn(move || {
         let mut s = String::new();
+        stdout.read_to_string(&mut s).ok();
+        s
+    });
+
+    let rdr = BufReader::new(child.stderr.take().expect("stderr should be piped"));
+    let (tx, rx) = crossbeam_channel::unbounded();
+    thread::spawn(move || {
         for line in rdr.lines() {
-            let line = line.unwrap();
-            stderr_line_cb(&line);
-            s.push_str(&line);
-            s.push('\n');
+            let line = match line {
+                Ok(line) => line,
+                Err(_) => break,
+            };
+            if tx.send(line).is_err() {
+                break;
+            }
+        }
+    });
+
+    let deadline = build_config.timeout.map(|t| Instant::now() + t);
+    let is_cancelled = || cancel.map_or(false, |c| c.load(Ordering::Relaxed));
+    let stopped = |mut child: Child| {
+        // the reader thread finishes once the pipe closes
+        kill(&mut child);
+        if is_cancelled() {
+            CompilationError::Cancelled
+        } else {
+            CompilationError::TimedOut(build_config.timeout.unwrap_or_default())
         }
-        s
     };
 
-    match child.wait() {
-        Ok(ex) => {
-            if ex.success() {
-                Ok(lib_file)
-            } else {
-                Err(CompilationError::CompileError(stderr))
-            }
+    let mut stderr = String::new();
+    loop {
+        let mut timeout = deadline.map(|d| d.saturating_duration_since(Instant::now()));
+        if cancel.is_some() {
+            timeout = Some(timeout.map_or(POLL, |t| t.min(POLL)));
         }
+
+        let line = match timeout {
+            Some(timeout) => match rx.recv_timeout(timeout) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    if is_cancelled() || deadline.map_or(false, |d| Instant::now() >= d) {
+                        return Err(stopped(child));
+                    }
+                    continue;
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            },
+            None => match rx.recv() {
+                Ok(line) => line,
+                Err(_) => break,
+            },
+        };
+        stderr_line_cb(&line);
+        stderr.push_str(&line);
+        stderr.push('\n');
+    }
+
+    match wait(&mut child, deadline, cancel) {
+        Ok(Some(status)) => Ok(CargoOutput {
+            stdout: stdout.join().unwrap_or_default(),
+            stderr,
+            status,
+        }),
+        Ok(None) => Err(stopped(child)),
         Err(e) => Err(CompilationError::IOError(e)),
     }
 }
 
+/// The `cargo test` arguments to run the `target` tests, such as `--doc`, with the build
+/// configuration.
+fn test_args(build_config: &BuildConfig, target: &str) -> Vec<String> {
+    let mut args = vec!["test".to_owned(), target.to_owned()];
+    args.extend(profile_args(build_config));
+    args
+}
+
+/// The `cargo` arguments shared by the subcommands to build with the configuration: the jobs,
+/// the profile, and the [`snapshot`](BuildConfig::snapshot).
+fn profile_args(build_config: &BuildConfig) -> Vec<String> {
+    let mut args = Vec::new();
+
+    if let Some(jobs) = build_config.jobs {
+        args.push("--jobs".to_owned());
+        args.push(jobs.to_string());
+    }
+
+    if build_config.release {
+        args.push("--release".to_owned());
+    }
+
+    if let Some(snapshot) = &build_config.snapshot {
+        args.push("--frozen".to_owned());
+        args.push("--target-dir".to_owned());
+        args.push(snapshot.target_dir.display().to_string());
+    }
+
+    args
+}
+
+/// The flags of the environment variable `key` with `extra` appended. Flags already set in the
+/// [`BuildConfig::env`], or otherwise in the process environment, are kept.
+fn append_flags(build_config: &BuildConfig, key: &str, extra: &[String]) -> String {
+    let existing = build_config
+        .env
+        .iter()
+        .rev()
+        .find(|(k, _)| k == key)
+        .map(|(_, v)| v.clone())
+        .or_else(|| env::var(key).ok())
+        .unwrap_or_default();
+
+    let mut flags = existing.trim().to_owned();
+    for flag in extra {
+        if !flags.is_empty() {
+            flags.push(' ');
+        }
+        flags.push_str(flag);
+    }
+    flags
+}
+
+/// Wait for the child to exit, polling until the deadline if there is one. Returns `None` if the
+/// deadline passes or the `cancel` flag is set.
+fn wait(
+    child: &mut Child,
+    deadline: Option<Instant>,
+    cancel: Option<&AtomicBool>,
+) -> io::Result<Option<ExitStatus>> {
+    if deadline.is_none() && cancel.is_none() {
+        return child.wait().map(Some);
+    }
+
+    loop {
+        if let Some(ex) = child.try_wait()? {
+            return Ok(Some(ex));
+        }
+        if deadline.map_or(false, |d| Instant::now() >= d)
+            || cancel.map_or(false, |c| c.load(Ordering::Relaxed))
+        {
+            return Ok(None);
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Kill the child and wait for it to exit. On unix the child's process group is killed as well,
+/// which includes the processes `cargo` spawned. The child must lead its own process group.
+fn kill(child: &mut Child) {
+    #[cfg(unix)]
+    {
+        // a negative pid signals the process group
+        unsafe {
+            libc::kill(-(child.id() as libc::pid_t), libc::SIGKILL);
+        }
+    }
+
+    child.kill().ok();
+    child.wait().ok();
+}
+
+/// Resolve the versions of the dependencies of the project in the given compilation directory,
+/// without compiling it.
+///
+/// `cargo` resolves the (usually wildcard) dependencies into `Cargo.lock`, respecting any versions
+/// that are already locked. Every package in the dependency graph is returned, excluding the
+/// library itself. These can be used to pin the versions of a session for reproducibility.
+pub fn resolved_dependencies<P: AsRef<Path>>(
+    compile_dir: P,
+    build_config: &BuildConfig,
+) -> Result<Vec<ResolvedDep>, CompilationError> {
+    let output = Command::new(build_config.cargo_program())
+        .current_dir(compile_dir)
+        .args(["metadata", "--format-version", "1"])
+        .envs(build_config.env.iter().map(|(k, v)| (k, v)))
+        .output()
+        .map_err(|e| no_build_command(build_config.cargo_program(), e))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+        return Err(CompilationError::CompileError(stderr));
+    }
+
+    parse_metadata(&output.stdout)
+        .map_err(|e| CompilationError::IOError(io::Error::new(io::ErrorKind::InvalidData, e)))
+}
+
+/// A dependency version resolved by `cargo`.
+#[derive(Debug, PartialEq, Eq, Clone)]
+pub struct ResolvedDep {
+    /// The package name.
+    pub name: String,
+    /// The exact version, ie `0.1.5`.
+    pub version: String,
+}
+
+/// The packages of the `cargo metadata` JSON output, excluding the workspace members. The packages
+/// are sorted by name and version.
+fn parse_metadata(metadata: &[u8]) -> serde_json::Result<Vec<ResolvedDep>> {
+    use serde_json::Value;
+
+    let metadata: Value = serde_json::from_slice(metadata)?;
+    let members = metadata["workspace_members"]
+        .as_array()
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+
+    let mut deps = metadata["packages"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|package| !members.contains(&package["id"]))
+        .filter_map(|package| {
+            Some(ResolvedDep {
+                name: package["name"].as_str()?.to_string(),
+                version: package["version"].as_str()?.to_string(),
+            })
+        })
+        .collect::<Vec<_>>();
+    deps.sort_by(|a, b| (&a.name, &a.version).cmp(&(&b.name, &b.version)));
+
+    Ok(deps)
+}
+
+/// Run the documentation tests of the items in the given compilation directory.
+///
+/// Items that have `///` doc comments with code examples are tested using `cargo test --doc`.
+/// The library is named by [`BuildConfig::lib_name`], by default `papyrus_mem_code`, so examples
+/// need to reference items through this path (ie `papyrus_mem_code::my_fn()`). The linking
+/// arguments are appended to any `RUSTDOCFLAGS` already set.
+///
+/// The results of each example are returned, a failing example is _not_ an error. An error is
+/// returned if the library fails to compile.
+pub fn doctest<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    mut stderr_line_cb: F,
+) -> Result<Vec<DoctestResult>, CompilationError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&str),
+{
+    let flags = append_flags(build_config, "RUSTDOCFLAGS", &extern_args(linking_config));
+    cargo_test(
+        compile_dir.as_ref(),
+        build_config,
+        &test_args(build_config, "--doc"),
+        &[("RUSTDOCFLAGS", flags)],
+        &mut stderr_line_cb,
+        parse_doctest_output,
+    )
+}
+
+/// Run `cargo test` with the arguments, parsing the results from stdout. A failing test is _not_
+/// an error, an error is returned if `cargo` fails without any results, such as when the library
+/// fails to compile.
+fn cargo_test<T, F: FnMut(&str)>(
+    compile_dir: &Path,
+    build_config: &BuildConfig,
+    args: &[String],
+    envs: &[(&str, String)],
+    stderr_line_cb: &mut F,
+    parse: fn(&str) -> Vec<T>,
+) -> Result<Vec<T>, CompilationError> {
+    let output = run_cargo(compile_dir, build_config, args, envs, stderr_line_cb, None)?;
+
+    let results = parse(&output.stdout);
+    if output.status.success() || !results.is_empty() {
+        Ok(results)
+    } else {
+        Err(CompilationError::CompileError(output.stderr))
+    }
+}
+
+/// The result of a single documentation test.
+#[derive(Debug, PartialEq, Clone)]
+pub struct DoctestResult {
+    /// The name of the test, as reported by `cargo`. Usually in the form `src/lib.rs - item (line
+    /// #)`.
+    pub name: String,
+    /// The test passed.
+    pub passed: bool,
+}
+
+/// Run the `#[test]` functions of the items in the given compilation directory.
+///
+/// The tests are run with `cargo test --lib`, and the result of each test is returned along with
+/// its captured output. A failing test is _not_ an error. An error is returned if the library
+/// fails to compile.
+pub fn unit_tests<P, F>(
+    compile_dir: P,
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+    mut stderr_line_cb: F,
+) -> Result<Vec<TestResult>, CompilationError>
+where
+    P: AsRef<Path>,
+    F: FnMut(&str),
+{
+    let mut args = test_args(build_config, "--lib");
+    args.push("--".to_owned());
+    args.push("--show-output".to_owned());
+
+    // only set when linking, as changing the flags rebuilds the dependencies
+    let externs = extern_args(linking_config);
+    let mut envs = Vec::new();
+    if !externs.is_empty() {
+        envs.push((
+            "RUSTFLAGS",
+            append_flags(build_config, "RUSTFLAGS", &externs),
+        ));
+    }
+
+    cargo_test(
+        compile_dir.as_ref(),
+        build_config,
+        &args,
+        &envs,
+        &mut stderr_line_cb,
+        parse_test_output,
+    )
+}
+
+/// The result of a single `#[test]` function.
+#[derive(Debug, PartialEq, Clone)]
+pub struct TestResult {
+    /// The path of the test function, ie `tests::adds`.
+    pub name: String,
+    /// The test passed.
+    pub passed: bool,
+    /// How long the test took. The stable test harness does not report times, so this is
+    /// usually `None`.
+    pub duration: Option<Duration>,
+    /// The captured standard output, including the panic message of a failed test.
+    pub stdout: String,
+}
+
+fn parse_test_output(stdout: &str) -> Vec<TestResult> {
+    let mut results = parse_doctest_output(stdout)
+        .into_iter()
+        .map(|DoctestResult { name, passed }| TestResult {
+            name,
+            passed,
+            duration: None,
+            stdout: String::new(),
+        })
+        .collect::<Vec<_>>();
+
+    // captured output is in `---- name stdout ----` sections
+    let mut lines = stdout.lines().peekable();
+    while let Some(line) = lines.next() {
+        let name = match line
+            .strip_prefix("---- ")
+            .and_then(|s| s.strip_suffix(" stdout ----"))
+        {
+            Some(name) => name,
+            None => continue,
+        };
+
+        let mut captured = Vec::new();
+        while let Some(line) = lines.peek() {
+            if line.starts_with("---- ") || *line == "successes:" || *line == "failures:" {
+                break;
+            }
+            captured.push(lines.next().unwrap_or_default());
+        }
+        while captured.last() == Some(&"") {
+            captured.pop();
+        }
+
+        if let Some(r) = results.iter_mut().find(|r| r.name == name) {
+            r.stdout = captured.iter().map(|line| format!("{}\n", line)).collect();
+        }
+    }
+
+    results
+}
+
+fn parse_doctest_output(stdout: &str) -> Vec<DoctestResult> {
+    stdout
+        .lines()
+        .filter(|line| line.starts_with("test "))
+        .filter_map(|line| {
+            let line = &line[5..];
+            let idx = line.rfind(" ... ")?;
+            let passed = match &line[idx + 5..] {
+                "ok" => true,
+                "FAILED" => false,
+                _ => return None,
+            };
+            Some(DoctestResult {
+                name: line[..idx].to_string(),
+                passed,
+            })
+        })
+        .collect()
+}
+
+const JSON_MESSAGE_FORMAT: &str = "--message-format=json";
+
+/// Arguments to `cargo rustc`. Further `rustc` arguments can be appended.
+pub(super) fn rustc_args(
+    linking_config: &crate::linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+) -> Vec<String> {
+    let mut args = vec!["rustc".to_owned()];
+    args.extend(profile_args(build_config));
+
+    if build_config.json_diagnostics {
+        args.push(JSON_MESSAGE_FORMAT.to_owned());
+    }
+
+    args.push("--".to_owned());
+    if build_config.suppress_warnings {
+        args.push("-Awarnings".to_owned());
+    }
+    args.extend(extern_args(linking_config));
+
+    args
+}
+
+/// Arguments to link the external libraries.
+fn extern_args(linking_config: &crate::linking::LinkingConfiguration) -> Vec<String> {
+    let mut args = Vec::new();
+
+    for external in linking_config.external_libs.iter() {
+        args.push("-L".to_owned());
+        args.push(format!("dependency={}", external.deps_path().display()));
+        args.push("--extern".to_owned());
+        args.push(format!(
+            "{}={}",
+            external.lib_name(),
+            external.lib_path().display()
+        ));
+        for (name, path) in external.exposed_dependencies() {
+            args.push("--extern".to_owned());
+            args.push(format!("{}={}", name, path.display()));
+        }
+    }
+
+    args
+}
+
 /// Function to rename the output library file and remove the associated dependency.
 ///
 /// In relation to [#44](https://github.com/kurtlawrence/papyrus/issues/44), loading a library will
@@ -109,11 +890,37 @@ fn rename_lib_file<P: AsRef<Path>>(compiled_lib: P) -> io::Result<PathBuf> {
 #[derive(Debug)]
 pub enum CompilationError {
     /// Failed to initialise `cargo build`. Usually because `cargo` is not in your `PATH` or Rust is not installed.
-    NoBuildCommand,
+    ///
+    /// The details describe the failure, and when `cargo` is not found, the `PATH` searched and how
+    /// to install Rust.
+    NoBuildCommand(String),
     /// A compiling error occured, with the contents of the stderr.
     CompileError(String),
     /// Generic IO errors.
     IOError(io::Error),
+    /// The build did not finish within the [`BuildConfig::timeout`] and was killed.
+    TimedOut(Duration),
+    /// The build was cancelled with [`CompileHandle::cancel`].
+    Cancelled,
+    /// A compiling error occured, with the compiler messages parsed from the JSON output. Only
+    /// returned with [`BuildConfig::json_diagnostics`].
+    Diagnostics(Vec<Diagnostic>),
+}
+
+/// A [`CompilationError::NoBuildCommand`] for when spawning `program` failed.
+fn no_build_command<P: AsRef<OsStr>>(program: P, e: io::Error) -> CompilationError {
+    let program = program.as_ref().to_string_lossy();
+    let details = if e.kind() == io::ErrorKind::NotFound {
+        let path = env::var_os("PATH").unwrap_or_default();
+        format!(
+            "`{}` was not found in PATH ({}). Is Rust installed? Install it with rustup from https://rustup.rs",
+            program,
+            path.to_string_lossy()
+        )
+    } else {
+        format!("`{}` could not be run: {}", program, e)
+    };
+    CompilationError::NoBuildCommand(details)
 }
 
 impl error::Error for CompilationError {}
@@ -121,25 +928,248 @@ impl error::Error for CompilationError {}
 impl fmt::Display for CompilationError {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         match self {
-            CompilationError::NoBuildCommand => {
-                write!(f, "cargo build command failed to start, is rust installed?")
+            CompilationError::NoBuildCommand(details) => {
+                write!(f, "cargo build command failed to start: {}", details)
             }
             CompilationError::CompileError(e) => write!(f, "{}", e),
             CompilationError::IOError(e) => write!(f, "io error occurred: {}", e),
+            CompilationError::TimedOut(t) => {
+                write!(f, "compilation timed out after {} seconds", t.as_secs_f64())
+            }
+            CompilationError::Cancelled => write!(f, "compilation was cancelled"),
+            CompilationError::Diagnostics(diagnostics) => {
+                for d in diagnostics {
+                    if let Some(rendered) = &d.rendered {
+                        write!(f, "{}", rendered)?;
+                        continue;
+                    }
+                    let level = match d.level {
+                        Level::Error => "error",
+                        Level::Warning => "warning",
+                    };
+                    writeln!(f, "{}: {}", level, d.message)?;
+                    writeln!(f, " --> src/lib.rs:{}:{}", d.line, d.column)?;
+                }
+                Ok(())
+            }
         }
     }
 }
 
 #[test]
 fn compilation_error_fmt_test() {
-    let e = CompilationError::NoBuildCommand;
+    let e = CompilationError::NoBuildCommand("no cargo".to_string());
     assert_eq!(
         &e.to_string(),
-        "cargo build command failed to start, is rust installed?"
+        "cargo build command failed to start: no cargo"
     );
+    let e = no_build_command("cargo", io::ErrorKind::NotFound.into()).to_string();
+    assert!(e.contains("`cargo` was not found in PATH"));
+    assert!(e.contains("https://rustup.rs"));
+    let e = no_build_command("cargo", io::ErrorKind::PermissionDenied.into()).to_string();
+    assert!(e.starts_with("cargo build command failed to start: `cargo` could not be run: "));
     let e = CompilationError::CompileError("compile err".to_string());
     assert_eq!(&e.to_string(), "compile err");
     let ioe = io::Error::new(io::ErrorKind::Other, "test");
     let e = CompilationError::IOError(ioe);
     assert_eq!(&e.to_string(), "io error occurred: test");
+    let e = CompilationError::TimedOut(Duration::from_millis(1500));
+    assert_eq!(&e.to_string(), "compilation timed out after 1.5 seconds");
+    let e = CompilationError::Cancelled;
+    assert_eq!(&e.to_string(), "compilation was cancelled");
+    let mut diagnostic = Diagnostic {
+        level: Level::Error,
+        message: "mismatched types".to_string(),
+        line: 3,
+        column: 5,
+        end_line: 3,
+        end_column: 6,
+        suggestion: None,
+        rendered: None,
+    };
+    let e = CompilationError::Diagnostics(vec![diagnostic.clone()]);
+    assert_eq!(
+        &e.to_string(),
+        "error: mismatched types\n --> src/lib.rs:3:5\n"
+    );
+    let rendered =
+        "error[E0308]: mismatched types\n --> src/lib.rs:3:5\n  |\n3 | a\n  | ^ expected `()`\n";
+    diagnostic.rendered = Some(rendered.to_string());
+    let e = CompilationError::Diagnostics(vec![diagnostic]);
+    assert_eq!(&e.to_string(), rendered);
+}
+
+#[test]
+fn rustc_args_test() {
+    let linking_config = crate::linking::LinkingConfiguration::default();
+    let mut build_config = BuildConfig::default();
+
+    assert_eq!(
+        rustc_args(&linking_config, &build_config),
+        vec!["rustc", "--", "-Awarnings"]
+    );
+
+    build_config.jobs = Some(1);
+    assert_eq!(
+        rustc_args(&linking_config, &build_config),
+        vec!["rustc", "--jobs", "1", "--", "-Awarnings"]
+    );
+
+    build_config.release = true;
+    assert_eq!(
+        rustc_args(&linking_config, &build_config),
+        vec!["rustc", "--jobs", "1", "--release", "--", "-Awarnings"]
+    );
+
+    build_config.suppress_warnings = false;
+    assert_eq!(
+        rustc_args(&linking_config, &build_config),
+        vec!["rustc", "--jobs", "1", "--release", "--"]
+    );
+}
+
+#[test]
+fn extern_args_exposed_dependency_test() {
+    let mut linking_config = crate::linking::LinkingConfiguration::default();
+    linking_config.external_libs.insert(
+        crate::linking::Extern::new(
+            "test-resources/external_kserd/target/debug/libexternal_kserd.rlib",
+        )
+        .unwrap()
+        .expose_dependency("rand")
+        .unwrap(),
+    );
+
+    let args = extern_args(&linking_config);
+    assert_eq!(args.len(), 6);
+    assert_eq!(&args[4], "--extern");
+    assert!(args[5].starts_with("rand="));
+    assert!(args[5].contains("external_kserd/target/debug/deps/librand-"));
+}
+
+#[test]
+fn parse_test_output_test() {
+    let stdout = "
+running 3 tests
+test adds ... ok
+test tests::prints ... ok
+test fails ... FAILED
+
+successes:
+
+---- tests::prints stdout ----
+hello
+world
+
+successes:
+    adds
+    tests::prints
+
+failures:
+
+---- fails stdout ----
+thread 'fails' panicked at src/lib.rs:4:5:
+oops
+
+
+failures:
+    fails
+
+test result: FAILED. 2 passed; 1 failed; 0 ignored; 0 measured; 0 filtered out
+";
+    let result = |name: &str, passed, stdout: &str| TestResult {
+        name: name.to_string(),
+        passed,
+        duration: None,
+        stdout: stdout.to_string(),
+    };
+    assert_eq!(
+        parse_test_output(stdout),
+        vec![
+            result("adds", true, ""),
+            result("tests::prints", true, "hello\nworld\n"),
+            result(
+                "fails",
+                false,
+                "thread 'fails' panicked at src/lib.rs:4:5:\noops\n"
+            ),
+        ]
+    );
+}
+
+#[test]
+fn parse_doctest_output_test() {
+    let stdout = r#"
+running 3 tests
+test src/lib.rs - a (line 3) ... ok
+test src/lib.rs - b (line 8) ... FAILED
+test src/lib.rs - c (line 12) ... ignored
+
+failures:
+
+test result: FAILED. 1 passed; 1 failed; 1 ignored; 0 measured; 0 filtered out
+"#;
+    assert_eq!(
+        parse_doctest_output(stdout),
+        vec![
+            DoctestResult {
+                name: "src/lib.rs - a (line 3)".to_string(),
+                passed: true
+            },
+            DoctestResult {
+                name: "src/lib.rs - b (line 8)".to_string(),
+                passed: false
+            },
+        ]
+    );
+}
+
+#[test]
+fn parse_metadata_test() {
+    let metadata = r#"{
+        "packages": [
+            {"name": "papyrus_mem_code", "version": "0.1.0", "id": "path+file:///tmp#papyrus_mem_code@0.1.0"},
+            {"name": "kserd", "version": "0.1.0", "id": "registry+https://github.com/rust-lang/crates.io-index#kserd@0.1.0"},
+            {"name": "fxhash", "version": "0.2.1", "id": "registry+https://github.com/rust-lang/crates.io-index#fxhash@0.2.1"}
+        ],
+        "workspace_members": ["path+file:///tmp#papyrus_mem_code@0.1.0"],
+        "version": 1
+    }"#;
+
+    assert_eq!(
+        parse_metadata(metadata.as_bytes()).unwrap(),
+        vec![
+            ResolvedDep {
+                name: "fxhash".to_string(),
+                version: "0.2.1".to_string()
+            },
+            ResolvedDep {
+                name: "kserd".to_string(),
+                version: "0.1.0".to_string()
+            },
+        ]
+    );
+    assert!(parse_metadata(b"not json").is_err());
+}
+
+#[test]
+fn append_flags_test() {
+    let extra = vec!["-L".to_owned(), "dependency=deps".to_owned()];
+    let build_config = BuildConfig {
+        env: vec![("RUSTDOCFLAGS".to_owned(), "--cfg docs ".to_owned())],
+        ..BuildConfig::default()
+    };
+
+    assert_eq!(
+        append_flags(&build_config, "RUSTDOCFLAGS", &extra),
+        "--cfg docs -L dependency=deps"
+    );
+    assert_eq!(
+        append_flags(&build_config, "PAPYRUS_FLAGS_UNSET", &extra),
+        "-L dependency=deps"
+    );
+    assert_eq!(
+        append_flags(&build_config, "RUSTDOCFLAGS", &[]),
+        "--cfg docs"
+    );
 }
@@ -0,0 +1,239 @@
+use super::{BuildConfig, CompilationError};
+use std::collections::hash_map::DefaultHasher;
+use std::collections::VecDeque;
+use std::hash::{Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::{fmt, fs};
+
+/// The directory in the compile directory which the cached libraries are stored in.
+const CACHE_DIR: &str = "target/papyrus-cache";
+
+/// A cache of compiled libraries, keyed by the project in the compile directory.
+///
+/// The key is a hash of the generated `Cargo.toml`, `src/lib.rs`, and build script, the linking
+/// arguments, and the [`BuildConfig`], so changing the crates, release mode, or any other
+/// configuration misses the cache. The contents of externally linked libraries are _not_ hashed,
+/// so the cache should be cleared if they are rebuilt.
+///
+/// A copy of each compiled library is kept in the compile directory. Once the capacity is
+/// reached, the least recently used library is evicted. Set the cache on the
+/// [`BuildConfig::cache`] to have [`compile`](super::compile) consult it. Clones of the cache
+/// share the cached libraries.
+#[derive(Clone)]
+pub struct CompileCache {
+    inner: Arc<Mutex<Entries>>,
+}
+
+struct Entries {
+    capacity: usize,
+    /// The cached libraries, most recently used last.
+    entries: VecDeque<(u64, PathBuf)>,
+}
+
+impl CompileCache {
+    /// A cache which keeps at most `capacity` libraries.
+    pub fn with_capacity(capacity: usize) -> Self {
+        let entries = Entries {
+            capacity,
+            entries: VecDeque::with_capacity(capacity),
+        };
+        CompileCache {
+            inner: Arc::new(Mutex::new(entries)),
+        }
+    }
+
+    /// The number of cached libraries.
+    pub fn len(&self) -> usize {
+        self.lock().entries.len()
+    }
+
+    /// There are no cached libraries.
+    pub fn is_empty(&self) -> bool {
+        self.lock().entries.is_empty()
+    }
+
+    /// Remove all cached libraries.
+    pub fn clear(&self) {
+        for (_, path) in self.lock().entries.drain(..) {
+            fs::remove_file(path).ok();
+        }
+    }
+
+    fn lock(&self) -> MutexGuard<'_, Entries> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// The library of the project in the compile directory, from the cache if the same project
+    /// has been compiled before with the same `rustc_args`, otherwise from `compile`.
+    ///
+    /// On a hit the cached library is copied to the library path without calling `compile`.
+    /// Otherwise the compiled library is added to the cache.
+    pub(super) fn get_or_compile<F>(
+        &self,
+        compile_dir: &Path,
+        rustc_args: &[String],
+        build_config: &BuildConfig,
+        compile: F,
+    ) -> Result<PathBuf, CompilationError>
+    where
+        F: FnOnce() -> Result<PathBuf, CompilationError>,
+    {
+        let key =
+            cache_key(compile_dir, rustc_args, build_config).map_err(CompilationError::IOError)?;
+
+        if let Some(cached) = self.lock().get(key) {
+            let lib_file = build_config.lib_path(compile_dir);
+            if let Some(parent) = lib_file.parent() {
+                fs::create_dir_all(parent).map_err(CompilationError::IOError)?;
+            }
+            fs::copy(cached, &lib_file).map_err(CompilationError::IOError)?;
+            return Ok(lib_file);
+        }
+
+        // the lock is not held while compiling
+        let lib_file = compile()?;
+
+        let dir = compile_dir.join(CACHE_DIR);
+        let cached = dir.join(format!("{:016x}.lib", key));
+        fs::create_dir_all(&dir)
+            .and_then(|_| fs::copy(&lib_file, &cached))
+            .map_err(CompilationError::IOError)?;
+        self.lock().insert(key, cached);
+
+        Ok(lib_file)
+    }
+}
+
+impl PartialEq for CompileCache {
+    /// Caches are equal if they are clones of each other.
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl fmt::Debug for CompileCache {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let entries = self.lock();
+        f.debug_struct("CompileCache")
+            .field("capacity", &entries.capacity)
+            .field("len", &entries.entries.len())
+            .finish()
+    }
+}
+
+impl Entries {
+    /// The cached library for `key`, marking it as the most recently used. A library which
+    /// has since been removed is forgotten.
+    fn get(&mut self, key: u64) -> Option<PathBuf> {
+        let idx = self.entries.iter().position(|(k, _)| *k == key)?;
+        let entry = self.entries.remove(idx)?;
+        if !entry.1.exists() {
+            return None;
+        }
+        let path = entry.1.clone();
+        self.entries.push_back(entry);
+        Some(path)
+    }
+
+    fn insert(&mut self, key: u64, path: PathBuf) {
+        if self.capacity == 0 {
+            fs::remove_file(path).ok();
+            return;
+        }
+        while self.entries.len() >= self.capacity {
+            if let Some((_, evicted)) = self.entries.pop_front() {
+                fs::remove_file(evicted).ok();
+            }
+        }
+        self.entries.push_back((key, path));
+    }
+}
+
+fn cache_key(
+    compile_dir: &Path,
+    rustc_args: &[String],
+    build_config: &BuildConfig,
+) -> std::io::Result<u64> {
+    let mut hasher = DefaultHasher::new();
+
+    fs::read_to_string(compile_dir.join("Cargo.toml"))?.hash(&mut hasher);
+    fs::read_to_string(compile_dir.join("src/lib.rs"))?.hash(&mut hasher);
+    fs::read_to_string(compile_dir.join("build.rs"))
+        .ok()
+        .hash(&mut hasher);
+    rustc_args.hash(&mut hasher);
+    let build_config = BuildConfig {
+        cache: None,
+        ..build_config.clone()
+    };
+    format!("{:?}", build_config).hash(&mut hasher);
+
+    Ok(hasher.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::{SourceCode, Statement, StmtGrp};
+    use crate::compile::{build_compile_dir, compile, exec};
+    use crate::linking::LinkingConfiguration;
+    use ::kserd::Kserd;
+
+    fn files(expr: &str) -> crate::code::ModsMap {
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: expr.to_string(),
+            semi: false,
+        }]));
+        vec![("lib".into(), src_code)].into_iter().collect()
+    }
+
+    #[test]
+    fn compile_cached_test() {
+        let compile_dir = "target/testing/compile_cached_test";
+        let linking_config = LinkingConfiguration::default();
+        let cache = CompileCache::with_capacity(1);
+        let build_config = BuildConfig {
+            cache: Some(cache.clone()),
+            ..BuildConfig::default()
+        };
+
+        let build = |expr: &str, build_config: &BuildConfig| {
+            build_compile_dir(compile_dir, &files(expr), &linking_config, build_config).unwrap();
+            let mut spawned = false;
+            let path = compile(compile_dir, &linking_config, build_config, |_| {
+                spawned = true
+            })
+            .unwrap();
+            (path, spawned)
+        };
+
+        let (path, spawned) = build("2+2", &build_config);
+        assert!(spawned);
+        assert_eq!(cache.len(), 1);
+
+        // identical input skips cargo
+        fs::remove_file(&path).unwrap();
+        let (path, spawned) = build("2+2", &build_config);
+        assert!(!spawned);
+        let r = exec(&path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+
+        // a different configuration misses
+        let release = BuildConfig {
+            release: true,
+            ..build_config.clone()
+        };
+        let (_, spawned) = build("2+2", &release);
+        assert!(spawned);
+
+        // the debug build was evicted
+        assert_eq!(cache.len(), 1);
+        let (_, spawned) = build("2+2", &build_config);
+        assert!(spawned);
+
+        cache.clear();
+        assert!(cache.is_empty());
+    }
+}
@@ -0,0 +1,375 @@
+use super::{CompileCache, LIBRARY_NAME};
+use crate::code::CrateType;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Configuration of the compilation step.
+///
+/// The configuration is passed to both [`build_compile_dir`] and [`compile`] and the same
+/// configuration should be used for both, otherwise the compiled library may not be found.
+///
+/// [`build_compile_dir`]: super::build_compile_dir
+/// [`compile`]: super::compile
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuildConfig {
+    /// The name of the generated library, used for the package name and the `[lib]` table.
+    ///
+    /// When linking multiple snippet libraries into one host, each needs a distinct name to avoid
+    /// symbol clashes. Must be a valid crate name. Defaults to `papyrus_mem_code`.
+    pub lib_name: String,
+    /// The number of parallel jobs `cargo` uses (`--jobs`). Defaults to `None`, which uses `cargo`'s
+    /// default of the number of CPUs.
+    ///
+    /// On memory constrained machines, [`BuildConfig::estimate_jobs`] can be used to pick a more
+    /// conservative value.
+    pub jobs: Option<usize>,
+    /// Resolve the toolchain's `cargo` once using `rustup which cargo`, with the
+    /// [`env`](BuildConfig::env), and invoke it directly rather than through the `rustup` proxy.
+    /// This avoids the toolchain resolution overhead on each compile. Falls back to `cargo` if
+    /// `rustup` is not present. Defaults to `false`.
+    pub resolve_cargo: bool,
+    /// Force incremental compilation on (`CARGO_INCREMENTAL=1`).
+    ///
+    /// The incremental cache lives in the compile directory ([`BuildConfig::incremental_dir`]), so
+    /// it is reused across compiles as long as the same compile directory is used, such as within a
+    /// REPL session. When `false`, `cargo`'s default for the debug profile (and the environment)
+    /// applies. Defaults to `false`.
+    pub incremental: bool,
+    /// The contents of a build script, written to `build.rs` in the compile directory.
+    ///
+    /// This can be used for code generation or environment probing, such as setting a `cfg` with
+    /// `println!("cargo:rustc-cfg=NAME")`. Defaults to `None`.
+    pub build_script: Option<String>,
+    /// The crates the build script depends on, rendered under `[build-dependencies]`.
+    pub build_deps: Vec<CrateType>,
+    /// Compile with optimisations (`--release`). Defaults to `false`.
+    ///
+    /// The library is compiled into `target/release`, so the same configuration must be used to
+    /// find it.
+    pub release: bool,
+    /// Environment variables set for `cargo`, in addition to the inherited environment.
+    ///
+    /// This can be used for isolated, reproducible builds, such as setting `CARGO_NET_OFFLINE`, a
+    /// custom `CARGO_HOME`, or the `RUSTUP_TOOLCHAIN`. Setting `PATH` also changes where `cargo` is
+    /// found. Defaults to empty.
+    pub env: Vec<(String, String)>,
+    /// The exact path to write the compiled library to, rather than the derived path in `target`.
+    ///
+    /// This is for embedders which manage their own output layout. A relative path is relative
+    /// to the compile directory. Only the dynamic library is produced, not the `.rlib`. Defaults
+    /// to `None`.
+    pub output_path: Option<PathBuf>,
+    /// Build from a prepared lock file and target directory, skipping dependency resolution.
+    ///
+    /// `cargo` is run with `--frozen`, so the dependencies must match the snapshot exactly. See
+    /// [`Snapshot`]. Defaults to `None`.
+    pub snapshot: Option<Snapshot>,
+    /// Suppress the compiler warnings (`-Awarnings`). Defaults to `true`.
+    ///
+    /// When `false`, the warnings are kept and each line is passed to the `stderr_line_cb` of
+    /// [`compile`](super::compile) along with any errors, so a front-end can show them.
+    pub suppress_warnings: bool,
+    /// Kill `cargo` if the build takes longer than this, returning
+    /// [`CompilationError::TimedOut`](super::CompilationError::TimedOut). Defaults to `None`, which
+    /// waits for the build indefinitely.
+    pub timeout: Option<Duration>,
+    /// Format the `src/lib.rs` of [`generate_project_files`] with `rustfmt`, for displaying or
+    /// exporting the project. Defaults to `false`.
+    ///
+    /// Requires the _format_ feature, and the source is left unformatted if `rustfmt` is not
+    /// installed. The source is formatted as the [`edition`](BuildConfig::edition). The compiled
+    /// source is not formatted, so compiler errors can still be mapped back to the input lines
+    /// with [`map_error_to_input_line`].
+    ///
+    /// [`generate_project_files`]: super::generate_project_files
+    /// [`map_error_to_input_line`]: super::map_error_to_input_line
+    pub format: bool,
+    /// Run `cargo` with `--message-format=json` and return the errors of a failed build as
+    /// [`CompilationError::Diagnostics`](super::CompilationError::Diagnostics). Defaults to
+    /// `false`.
+    ///
+    /// The compiler messages are not passed to the `stderr_line_cb` of
+    /// [`compile`](super::compile), only the progress is. If no messages can be parsed, the
+    /// stderr output is returned as a [`CompileError`](super::CompilationError::CompileError).
+    pub json_diagnostics: bool,
+    /// The Rust edition the snippets are compiled with. Defaults to [`Edition::E2018`].
+    pub edition: Edition,
+    /// Raw TOML appended to the generated `Cargo.toml`, after the dependencies. Defaults to
+    /// `None`.
+    ///
+    /// This can tune the code generation, for example `"[profile.dev]\nopt-level = 2"`. The
+    /// text must start with a table header, and must not redefine the generated `[package]`,
+    /// `[lib]`, or `[dependencies]` tables. It is checked by [`build_compile_dir`].
+    ///
+    /// [`build_compile_dir`]: super::build_compile_dir
+    pub extra_toml: Option<String>,
+    /// Reuse the library of an identical project compiled before, rather than invoking `cargo`.
+    /// See [`CompileCache`]. Defaults to `None`.
+    pub cache: Option<CompileCache>,
+}
+
+/// A Rust edition, set in the `[package]` table of the generated `Cargo.toml`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum Edition {
+    /// The 2015 edition.
+    E2015,
+    /// The 2018 edition.
+    #[default]
+    E2018,
+    /// The 2021 edition.
+    E2021,
+}
+
+impl Edition {
+    /// The edition as written in `Cargo.toml`, ie `2018`.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Edition::E2015 => "2015",
+            Edition::E2018 => "2018",
+            Edition::E2021 => "2021",
+        }
+    }
+}
+
+/// A prepared `Cargo.lock` and warmed target directory which a build reuses wholesale.
+///
+/// Building from a snapshot skips dependency resolution and updating the registry, and the
+/// dependencies are already compiled, which makes for a fast startup. The lock file is copied
+/// into the compile directory, and the compiled library is written into the snapshot's target
+/// directory, so concurrent builds should each use their own snapshot.
+///
+/// The snapshot was built with the same [`lib_name`](BuildConfig::lib_name) and crates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Snapshot {
+    /// The `Cargo.lock` file.
+    pub lockfile: PathBuf,
+    /// The target directory, used with `--target-dir`. Should be absolute.
+    pub target_dir: PathBuf,
+}
+
+impl Snapshot {
+    /// The snapshot of a compile directory which has been compiled.
+    pub fn from_compile_dir<P: AsRef<Path>>(compile_dir: P) -> std::io::Result<Self> {
+        let compile_dir = compile_dir.as_ref().canonicalize()?;
+        Ok(Snapshot {
+            lockfile: compile_dir.join("Cargo.lock"),
+            target_dir: compile_dir.join("target"),
+        })
+    }
+}
+
+impl Default for BuildConfig {
+    fn default() -> Self {
+        BuildConfig {
+            lib_name: LIBRARY_NAME.to_string(),
+            jobs: None,
+            resolve_cargo: false,
+            incremental: false,
+            build_script: None,
+            build_deps: Vec::new(),
+            release: false,
+            env: Vec::new(),
+            output_path: None,
+            snapshot: None,
+            suppress_warnings: true,
+            timeout: None,
+            format: false,
+            json_diagnostics: false,
+            edition: Edition::default(),
+            extra_toml: None,
+            cache: None,
+        }
+    }
+}
+
+impl BuildConfig {
+    /// Estimates a conservative number of parallel jobs, based on available cores and memory.
+    ///
+    /// Allows one job per GiB of available memory, up to the number of cores. If the available
+    /// memory cannot be determined, the number of cores is used.
+    pub fn estimate_jobs() -> usize {
+        let cores = std::thread::available_parallelism()
+            .map(|x| x.get())
+            .unwrap_or(1);
+
+        std::fs::read_to_string("/proc/meminfo")
+            .ok()
+            .and_then(|s| parse_mem_available_kib(&s))
+            .map(|kib| (kib / (1024 * 1024)) as usize)
+            .map(|gib| gib.max(1).min(cores))
+            .unwrap_or(cores)
+    }
+
+    /// The `cargo` program to invoke.
+    ///
+    /// If [`resolve_cargo`](BuildConfig::resolve_cargo) is set, the resolved path is cached for
+    /// the lifetime of the process. It is cached for each [`env`](BuildConfig::env), as the
+    /// environment can select a different toolchain.
+    pub fn cargo_program(&self) -> PathBuf {
+        static RESOLVED: CargoCache = Mutex::new(Vec::new());
+
+        if self.resolve_cargo {
+            resolve_cargo(&RESOLVED, Path::new("rustup"), &self.env)
+        } else {
+            PathBuf::from("cargo")
+        }
+    }
+
+    /// The path of the dynamic library that compiling in `compile_dir` produces.
+    ///
+    /// This is the [`output_path`](BuildConfig::output_path) if set. Otherwise the file is named
+    /// for the platform, such as `libNAME.so`, `libNAME.dylib` on macOS, or `NAME.dll` on Windows.
+    pub fn lib_path<P: AsRef<Path>>(&self, compile_dir: P) -> PathBuf {
+        use std::env::consts::{DLL_PREFIX, DLL_SUFFIX};
+
+        if let Some(path) = &self.output_path {
+            return compile_dir.as_ref().join(path);
+        }
+
+        self.target_dir(compile_dir)
+            .join(format!("{}{}{}", DLL_PREFIX, self.lib_name, DLL_SUFFIX))
+    }
+
+    /// The path of the `.rlib` that compiling in `compile_dir` produces.
+    pub fn rlib_path<P: AsRef<Path>>(&self, compile_dir: P) -> PathBuf {
+        self.target_dir(compile_dir)
+            .join(format!("lib{}.rlib", self.lib_name))
+    }
+
+    /// The directory of the incremental compilation cache in `compile_dir`.
+    pub fn incremental_dir<P: AsRef<Path>>(&self, compile_dir: P) -> PathBuf {
+        self.target_dir(compile_dir).join("incremental")
+    }
+
+    /// The directory of the compiled profile, ie `target/debug/`, which is in the
+    /// [`snapshot`](BuildConfig::snapshot) target directory if set.
+    fn target_dir<P: AsRef<Path>>(&self, compile_dir: P) -> PathBuf {
+        let profile = if self.release { "release" } else { "debug" };
+        match &self.snapshot {
+            Some(snapshot) => snapshot.target_dir.join(profile),
+            None => compile_dir.as_ref().join("target").join(profile),
+        }
+    }
+}
+
+/// The resolved `cargo` programs, keyed by the environment they were resolved with.
+type CargoCache = Mutex<Vec<(Vec<(String, String)>, PathBuf)>>;
+
+fn resolve_cargo(cache: &CargoCache, rustup: &Path, env: &[(String, String)]) -> PathBuf {
+    let mut cache = cache.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((_, cargo)) = cache.iter().find(|(e, _)| e.as_slice() == env) {
+        return cargo.clone();
+    }
+
+    let cargo = Command::new(rustup)
+        .arg("which")
+        .arg("cargo")
+        .envs(env.iter().map(|(k, v)| (k, v)))
+        .output()
+        .ok()
+        .filter(|output| output.status.success())
+        .and_then(|output| String::from_utf8(output.stdout).ok())
+        .map(|s| s.trim().to_string())
+        .filter(|s| !s.is_empty())
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("cargo"));
+    cache.push((env.to_vec(), cargo.clone()));
+    cargo
+}
+
+/// Parses the `MemAvailable` line of `/proc/meminfo`.
+fn parse_mem_available_kib(meminfo: &str) -> Option<u64> {
+    meminfo
+        .lines()
+        .find(|line| line.starts_with("MemAvailable:"))?
+        .split_whitespace()
+        .nth(1)?
+        .parse()
+        .ok()
+}
+
+#[test]
+fn parse_mem_available_kib_test() {
+    let meminfo = "MemTotal:       16314172 kB\n\
+                   MemFree:         1097012 kB\n\
+                   MemAvailable:    8516928 kB\n\
+                   Buffers:          764836 kB\n";
+    assert_eq!(parse_mem_available_kib(meminfo), Some(8516928));
+    assert_eq!(parse_mem_available_kib("MemTotal: 1 kB"), None);
+    assert!(BuildConfig::estimate_jobs() >= 1);
+}
+
+#[test]
+fn lib_path_test() {
+    let file = if cfg!(windows) {
+        "papyrus_mem_code.dll"
+    } else if cfg!(target_os = "macos") {
+        "libpapyrus_mem_code.dylib"
+    } else {
+        "libpapyrus_mem_code.so"
+    };
+
+    let mut config = BuildConfig::default();
+    assert_eq!(
+        config.lib_path("dir"),
+        Path::new("dir/target/debug").join(file)
+    );
+
+    config.release = true;
+    assert_eq!(
+        config.lib_path("dir"),
+        Path::new("dir/target/release").join(file)
+    );
+    assert_eq!(
+        config.rlib_path("dir"),
+        Path::new("dir/target/release/libpapyrus_mem_code.rlib")
+    );
+}
+
+#[cfg(unix)]
+#[test]
+fn resolve_cargo_test() {
+    use std::os::unix::fs::PermissionsExt;
+
+    let dir = Path::new("target/testing/resolve_cargo_test");
+    std::fs::create_dir_all(dir).unwrap();
+    let calls = dir.join("calls");
+    std::fs::remove_file(&calls).ok();
+
+    // a fixture rustup which records each call
+    let rustup = dir.join("rustup");
+    std::fs::write(
+        &rustup,
+        "#!/bin/sh\necho called >> \"$(dirname \"$0\")/calls\"\necho /$RUSTUP_TOOLCHAIN/bin/cargo\n",
+    )
+    .unwrap();
+    std::fs::set_permissions(&rustup, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+    let toolchain = |name: &str| vec![("RUSTUP_TOOLCHAIN".to_string(), name.to_string())];
+    let cache = Mutex::new(Vec::new());
+    assert_eq!(
+        resolve_cargo(&cache, &rustup, &toolchain("stable")),
+        Path::new("/stable/bin/cargo")
+    );
+    assert_eq!(
+        resolve_cargo(&cache, &rustup, &toolchain("stable")),
+        Path::new("/stable/bin/cargo")
+    );
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "called\n");
+
+    // another environment resolves another toolchain
+    assert_eq!(
+        resolve_cargo(&cache, &rustup, &toolchain("nightly")),
+        Path::new("/nightly/bin/cargo")
+    );
+    assert_eq!(std::fs::read_to_string(&calls).unwrap(), "called\ncalled\n");
+
+    // falls back to cargo
+    let cache = Mutex::new(Vec::new());
+    assert_eq!(
+        resolve_cargo(&cache, &dir.join("no-rustup"), &[]),
+        Path::new("cargo")
+    );
+}
@@ -1,31 +1,162 @@
-use super::LIBRARY_NAME;
-use crate::code::{self, CrateType, ModsMap};
+use super::{BuildConfig, Edition};
+use crate::code::{self, CrateSource, CrateType, ModsMap};
 use crate::linking;
 use std::fs;
 use std::io::{self, Write};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 /// Constructs the compile directory.
 /// Takes a list of source files and writes the contents to file.
 /// Builds `Cargo.toml` using crates found in `SourceFile`.
+/// The [`snapshot`](BuildConfig::snapshot) lock file is copied in if there is one.
 pub fn build_compile_dir<P: AsRef<Path>>(
     compile_dir: P,
     mods_map: &ModsMap,
     linking_config: &linking::LinkingConfiguration,
+    build_config: &BuildConfig,
 ) -> io::Result<()> {
     let compile_dir = compile_dir.as_ref();
 
+    if let Some(extra) = &build_config.extra_toml {
+        check_extra_toml(extra).map_err(|e| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("invalid extra Cargo.toml contents: {}", e),
+            )
+        })?;
+    }
+
+    for (path, contents) in project_files(mods_map, linking_config, build_config) {
+        create_file_and_dir(compile_dir.join(path))?.write_all(contents.as_bytes())?;
+    }
+
+    if let Some(snapshot) = &build_config.snapshot {
+        let lockfile = compile_dir.join("Cargo.lock");
+        if !same_file(&snapshot.lockfile, &lockfile) {
+            fs::copy(&snapshot.lockfile, lockfile)?;
+        }
+    }
+
+    // cargo picks up a build script left from a previous configuration
+    if build_config.build_script.is_none() {
+        match fs::remove_file(compile_dir.join("build.rs")) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+            _ => (),
+        }
+    }
+
+    Ok(())
+}
+
+/// Removes the `target/` directory of the compile directory, reclaiming the disk space used by
+/// compilation. Returns the number of bytes freed.
+///
+/// Only `target/` is removed, the project files are kept. This includes the libraries of a
+/// [`CompileCache`](super::CompileCache). A missing `target/` frees nothing, and a symlinked
+/// `target/` has only the link removed.
+pub fn clean_compile_dir<P: AsRef<Path>>(compile_dir: P) -> io::Result<u64> {
+    let target = compile_dir.as_ref().join("target");
+
+    let meta = match fs::symlink_metadata(&target) {
+        Ok(meta) => meta,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(e) => return Err(e),
+    };
+
+    if meta.is_dir() {
+        let size = dir_size(&target)?;
+        fs::remove_dir_all(&target)?;
+        Ok(size)
+    } else {
+        fs::remove_file(&target)?;
+        Ok(0)
+    }
+}
+
+/// The total size of the files in `dir`, not following symlinks.
+fn dir_size(dir: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let meta = entry.metadata()?;
+        size += if meta.is_dir() {
+            dir_size(&entry.path())?
+        } else {
+            meta.len()
+        };
+    }
+    Ok(size)
+}
+
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (a.canonicalize(), b.canonicalize()) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+/// Generates the files of the compile directory, without writing to disk.
+///
+/// Returns the relative path and contents of each file (`Cargo.toml`, `src/lib.rs`, and `build.rs`
+/// if there is a [`build_script`](BuildConfig::build_script)). This can be used to write the
+/// project into a virtual file system or to send it to a remote builder.
+///
+/// With [`BuildConfig::format`] the `src/lib.rs` is formatted, unlike the one
+/// [`build_compile_dir`] writes.
+pub fn generate_project_files(
+    mods_map: &ModsMap,
+    linking_config: &linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+) -> Vec<(PathBuf, String)> {
+    #[allow(unused_mut)]
+    let mut files = project_files(mods_map, linking_config, build_config);
+
+    #[cfg(feature = "format")]
+    {
+        if build_config.format {
+            let src = &mut files[1].1;
+            if let Ok(formatted) = crate::fmt::format_source(src, build_config.edition) {
+                *src = formatted;
+            }
+        }
+    }
+
+    files
+}
+
+/// The project files, with `src/lib.rs` second, as they are compiled.
+fn project_files(
+    mods_map: &ModsMap,
+    linking_config: &linking::LinkingConfiguration,
+    build_config: &BuildConfig,
+) -> Vec<(PathBuf, String)> {
     let crates = mods_map.iter().flat_map(|kvp| kvp.1.crates.iter());
 
-    // write cargo toml contents
-    create_file_and_dir(compile_dir.join("Cargo.toml"))?
-        .write_all(cargotoml_contents(LIBRARY_NAME, crates).as_bytes())?;
+    let mut cargotoml = cargotoml_contents(
+        &build_config.lib_name,
+        build_config.edition,
+        crates,
+        linking_config.runtime,
+        &build_config.build_deps,
+    );
+    if let Some(extra) = &build_config.extra_toml {
+        cargotoml.push('\n');
+        cargotoml.push_str(extra.trim());
+        cargotoml.push('\n');
+    }
 
     let (src_code, _map) = code::construct_source_code(mods_map, linking_config);
 
-    create_file_and_dir(compile_dir.join("src/lib.rs"))?.write_all(src_code.as_bytes())?;
+    let mut files = vec![
+        (PathBuf::from("Cargo.toml"), cargotoml),
+        (PathBuf::from("src/lib.rs"), src_code),
+    ];
 
-    Ok(())
+    if let Some(script) = &build_config.build_script {
+        files.push((PathBuf::from("build.rs"), script.clone()));
+    }
+
+    files
 }
 
 /// Creates the specified file along with the directory to it if it doesn't exist.
@@ -57,26 +188,338 @@ fn create_file_and_dir_test() {
     assert!(!p.exists());
 }
 
-fn cargotoml_contents<'a, I: Iterator<Item = &'a CrateType>>(lib_name: &str, crates: I) -> String {
+fn cargotoml_contents<'a, I: Iterator<Item = &'a CrateType>>(
+    lib_name: &str,
+    edition: Edition,
+    crates: I,
+    runtime: Option<linking::AsyncRuntime>,
+    build_deps: &[CrateType],
+) -> String {
+    let crates = crates.map(dependency_line);
+
+    let build_deps = if build_deps.is_empty() {
+        String::new()
+    } else {
+        format!(
+            "\n[build-dependencies]\n{}\n",
+            build_deps
+                .iter()
+                .map(dependency_line)
+                .collect::<Vec<_>>()
+                .join("\n")
+        )
+    };
+
     format!(
         r#"[package]
 name = "{lib_name}"
 version = "0.1.0"
-edition = "2018"
+edition = "{edition}"
 
 [lib]
 name = "{lib_name}"
-crate-type = [ "cdylib" ]
+crate-type = [ "cdylib", "rlib" ]
 path = "src/lib.rs"
 
 [dependencies]
 kserd = {{ version = "0.1", default-features = false }}
 {crates}
-"#,
+{build_deps}"#,
         lib_name = lib_name,
+        edition = edition.as_str(),
+        build_deps = build_deps,
         crates = crates
-            .map(|c| format!(r#"{} = "*""#, c.cargo_name))
+            .chain(runtime.map(|rt| rt.dependency().to_string()))
             .collect::<Vec<_>>()
             .join("\n")
     )
 }
+
+/// Check that raw TOML parses, starts with a table header, and does not redefine the generated
+/// tables.
+fn check_extra_toml(extra: &str) -> Result<(), String> {
+    use toml::Value;
+    const ROOT: &str = "papyrus-extra-toml-root";
+
+    let table = extra.parse::<Value>().map_err(|e| e.to_string())?;
+
+    // keys before a table header would be added to the generated `[dependencies]`
+    let root = format!("[{}]\n{}", ROOT, extra)
+        .parse::<Value>()
+        .map_err(|e| e.to_string())?;
+    if root
+        .get(ROOT)
+        .and_then(Value::as_table)
+        .map(|t| !t.is_empty())
+        == Some(true)
+    {
+        return Err("expecting a table header first".to_string());
+    }
+
+    for generated in &["package", "lib", "dependencies"] {
+        if table.get(generated).is_some() {
+            return Err(format!("the `[{}]` table is already generated", generated));
+        }
+    }
+
+    Ok(())
+}
+
+fn dependency_line(c: &CrateType) -> String {
+    let mut fields = Vec::new();
+    match &c.source {
+        CrateSource::Registry => {
+            let version = c.version.as_deref().unwrap_or("*");
+            if c.features.is_empty() {
+                return format!("{} = {}", c.cargo_name, toml_string(version));
+            }
+            fields.push(format!("version = {}", toml_string(version)));
+        }
+        CrateSource::Path(path) => {
+            fields.push(format!(
+                "path = {}",
+                toml_string(&path.display().to_string())
+            ));
+        }
+        CrateSource::Git { url, rev } => {
+            fields.push(format!("git = {}", toml_string(url)));
+            if let Some(rev) = rev {
+                fields.push(format!("rev = {}", toml_string(rev)));
+            }
+        }
+    }
+
+    if c.source != CrateSource::Registry {
+        if let Some(version) = &c.version {
+            fields.push(format!("version = {}", toml_string(version)));
+        }
+    }
+
+    if !c.features.is_empty() {
+        fields.push(format!(
+            "features = [ {} ]",
+            c.features
+                .iter()
+                .map(|f| toml_string(f))
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    format!("{} = {{ {} }}", c.cargo_name, fields.join(", "))
+}
+
+/// A TOML basic string, escaping backslashes (as in Windows paths) and quotes.
+fn toml_string(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[test]
+fn clean_compile_dir_test() {
+    let dir = Path::new("target/testing/clean_compile_dir_test");
+    fs::remove_dir_all(dir).ok();
+    assert_eq!(clean_compile_dir(dir).unwrap(), 0);
+
+    create_file_and_dir(dir.join("Cargo.toml")).unwrap();
+    create_file_and_dir(dir.join("target/a"))
+        .unwrap()
+        .write_all(b"0123456789")
+        .unwrap();
+    create_file_and_dir(dir.join("target/debug/b"))
+        .unwrap()
+        .write_all(b"01234")
+        .unwrap();
+
+    assert_eq!(clean_compile_dir(dir).unwrap(), 15);
+    assert!(!dir.join("target").exists());
+    assert!(dir.join("Cargo.toml").exists());
+    assert_eq!(clean_compile_dir(dir).unwrap(), 0);
+}
+
+#[test]
+fn cargotoml_contents_features_directive_test() {
+    let input = r#"//! papyrus: feature "serde_json/preserve_order"
+extern crate rand;
+extern crate serde_json;"#;
+    let input = match crate::input::parse_program(input) {
+        crate::input::InputResult::Program(input) => input,
+        x => panic!("expecting program, got {:?}", x),
+    };
+
+    let contents = cargotoml_contents("test", Edition::E2018, input.crates.iter(), None, &[]);
+    assert!(contents.contains("\nrand = \"*\"\n"));
+    assert!(contents
+        .contains("\nserde-json = { version = \"*\", features = [ \"preserve_order\" ] }\n"));
+
+    let input = r#"//! papyrus: crate rand = "0.7"
+//! papyrus: crate serde_json = "1.0.40"
+//! papyrus: feature "serde_json/preserve_order"
+extern crate rand;
+extern crate serde_json;"#;
+    let input = match crate::input::parse_program(input) {
+        crate::input::InputResult::Program(input) => input,
+        x => panic!("expecting program, got {:?}", x),
+    };
+
+    let contents = cargotoml_contents("test", Edition::E2018, input.crates.iter(), None, &[]);
+    assert!(contents.contains("\nrand = \"0.7\"\n"));
+    assert!(contents
+        .contains("\nserde-json = { version = \"1.0.40\", features = [ \"preserve_order\" ] }\n"));
+}
+
+#[test]
+fn dependency_line_source_test() {
+    let mut c = CrateType::parse_str("extern crate rand;").unwrap();
+    assert_eq!(dependency_line(&c), r#"rand = "*""#);
+
+    c.source = CrateSource::Path(PathBuf::from(r"C:\rand"));
+    assert_eq!(dependency_line(&c), r#"rand = { path = "C:\\rand" }"#);
+
+    c.source = CrateSource::Git {
+        url: "https://github.com/rust-random/rand".to_string(),
+        rev: Some("abc123".to_string()),
+    };
+    c.version = Some("0.7".to_string());
+    c.features.push("std".to_string());
+    assert_eq!(
+        dependency_line(&c),
+        r#"rand = { git = "https://github.com/rust-random/rand", rev = "abc123", version = "0.7", features = [ "std" ] }"#
+    );
+
+    c.source = CrateSource::Registry;
+    c.version = Some(r#"0.7" }"#.to_string());
+    c.features = vec![r#"std", "x"#.to_string()];
+    assert_eq!(
+        dependency_line(&c),
+        r#"rand = { version = "0.7\" }", features = [ "std\", \"x" ] }"#
+    );
+}
+
+#[test]
+fn cargotoml_contents_edition_test() {
+    let contents = cargotoml_contents("test", Edition::default(), None.into_iter(), None, &[]);
+    assert!(contents.contains("\nedition = \"2018\"\n"));
+
+    for (edition, s) in &[
+        (Edition::E2015, "2015"),
+        (Edition::E2018, "2018"),
+        (Edition::E2021, "2021"),
+    ] {
+        let contents = cargotoml_contents("test", *edition, None.into_iter(), None, &[]);
+        assert!(contents.contains(&format!(
+            "[package]\nname = \"test\"\nversion = \"0.1.0\"\nedition = \"{}\"\n",
+            s
+        )));
+    }
+}
+
+#[test]
+fn check_extra_toml_test() {
+    assert_eq!(
+        check_extra_toml(
+            "[profile.dev]\nopt-level = 2 # faster\ndebug = false\n\n[profile.release]\nlto = \"thin\"\n"
+        ),
+        Ok(())
+    );
+    // values spanning lines, and other value types
+    assert_eq!(
+        check_extra_toml(
+            "[x]\nfeatures = [\n    \"a\",\n]\nf = inf\nd = 1979-05-27\ns = \"\"\"\nmulti \\\n  line\"\"\"\n"
+        ),
+        Ok(())
+    );
+    assert_eq!(
+        check_extra_toml("opt-level = 2"),
+        Err("expecting a table header first".to_string())
+    );
+    assert_eq!(
+        check_extra_toml("profile.dev.opt-level = 2\n[x]"),
+        Err("expecting a table header first".to_string())
+    );
+    assert!(check_extra_toml("[profile.dev]\nopt-level")
+        .unwrap_err()
+        .contains("line 2"));
+    assert!(check_extra_toml("[profile.dev]\nopt-level = fast").is_err());
+    assert!(check_extra_toml("[profile.release]\nlto = \"a\" \"b\"").is_err());
+    assert!(check_extra_toml("[x]\nx = [1, }]").is_err());
+    assert_eq!(
+        check_extra_toml("[dependencies]\nrand = \"0.7\""),
+        Err("the `[dependencies]` table is already generated".to_string())
+    );
+    assert!(check_extra_toml("[profile dev").is_err());
+}
+
+#[test]
+fn generate_project_files_test() {
+    use crate::code::{SourceCode, Statement, StmtGrp};
+
+    let mut code = SourceCode::new();
+    code.stmts.push(StmtGrp(vec![Statement {
+        expr: "2+2".to_string(),
+        semi: false,
+    }]));
+    code.crates
+        .push(CrateType::parse_str("extern crate rand;").unwrap());
+    let mods_map = vec![("lib".into(), code)].into_iter().collect();
+
+    let files = generate_project_files(
+        &mods_map,
+        &linking::LinkingConfiguration::default(),
+        &BuildConfig::default(),
+    );
+
+    assert_eq!(files.len(), 2);
+    assert_eq!(files[0].0, Path::new("Cargo.toml"));
+    assert!(files[0].1.contains("name = \"papyrus_mem_code\""));
+    assert!(files[0].1.contains("\nrand = \"*\""));
+    assert_eq!(files[1].0, Path::new("src/lib.rs"));
+    assert!(files[1].1.contains("\nlet out0 = 2+2;"));
+
+    let build_config = BuildConfig {
+        extra_toml: Some("[profile.dev]\nopt-level = 2".to_string()),
+        ..BuildConfig::default()
+    };
+    let files = generate_project_files(
+        &mods_map,
+        &linking::LinkingConfiguration::default(),
+        &build_config,
+    );
+    assert!(files[0].1.ends_with("\n[profile.dev]\nopt-level = 2\n"));
+    let err = build_compile_dir(
+        "target/testing/extra_toml_test",
+        &mods_map,
+        &linking::LinkingConfiguration::default(),
+        &BuildConfig {
+            extra_toml: Some("opt-level = 2".to_string()),
+            ..BuildConfig::default()
+        },
+    )
+    .unwrap_err();
+    assert_eq!(err.kind(), io::ErrorKind::InvalidInput);
+
+    #[cfg(feature = "format")]
+    {
+        let build_config = BuildConfig {
+            format: true,
+            ..BuildConfig::default()
+        };
+        let files = generate_project_files(
+            &mods_map,
+            &linking::LinkingConfiguration::default(),
+            &build_config,
+        );
+        assert!(files[1].1.contains("let out0 = 2 + 2;"));
+
+        // the compiled source is left unformatted, so diagnostics map back to the input
+        let dir = Path::new("target/testing/format_project_files_test");
+        build_compile_dir(
+            dir,
+            &mods_map,
+            &linking::LinkingConfiguration::default(),
+            &build_config,
+        )
+        .unwrap();
+        let lib = fs::read_to_string(dir.join("src/lib.rs")).unwrap();
+        assert!(lib.contains("\nlet out0 = 2+2;"));
+    }
+}
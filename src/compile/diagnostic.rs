@@ -0,0 +1,497 @@
+use super::{suggest::split_diagnostics, CompilationError};
+use crate::code::StmtGrp;
+use crate::input::{self, InputResult};
+use syn::{Block, Item, Stmt};
+
+/// A compiler error or warning located in the generated library source.
+#[derive(Debug, PartialEq, Clone)]
+pub struct Diagnostic {
+    /// Whether this is an error or a warning.
+    pub level: Level,
+    /// The message, ie `mismatched types`.
+    pub message: String,
+    /// The 1-based line in the generated `src/lib.rs`.
+    pub line: usize,
+    /// The 1-based column in the generated `src/lib.rs`.
+    pub column: usize,
+    /// The 1-based line the span ends on, the same as `line` unless the span covers multiple
+    /// lines.
+    pub end_line: usize,
+    /// The 1-based column _after_ the end of the span. If the span could not be determined the
+    /// span is a single character.
+    pub end_column: usize,
+    /// The replacement the compiler suggests for the span, if any. Only parsed from the JSON
+    /// messages, see [`json_diagnostics`].
+    pub suggestion: Option<String>,
+    /// The message as the compiler renders it, with the source snippet and any notes. Only parsed
+    /// from the JSON messages, see [`json_diagnostics`].
+    pub rendered: Option<String>,
+}
+
+/// The level of a [`Diagnostic`].
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Level {
+    /// A compilation error.
+    Error,
+    /// A compilation warning.
+    Warning,
+}
+
+impl CompilationError {
+    /// Parse the located errors out of a compile error.
+    ///
+    /// Only [`CompileError`](CompilationError::CompileError) and
+    /// [`Diagnostics`](CompilationError::Diagnostics) have diagnostics.
+    pub fn diagnostics(&self) -> Vec<Diagnostic> {
+        match self {
+            CompilationError::CompileError(stderr) => diagnostics(stderr),
+            CompilationError::Diagnostics(diagnostics) => diagnostics.clone(),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Parse the errors and warnings which point into `src/lib.rs` out of the compiler's `stderr`
+/// output.
+pub fn diagnostics(stderr: &str) -> Vec<Diagnostic> {
+    split_diagnostics(stderr)
+        .into_iter()
+        .filter_map(|diagnostic| {
+            let header = diagnostic[0];
+            let level = if header.starts_with("warning") {
+                Level::Warning
+            } else {
+                Level::Error
+            };
+            let message = header[header.find(": ")? + 2..].to_string();
+            let mut location = diagnostic
+                .iter()
+                .find_map(|line| line.trim_start().strip_prefix("--> src/lib.rs:"))?
+                .split(':');
+            let line = location.next()?.parse().ok()?;
+            let column = location.next()?.parse().ok()?;
+            let (end_line, end_column) = span_end(&diagnostic).unwrap_or((line, column + 1));
+            Some(Diagnostic {
+                level,
+                message,
+                line,
+                column,
+                end_line,
+                end_column,
+                suggestion: None,
+                rendered: None,
+            })
+        })
+        .collect()
+}
+
+/// Parse the errors and warnings which point into `src/lib.rs` out of the JSON messages `cargo`
+/// writes to stdout with `--message-format=json`.
+///
+/// Lines which are not compiler messages are skipped.
+pub fn json_diagnostics(stdout: &str) -> Vec<Diagnostic> {
+    use serde_json::Value;
+
+    stdout
+        .lines()
+        .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+        .filter(|msg| msg["reason"] == "compiler-message")
+        .filter_map(|msg| {
+            let msg = &msg["message"];
+            let level = match msg["level"].as_str()? {
+                "error" => Level::Error,
+                "warning" => Level::Warning,
+                _ => return None,
+            };
+            let span = msg["spans"]
+                .as_array()?
+                .iter()
+                .find(|span| span["is_primary"] == true && span["file_name"] == "src/lib.rs")?;
+            let num = |key: &str| span[key].as_u64().map(|n| n as usize);
+            // the suggestion is usually on a child help message
+            let suggestion = std::iter::once(msg)
+                .chain(msg["children"].as_array().into_iter().flatten())
+                .filter_map(|m| m["spans"].as_array())
+                .flatten()
+                .find_map(|span| span["suggested_replacement"].as_str())
+                .map(String::from);
+
+            Some(Diagnostic {
+                level,
+                message: msg["message"].as_str()?.to_string(),
+                line: num("line_start")?,
+                column: num("column_start")?,
+                end_line: num("line_end")?,
+                end_column: num("column_end")?,
+                suggestion,
+                rendered: msg["rendered"].as_str().map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// The end of the primary span, parsed from the carets of the snippet.
+fn span_end(diagnostic: &[&str]) -> Option<(usize, usize)> {
+    let mut code_line = None;
+    for line in diagnostic {
+        let (gutter, rest) = match line.find('|') {
+            Some(idx) => (&line[..idx], &line[idx + 1..]),
+            None => continue,
+        };
+        let rest = rest.strip_prefix(' ').unwrap_or(rest);
+
+        if let Ok(n) = gutter.trim().parse::<usize>() {
+            code_line = Some(n);
+            continue;
+        } else if !gutter.trim().is_empty() {
+            continue;
+        }
+
+        let caret = match rest.find('^') {
+            Some(idx) => idx,
+            None => continue,
+        };
+        let carets = rest[caret..].chars().take_while(|&c| c == '^').count();
+        let before = &rest[..caret];
+
+        if let Some(marker) = before.rfind("|_") {
+            // the end of a multi-line span, the code lines are offset by the `| ` gutter
+            let column = caret - (marker + 2) + carets + 1;
+            return Some((code_line?, column));
+        } else if before.trim().is_empty() {
+            return Some((code_line?, caret + carets + 1));
+        }
+        // otherwise the start of a multi-line span
+    }
+
+    None
+}
+
+impl Diagnostic {
+    /// Render the diagnostic with the offending lines of `source`, like `rustc` displays it.
+    ///
+    /// `source` is the contents of the generated `src/lib.rs`. The span is underlined with carets,
+    /// spans covering multiple lines underline each line. Tabs are expanded to four spaces.
+    ///
+    /// # Example
+    /// ```rust
+    /// # use papyrus::compile::{Diagnostic, Level};
+    /// let diag = Diagnostic {
+    ///     level: Level::Error,
+    ///     message: "cannot find value `b` in this scope".to_string(),
+    ///     line: 1,
+    ///     column: 9,
+    ///     end_line: 1,
+    ///     end_column: 10,
+    ///     suggestion: None,
+    ///     rendered: None,
+    /// };
+    ///
+    /// assert_eq!(
+    ///     diag.render_with_source("let a = b;"),
+    ///     "error: cannot find value `b` in this scope
+    ///  --> src/lib.rs:1:9
+    ///   |
+    /// 1 | let a = b;
+    ///   |         ^
+    /// "
+    /// );
+    /// ```
+    pub fn render_with_source(&self, source: &str) -> String {
+        use std::fmt::Write;
+
+        let level = match self.level {
+            Level::Error => "error",
+            Level::Warning => "warning",
+        };
+        let last_line = self.end_line.max(self.line);
+        let pad = " ".repeat(last_line.to_string().len());
+
+        let mut s = String::new();
+        writeln!(s, "{}: {}", level, self.message).ok();
+        writeln!(s, "{}--> src/lib.rs:{}:{}", pad, self.line, self.column).ok();
+        writeln!(s, "{} |", pad).ok();
+
+        for (idx, code) in source
+            .lines()
+            .enumerate()
+            .skip(self.line.saturating_sub(1))
+            .take(last_line + 1 - self.line.max(1))
+        {
+            let n = idx + 1;
+            let chars = code.chars().collect::<Vec<_>>();
+            let start = if n == self.line {
+                self.column.saturating_sub(1)
+            } else {
+                chars.iter().take_while(|c| c.is_whitespace()).count()
+            };
+            let end = if n == last_line {
+                self.end_column.saturating_sub(1)
+            } else {
+                chars.len()
+            };
+            let start = start.min(chars.len());
+            let end = end.min(chars.len()).max(start + 1);
+
+            let offset = expanded_width(&chars[..start]);
+            let carets = expanded_width(&chars[start..end.min(chars.len())]).max(1);
+
+            writeln!(s, "{:>w$} | {}", n, expand_tabs(code), w = pad.len()).ok();
+            writeln!(s, "{} | {}{}", pad, " ".repeat(offset), "^".repeat(carets)).ok();
+        }
+
+        s
+    }
+}
+
+const TAB_WIDTH: usize = 4;
+
+fn expand_tabs(s: &str) -> String {
+    s.replace('\t', &" ".repeat(TAB_WIDTH))
+}
+
+fn expanded_width(chars: &[char]) -> usize {
+    chars
+        .iter()
+        .map(|&c| if c == '\t' { TAB_WIDTH } else { 1 })
+        .sum()
+}
+
+/// Map a diagnostic back to the 0-based line in the user's original input `src`.
+///
+/// `generated` is the contents of the generated `src/lib.rs` which `src` was compiled in. The
+/// statements of `src` are located in the evaluation function, this accounts for the code that is
+/// generated before the input (crates, persistent module code, the function signature). Returns
+/// `None` if the diagnostic does not fall within the input statements, errors in items are not
+/// mapped.
+///
+/// The input is formatted before being compiled, so for a statement spanning multiple lines the
+/// line within the statement is a best guess.
+pub fn map_error_to_input_line(diag: &Diagnostic, src: &str, generated: &str) -> Option<usize> {
+    let stmts = match input::parse_program(src) {
+        InputResult::Program(input) => input.stmts,
+        _ => return None,
+    };
+
+    // the line spans of the statements in src, matching the statements parse_program produces
+    let block = syn::parse_str::<Block>(&format!("{{ {} }}", src)).ok()?;
+    let spans = block
+        .stmts
+        .iter()
+        .filter(|stmt| match stmt {
+            Stmt::Item(Item::Macro(_)) => true,
+            Stmt::Item(_) => false,
+            _ => true,
+        })
+        .map(|stmt| {
+            let span = syn::spanned::Spanned::span(stmt);
+            (span.start().line - 1, span.end().line - 1)
+        })
+        .collect::<Vec<_>>();
+
+    if stmts.is_empty() || spans.len() != stmts.len() {
+        return None;
+    }
+
+    let start_line = grp_start_line(&StmtGrp(stmts.clone()), generated)?;
+    let mut rel = diag.line.checked_sub(start_line)?;
+
+    for (stmt, (start, end)) in stmts.iter().zip(spans) {
+        let lines = stmt.expr.lines().count().max(1);
+        if rel < lines {
+            return Some(start + rel.min(end - start));
+        }
+        rel -= lines;
+    }
+
+    None
+}
+
+/// The 1-based line in `generated` where the statement group starts.
+///
+/// The last occurrence is used, matching the group's `let out# = expr;` binding.
+fn grp_start_line(grp: &StmtGrp, generated: &str) -> Option<usize> {
+    let (last, init) = grp.0.split_last()?;
+
+    let mut prefix = String::new();
+    for stmt in init {
+        prefix.push_str(&stmt.expr);
+        if stmt.semi {
+            prefix.push(';');
+        }
+        prefix.push('\n');
+    }
+    prefix.push_str("let out");
+    let binding = format!(" = {};", last.expr);
+
+    generated
+        .rmatch_indices(&prefix)
+        .find(|(idx, _)| {
+            let rest =
+                generated[idx + prefix.len()..].trim_start_matches(|c: char| c.is_ascii_digit());
+            rest.starts_with(&binding)
+        })
+        .map(|(idx, _)| generated[..idx].matches('\n').count() + 1)
+}
+
+#[test]
+fn diagnostics_test() {
+    let stderr = r#"   Compiling papyrus_mem_code v0.1.0 (/home/papyrus)
+error[E0599]: no method named `foo` found for type `{integer}` in the current scope
+ --> src/lib.rs:4:3
+  |
+4 | a.foo()
+  |   ^^^ method not found in `{integer}`
+
+warning: unused variable: `b`
+ --> src/lib.rs:3:5
+  |
+3 | let b = 1;
+  |     ^ help: if this is intentional, prefix it with an underscore: `_b`
+
+error: aborting due to previous error
+"#;
+    assert_eq!(
+        diagnostics(stderr),
+        vec![
+            Diagnostic {
+                level: Level::Error,
+                message: "no method named `foo` found for type `{integer}` in the current scope"
+                    .to_string(),
+                line: 4,
+                column: 3,
+                end_line: 4,
+                end_column: 6,
+                suggestion: None,
+                rendered: None,
+            },
+            Diagnostic {
+                level: Level::Warning,
+                message: "unused variable: `b`".to_string(),
+                line: 3,
+                column: 5,
+                end_line: 3,
+                end_column: 6,
+                suggestion: None,
+                rendered: None,
+            }
+        ]
+    );
+}
+
+#[test]
+fn json_diagnostics_test() {
+    let stdout = r#"{"reason":"compiler-artifact","package_id":"fxhash 0.2.1"}
+{"reason":"compiler-message","message":{"message":"unused variable: `b`","level":"warning","spans":[{"file_name":"src/lib.rs","line_start":3,"line_end":3,"column_start":5,"column_end":6,"is_primary":true,"suggested_replacement":null}],"children":[{"message":"if this is intentional, prefix it with an underscore","level":"help","spans":[{"file_name":"src/lib.rs","line_start":3,"line_end":3,"column_start":5,"column_end":6,"is_primary":true,"suggested_replacement":"_b"}],"children":[]}],"rendered":"warning: unused variable: `b`\n"}}
+{"reason":"compiler-message","message":{"message":"mismatched types","level":"error","spans":[{"file_name":"src/other.rs","line_start":1,"line_end":1,"column_start":1,"column_end":2,"is_primary":true},{"file_name":"src/lib.rs","line_start":4,"line_end":5,"column_start":13,"column_end":2,"is_primary":true}],"children":[]}}
+{"reason":"compiler-message","message":{"message":"aborting due to previous error","level":"error","spans":[],"children":[]}}
+not json
+"#;
+    assert_eq!(
+        json_diagnostics(stdout),
+        vec![
+            Diagnostic {
+                level: Level::Warning,
+                message: "unused variable: `b`".to_string(),
+                line: 3,
+                column: 5,
+                end_line: 3,
+                end_column: 6,
+                suggestion: Some("_b".to_string()),
+                rendered: Some("warning: unused variable: `b`\n".to_string()),
+            },
+            Diagnostic {
+                level: Level::Error,
+                message: "mismatched types".to_string(),
+                line: 4,
+                column: 13,
+                end_line: 5,
+                end_column: 2,
+                suggestion: None,
+                rendered: None,
+            }
+        ]
+    );
+}
+
+#[test]
+fn multi_line_span_test() {
+    let stderr = r#"error[E0308]: mismatched types
+ --> src/lib.rs:3:13
+  |
+3 |       let a = {
+  |  _____________^
+4 | |         1
+5 | |     };
+  | |_____^ expected `()`, found integer
+"#;
+    let diag = diagnostics(stderr).remove(0);
+    assert_eq!((diag.line, diag.column), (3, 13));
+    assert_eq!((diag.end_line, diag.end_column), (5, 6));
+}
+
+#[test]
+fn render_with_source_test() {
+    let diag = Diagnostic {
+        level: Level::Warning,
+        message: "unused variable: `b`".to_string(),
+        line: 2,
+        column: 6,
+        end_line: 2,
+        end_column: 7,
+        suggestion: None,
+        rendered: None,
+    };
+    // the tab is expanded and the caret aligned under `b`
+    assert_eq!(
+        diag.render_with_source("fn f() {\n\tlet b = 1;\n}"),
+        "warning: unused variable: `b`\n \
+         --> src/lib.rs:2:6\n  \
+         |\n\
+         2 |     let b = 1;\n  \
+         |         ^\n"
+    );
+
+    let diag = Diagnostic {
+        level: Level::Error,
+        message: "mismatched types".to_string(),
+        line: 1,
+        column: 9,
+        end_line: 3,
+        end_column: 2,
+        suggestion: None,
+        rendered: None,
+    };
+    assert_eq!(
+        diag.render_with_source("let a = {\n    1\n};"),
+        "error: mismatched types\n \
+         --> src/lib.rs:1:9\n  \
+         |\n\
+         1 | let a = {\n  \
+         |         ^\n\
+         2 |     1\n  \
+         |     ^\n\
+         3 | };\n  \
+         | ^\n"
+    );
+
+    // the line numbers are right aligned in the gutter
+    let diag = Diagnostic {
+        line: 9,
+        column: 1,
+        end_line: 10,
+        end_column: 2,
+        ..diag
+    };
+    let source = "\n".repeat(8) + "a(\n)";
+    assert_eq!(
+        diag.render_with_source(&source),
+        "error: mismatched types\n  \
+         --> src/lib.rs:9:1\n   \
+         |\n \
+         9 | a(\n   \
+         | ^^\n\
+         10 | )\n   \
+         | ^\n"
+    );
+}
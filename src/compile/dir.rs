@@ -0,0 +1,119 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// A uniquely named compile directory which is removed when dropped.
+///
+/// The directory can be used anywhere a compile directory path is taken, such as
+/// [`build_compile_dir`](super::build_compile_dir). Use [`persist`](CompileDir::persist) to keep
+/// the directory once finished with.
+#[derive(Debug)]
+pub struct CompileDir {
+    path: PathBuf,
+    persist: bool,
+}
+
+impl CompileDir {
+    /// Create a directory under the system temporary directory, which respects `TMPDIR`.
+    pub fn temp() -> io::Result<Self> {
+        Self::new_in(env::temp_dir())
+    }
+
+    /// Create a directory in memory, under `/dev/shm` on Linux, for faster rebuilds.
+    ///
+    /// Falls back to [`temp`](CompileDir::temp) if there is no in-memory file system.
+    pub fn temp_in_memory() -> io::Result<Self> {
+        let shm = Path::new("/dev/shm");
+        if cfg!(target_os = "linux") && shm.is_dir() {
+            if let Ok(dir) = Self::new_in(shm) {
+                return Ok(dir);
+            }
+        }
+        Self::temp()
+    }
+
+    /// Create a uniquely named directory in `parent`.
+    pub fn new_in<P: AsRef<Path>>(parent: P) -> io::Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let parent = parent.as_ref();
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.subsec_nanos())
+            .unwrap_or_default();
+
+        loop {
+            let name = format!(
+                "papyrus-{}-{}-{}",
+                std::process::id(),
+                COUNTER.fetch_add(1, Ordering::Relaxed),
+                nanos
+            );
+            let path = parent.join(name);
+            match fs::create_dir(&path) {
+                Ok(()) => {
+                    return Ok(CompileDir {
+                        path,
+                        persist: false,
+                    })
+                }
+                Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    /// The path of the directory.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Keep the directory rather than removing it when dropped, returning its path.
+    pub fn persist(mut self) -> PathBuf {
+        self.persist = true;
+        std::mem::take(&mut self.path)
+    }
+}
+
+impl AsRef<Path> for CompileDir {
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl Drop for CompileDir {
+    fn drop(&mut self) {
+        if !self.persist {
+            fs::remove_dir_all(&self.path).ok();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compile_dir_test() {
+        let a = CompileDir::temp().unwrap();
+        let b = CompileDir::temp().unwrap();
+        assert_ne!(a.path(), b.path());
+        assert!(a.path().starts_with(env::temp_dir()));
+        assert!(a.path().is_dir());
+
+        fs::write(a.path().join("Cargo.toml"), "").unwrap();
+        let path = a.path().to_path_buf();
+        drop(a);
+        assert!(!path.exists());
+
+        let path = b.persist();
+        assert!(path.is_dir());
+        fs::remove_dir(path).unwrap();
+
+        let mem = CompileDir::temp_in_memory().unwrap();
+        assert!(mem.path().is_dir());
+    }
+}
@@ -1,12 +1,13 @@
 use ::kserd::Kserd;
 use libloading::{Library, Symbol};
+use std::borrow::Cow;
 use std::path::Path;
 
 /// We don't type anything here. You must be **VERY** careful to pass through the correct borrow to match the
 /// function signature!
 type DataFunc<D> = unsafe fn(D) -> Kserd<'static>;
 
-type ExecResult = Result<(Kserd<'static>, Library), &'static str>;
+type ExecResult = Result<(Kserd<'static>, Library), Cow<'static, str>>;
 
 pub(crate) fn exec<P: AsRef<Path>, D>(
     library_file: P,
@@ -27,8 +28,12 @@ fn exec_no_redirect<P: AsRef<Path>, Data>(
     let res = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| unsafe { func(app_data) }));
 
     match res {
+        // the error of a `?` operator
+        Ok(kserd) if kserd.id() == Some(crate::code::EVAL_ERROR_ID) => {
+            Err(kserd.str().unwrap_or_default().to_owned().into())
+        }
         Ok(kserd) => Ok((kserd, lib)),
-        Err(_) => Err("a panic occured with evaluation"),
+        Err(_) => Err("a panic occured with evaluation".into()),
     }
 }
 
@@ -1,24 +1,49 @@
 //! Pertains to compiling a working directory into a library, then executing a function in that library.
 
 mod build;
+mod cache;
+mod config;
 mod construct;
+mod diagnostic;
+mod dir;
 mod execute;
+mod suggest;
+mod syntax;
+mod toolchain;
+mod type_of;
+mod variants;
 
-pub use self::build::{compile, unshackle_library_file, CompilationError};
-pub use self::construct::build_compile_dir;
+pub use self::build::{
+    check, compile, compile_async, compile_with_warnings, doctest, emit, resolved_dependencies,
+    unit_tests, unshackle_library_file, CompilationError, CompileHandle, DoctestResult, Emit,
+    ResolvedDep, TestResult,
+};
+pub use self::cache::CompileCache;
+pub use self::config::{BuildConfig, Edition, Snapshot};
+pub use self::construct::{build_compile_dir, clean_compile_dir, generate_project_files};
+pub use self::diagnostic::{
+    diagnostics, json_diagnostics, map_error_to_input_line, Diagnostic, Level,
+};
+pub use self::dir::CompileDir;
 pub(crate) use self::execute::exec;
+pub use self::suggest::{suggestions, Suggestion};
+pub use self::syntax::{check_syntax, SyntaxError};
+pub use self::toolchain::{detect_toolchain_channel, Channel};
+pub use self::type_of::type_of;
+pub use self::variants::{compile_variants, RunError, Variant};
 
-/// The library name to compile as.c
+/// The default library name to compile as.
 const LIBRARY_NAME: &str = "papyrus_mem_code";
 
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::code::*;
-    use crate::linking::{Extern, LinkingConfiguration};
+    use crate::linking::{AsyncRuntime, Extern, LinkingConfiguration};
     use ::kserd::Kserd;
     use std::fs;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
+    use std::time::{Duration, Instant};
 
     #[test]
     fn nodata_build_fmt_compile_eval_test() {
@@ -27,13 +52,25 @@ mod tests {
         let linking_config = LinkingConfiguration::default();
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
             .unwrap()
             .contains("\nlet out0 = 2+2;"));
 
         // compile
-        let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+        let path = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
 
         // eval
         let r = exec::<_, _>(path, "_lib_intern_eval", &()).unwrap(); // execute library fn
@@ -52,13 +89,25 @@ mod tests {
         );
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
             .unwrap()
             .contains("\nlet out0 = 2+2;"));
 
         // compile
-        let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+        let path = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
 
         // eval
         let r = exec::<_, _>(path, "_lib_intern_eval", &()).unwrap(); // execute library fn
@@ -77,7 +126,13 @@ mod tests {
         );
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
             .unwrap()
             .contains("\nlet out0 = 2+2;"));
@@ -89,7 +144,13 @@ mod tests {
         // 	.contains("\n    let out0 = 2 + 2;")); // should be tabbed in (once, unless i wrap it more)
 
         // compile
-        let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+        let path = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
 
         // eval
         let r = exec::<_, _>(path, "_lib_intern_eval", &()).unwrap(); // execute library fn
@@ -108,7 +169,13 @@ mod tests {
         );
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
             .unwrap()
             .contains("\nlet out0 = 2+2;"));
@@ -120,7 +187,13 @@ mod tests {
         // 	.contains("\n    let out0 = 2 + 2;")); // should be tabbed in (once, unless i wrap it more)
 
         // compile
-        let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+        let path = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
 
         // eval
         let r = exec(path, "_lib_intern_eval", &()).unwrap(); // execute library fn
@@ -135,13 +208,24 @@ mod tests {
         let linking_config = LinkingConfiguration::default();
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
             .unwrap()
             .contains("\nlet out0 = 2+;"));
 
         // compile
-        let r = compile(&compile_dir, &linking_config, |_| ());
+        let r = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        );
         assert!(r.is_err());
         match r.unwrap_err() {
             CompilationError::CompileError(_) => (),
@@ -149,6 +233,474 @@ mod tests {
         }
     }
 
+    #[test]
+    fn json_diagnostics_compile_test() {
+        let compile_dir = "target/testing/json_diagnostics_compile_test";
+        let mut code = SourceCode::new();
+        code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let a: u8 = \"a\"".to_string(),
+                semi: true,
+            },
+            Statement {
+                expr: "a".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            json_diagnostics: true,
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let err = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap_err();
+        let diagnostics = match err {
+            CompilationError::Diagnostics(d) => d,
+            e => panic!("expecting Diagnostics: {}", e),
+        };
+
+        let diag = &diagnostics[0];
+        assert_eq!(diag.level, Level::Error);
+        assert_eq!(diag.message, "mismatched types");
+        let lib = fs::read_to_string(format!("{}/src/lib.rs", compile_dir)).unwrap();
+        let line = lib.lines().nth(diag.line - 1).unwrap();
+        assert_eq!(&line[diag.column - 1..diag.end_column - 1], "\"a\"");
+    }
+
+    #[test]
+    fn custom_lib_name_test() {
+        let compile_dir = "target/testing/custom_lib_name_test";
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            lib_name: "custom_snippet_lib".to_string(),
+            ..BuildConfig::default()
+        };
+
+        // build
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let cargotoml = fs::read_to_string(format!("{}/Cargo.toml", compile_dir)).unwrap();
+        assert!(cargotoml.contains("[lib]\nname = \"custom_snippet_lib\"\n"));
+
+        // compile
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        assert_eq!(path, build_config.lib_path(compile_dir));
+        assert!(path.exists());
+        let filename = path.file_name().unwrap().to_string_lossy().into_owned();
+        assert!(filename.contains("custom_snippet_lib"));
+        assert!(build_config.rlib_path(compile_dir).exists());
+    }
+
+    #[test]
+    fn compile_variants_test() {
+        let dir = "target/testing/compile_variants_test";
+        let variant = |expr: &str| {
+            let mut code = SourceCode::new();
+            code.stmts.push(StmtGrp(vec![Statement {
+                expr: expr.to_string(),
+                semi: false,
+            }]));
+            vec![("lib".into(), code)].into_iter().collect()
+        };
+        let variants = vec![
+            ("variant_a".to_string(), variant("(1..=10u64).sum::<u64>()")),
+            ("variant_b".to_string(), variant("10 * 11 / 2")),
+            ("not-valid".to_string(), variant("1")),
+        ];
+
+        // the output path would be shared by the variants
+        let build_config = BuildConfig {
+            output_path: Some("lib.so".into()),
+            ..BuildConfig::default()
+        };
+        let mut results = compile_variants(
+            variants,
+            dir,
+            &LinkingConfiguration::default(),
+            &build_config,
+        )
+        .into_iter();
+
+        let mut a = results.next().unwrap().unwrap();
+        let mut b = results.next().unwrap().unwrap();
+        assert!(results.next().unwrap().is_err());
+
+        assert_eq!(&a.name, "variant_a");
+        assert_eq!(&b.name, "variant_b");
+        assert!(a.lib_file.exists());
+        assert!(b.lib_file.exists());
+        assert_ne!(a.lib_file, b.lib_file);
+        assert!(a.lib_file.starts_with(Path::new(dir).join("variant_a")));
+        assert!(a.lib_file.to_string_lossy().contains("variant_a"));
+        assert!(b.lib_file.to_string_lossy().contains("variant_b"));
+
+        assert_eq!(a.run().unwrap().0, Kserd::new_num(55));
+        assert_eq!(b.run().unwrap().0, Kserd::new_num(55));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn build_env_test() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let compile_dir = "target/testing/build_env_test";
+        let bin = Path::new(compile_dir).join("bin");
+        fs::create_dir_all(&bin).unwrap();
+        let seen = Path::new(compile_dir).join("seen");
+        fs::remove_file(&seen).ok();
+
+        // a fixture cargo which records the toolchain it is invoked with
+        let cargo = bin.join("cargo");
+        let script = format!(
+            "#!/bin/sh\necho \"$RUSTUP_TOOLCHAIN\" > \"{}\"\n",
+            fs::canonicalize(compile_dir)
+                .unwrap()
+                .join("seen")
+                .display()
+        );
+        fs::write(&cargo, script).unwrap();
+        fs::set_permissions(&cargo, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            env: vec![
+                (
+                    "PATH".to_string(),
+                    fs::canonicalize(&bin).unwrap().display().to_string(),
+                ),
+                (
+                    "RUSTUP_TOOLCHAIN".to_string(),
+                    "fixture-toolchain".to_string(),
+                ),
+            ],
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+
+        assert_eq!(fs::read_to_string(&seen).unwrap(), "fixture-toolchain\n");
+    }
+
+    #[test]
+    fn output_path_test() {
+        let compile_dir = "target/testing/output_path_test";
+        let output = fs::canonicalize("target")
+            .unwrap()
+            .join("testing/output_path_test_out/snippet.lib");
+        fs::remove_file(&output).ok();
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            output_path: Some(output.clone()),
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+
+        assert_eq!(path, output);
+        assert!(path.exists());
+        assert!(!BuildConfig::default().lib_path(compile_dir).exists());
+
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+    }
+
+    #[test]
+    fn check_test() {
+        let compile_dir = "target/testing/check_test";
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            release: true,
+            ..BuildConfig::default()
+        };
+
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        check(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        // no library is produced under either profile
+        for &release in &[false, true] {
+            let config = BuildConfig {
+                release,
+                ..build_config.clone()
+            };
+            assert!(!config.lib_path(compile_dir).exists());
+        }
+
+        let mut code = SourceCode::new();
+        code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let a: u8 = \"a\";".to_string(),
+                semi: false,
+            },
+            Statement {
+                expr: "a".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), code)].into_iter().collect();
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        match check(compile_dir, &linking_config, &build_config, |_| ()) {
+            Err(CompilationError::CompileError(e)) => assert!(e.contains("mismatched types")),
+            r => panic!("expected a compile error: {:?}", r),
+        }
+    }
+
+    #[test]
+    fn compile_async_test() {
+        let compile_dir = "target/testing/compile_async_test";
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let handle = compile_async(compile_dir, &linking_config, &build_config, |_| ());
+        let path = loop {
+            match handle.poll() {
+                Some(r) => break r.unwrap(),
+                None => std::thread::sleep(Duration::from_millis(10)),
+            }
+        };
+        assert!(path.exists());
+        assert!(handle.poll().is_none());
+    }
+
+    #[test]
+    fn compile_async_cancel_test() {
+        let compile_dir = "target/testing/compile_async_cancel_test";
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            build_script: Some(
+                "fn main() { std::thread::sleep(std::time::Duration::from_secs(120)); }"
+                    .to_string(),
+            ),
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let start = Instant::now();
+        let handle = compile_async(compile_dir, &linking_config, &build_config, |_| ());
+        std::thread::sleep(Duration::from_millis(200));
+        handle.cancel();
+        match handle.wait() {
+            Err(CompilationError::Cancelled) => (),
+            r => panic!("expected cancelling: {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[test]
+    fn timeout_test() {
+        let compile_dir = "target/testing/timeout_test";
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            timeout: Some(Duration::from_millis(1)),
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let start = Instant::now();
+        match compile(compile_dir, &linking_config, &build_config, |_| ()) {
+            Err(CompilationError::TimedOut(t)) => assert_eq!(t, Duration::from_millis(1)),
+            r => panic!("expected a timeout: {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        // a generous timeout completes
+        let build_config = BuildConfig {
+            timeout: Some(Duration::from_secs(300)),
+            ..BuildConfig::default()
+        };
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        assert!(path.exists());
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn timeout_kills_build_script_test() {
+        let compile_dir = "target/testing/timeout_kills_build_script_test";
+        let pid_file = Path::new(compile_dir).join("build_script.pid");
+        fs::remove_file(&pid_file).ok();
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            build_script: Some(
+                r#"fn main() {
+    let pid = concat!(env!("CARGO_MANIFEST_DIR"), "/build_script.pid");
+    std::fs::write(pid, std::process::id().to_string()).unwrap();
+    std::thread::sleep(std::time::Duration::from_secs(120));
+}"#
+                .to_string(),
+            ),
+            timeout: Some(Duration::from_secs(10)),
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let start = Instant::now();
+        match compile(compile_dir, &linking_config, &build_config, |_| ()) {
+            Err(CompilationError::TimedOut(_)) => (),
+            r => panic!("expected a timeout: {:?}", r),
+        }
+        assert!(start.elapsed() < Duration::from_secs(60));
+
+        // the build script is killed along with cargo
+        let pid = fs::read_to_string(&pid_file).expect("build script should have started");
+        let alive = || {
+            std::process::Command::new("kill")
+                .args(["-0", &pid])
+                .stderr(std::process::Stdio::null())
+                .status()
+                .map(|s| s.success())
+                .unwrap_or(false)
+        };
+        let killed = Instant::now();
+        while alive() && killed.elapsed() < Duration::from_secs(5) {
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        assert!(!alive());
+    }
+
+    #[test]
+    fn snapshot_test() {
+        let fxhash = || CrateType::parse_str("extern crate fxhash;").unwrap();
+        let linking_config = LinkingConfiguration::default();
+
+        // compile once to warm the snapshot
+        let base_dir = "target/testing/snapshot_test_base";
+        let mut src_code = SourceCode::new();
+        src_code.crates.push(fxhash());
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "fxhash::hash(&1)".to_string(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        build_compile_dir(base_dir, &files, &linking_config, &BuildConfig::default()).unwrap();
+        compile(base_dir, &linking_config, &BuildConfig::default(), |_| ()).unwrap();
+
+        let snapshot = Snapshot::from_compile_dir(base_dir).unwrap();
+        assert!(snapshot.target_dir.is_absolute());
+
+        // a different snippet with the same dependencies
+        let compile_dir = "target/testing/snapshot_test";
+        fs::remove_dir_all(compile_dir).ok();
+        let mut src_code = SourceCode::new();
+        src_code.crates.push(fxhash());
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "fxhash::hash(&2) > 0".to_string(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let build_config = BuildConfig {
+            snapshot: Some(snapshot.clone()),
+            ..BuildConfig::default()
+        };
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+
+        let mut stderr = Vec::new();
+        let path = compile(compile_dir, &linking_config, &build_config, |line| {
+            stderr.push(line.to_string())
+        })
+        .unwrap();
+
+        // nothing is resolved
+        assert!(
+            stderr
+                .iter()
+                .all(|l| !l.contains("Updating") && !l.contains("Locking")),
+            "{:?}",
+            stderr
+        );
+        assert!(path.starts_with(&snapshot.target_dir));
+
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_bool(true));
+    }
+
+    #[test]
+    fn tokio_runtime_test() {
+        let compile_dir = "target/testing/tokio_runtime_test";
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "async { 2 + 2 }.await".to_string(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration {
+            runtime: Some(AsyncRuntime::Tokio),
+            ..LinkingConfiguration::default()
+        };
+        let build_config = BuildConfig::default();
+
+        // build
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let cargotoml = fs::read_to_string(format!("{}/Cargo.toml", compile_dir)).unwrap();
+        assert!(cargotoml.contains("\ntokio = "));
+
+        // compile
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+
+        // eval
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+    }
+
+    #[test]
+    fn emit_asm_test() {
+        let compile_dir = "target/testing/emit_asm_test";
+        let files = vec![pass_compile_eval_file()].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+
+        let path = emit(
+            compile_dir,
+            &linking_config,
+            &build_config,
+            Emit::Asm,
+            |_| (),
+        )
+        .unwrap();
+        assert_eq!(path.extension().unwrap(), "s");
+        let asm = fs::read_to_string(path).unwrap();
+        assert!(!asm.is_empty());
+        assert!(asm.contains("_lib_intern_eval"));
+    }
+
+    #[test]
+    fn compile_error_suggestions_test() {
+        let compile_dir = "target/testing/compile_error_suggestions_test";
+        let mut code = SourceCode::new();
+        code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let abc = 1".to_string(),
+                semi: true,
+            },
+            Statement {
+                expr: "abd".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let err = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap_err();
+
+        assert!(err.suggestions().contains(&Suggestion::RenameTo {
+            from: "abd".to_string(),
+            to: "abc".to_string()
+        }));
+    }
+
     // TODO enable when not on nightly
     // Maybe look into why it doesn't work on nightly?
     // #[test]
@@ -158,13 +710,13 @@ mod tests {
     //     let linking_config = LinkingConfiguration::default();
 
     //     // build
-    //     build_compile_dir(&compile_dir, files.iter(), &linking_config).unwrap();
+    //     build_compile_dir(&compile_dir, files.iter(), &linking_config, &BuildConfig::default()).unwrap();
     //     assert!(fs::read_to_string(&format!("{}/src/lib.rs", compile_dir))
     //         .unwrap()
     //         .contains("\nlet out0 = panic!(\"eval panic\");"));
 
     //     // compile
-    //     let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+    //     let path = compile(&compile_dir, &linking_config, &BuildConfig::default(), |_| ()).unwrap();
 
     //     // eval
     //     let r = exec::<_, _, std::io::Sink>(&path, "_lib_intern_eval", &(), None); // execute library fn
@@ -199,6 +751,77 @@ mod tests {
         ("lib".into(), code)
     }
 
+    #[test]
+    fn doctest_test() {
+        let compile_dir = "target/testing/doctest_test";
+        let files = vec![{
+            let mut code = SourceCode::new();
+            code.items.push((
+                "/// ```\n/// assert_eq!(papyrus_mem_code::one(), 1);\n/// ```\npub fn one() -> i32 { 1 }"
+                    .to_string(),
+                false,
+            ));
+            ("lib".into(), code)
+        }]
+        .into_iter()
+        .collect();
+        let linking_config = LinkingConfiguration::default();
+
+        build_compile_dir(
+            compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
+
+        let results = doctest(
+            compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert!(results[0].passed);
+        assert!(results[0].name.contains("one"));
+    }
+
+    #[test]
+    fn unit_tests_test() {
+        let compile_dir = "target/testing/unit_tests_test";
+        let files = vec![{
+            let mut code = SourceCode::new();
+            code.items.push((
+                "#[test]\nfn prints() { println!(\"hello from test\"); }".to_string(),
+                false,
+            ));
+            code.items.push((
+                "#[test]\nfn fails() { assert_eq!(1, 2); }".to_string(),
+                false,
+            ));
+            ("lib".into(), code)
+        }]
+        .into_iter()
+        .collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+
+        let mut results = unit_tests(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        results.sort_by(|a, b| a.name.cmp(&b.name));
+
+        assert_eq!(results.len(), 2);
+        assert_eq!(results[0].name, "fails");
+        assert!(!results[0].passed);
+        assert!(results[0].stdout.contains("assertion"));
+        assert_eq!(results[1].name, "prints");
+        assert!(results[1].passed);
+        assert_eq!(results[1].stdout, "hello from test\n");
+    }
+
     #[test]
     fn output_externally_linked_type_as_kserd() {
         let compile_dir = "target/testing/output_externally_linked_type_as_kserd";
@@ -228,17 +851,355 @@ mod tests {
             .push_str("use external_kserd::{kserd, rand};");
 
         // build
-        build_compile_dir(&compile_dir, &files, &linking_config).unwrap();
+        build_compile_dir(
+            &compile_dir,
+            &files,
+            &linking_config,
+            &BuildConfig::default(),
+        )
+        .unwrap();
         let filestr = fs::read_to_string(&format!("{}/src/lib.rs", compile_dir)).unwrap();
         assert!(filestr.contains("\nlet out0 = rand::random::<u8>();"));
         assert!(filestr.contains("\nlet out1 = 2+2;"));
 
         // compile
-        let path = compile(&compile_dir, &linking_config, |_| ()).unwrap();
+        let path = compile(
+            &compile_dir,
+            &linking_config,
+            &BuildConfig::default(),
+            |_| (),
+        )
+        .unwrap();
 
         // eval
         let r = exec::<_, _>(path, "_lib_intern_eval", &()).unwrap(); // execute library fn
 
         assert_eq!(r.0, Kserd::new_num(4));
     }
+
+    #[test]
+    fn exposed_host_dependency_test() {
+        let compile_dir = "target/testing/exposed_host_dependency_test";
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "rand::random::<u8>().saturating_mul(0) + 4".into(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let mut linking_config = LinkingConfiguration::default();
+        linking_config.external_libs.insert(
+            Extern::new("test-resources/external_kserd/target/debug/libexternal_kserd.rlib")
+                .unwrap()
+                .expose_dependency("rand")
+                .unwrap(),
+        );
+        let build_config = BuildConfig::default();
+
+        // build
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let cargotoml = fs::read_to_string(format!("{}/Cargo.toml", compile_dir)).unwrap();
+        assert!(!cargotoml.contains("rand"));
+
+        // compile
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+
+        // eval
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+    }
+
+    #[test]
+    fn map_error_to_input_line_test() {
+        let compile_dir = "target/testing/map_error_to_input_line_test";
+        let src = "let a = 1;\na.foo()";
+        let input = match crate::input::parse_program(src) {
+            crate::input::InputResult::Program(input) => input,
+            x => panic!("expecting program, got {:?}", x),
+        };
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(input.stmts));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let err = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap_err();
+
+        let diags = err.diagnostics();
+        assert_eq!(diags.len(), 1);
+        let generated = fs::read_to_string(format!("{}/src/lib.rs", compile_dir)).unwrap();
+        assert_eq!(map_error_to_input_line(&diags[0], src, &generated), Some(1));
+    }
+
+    #[test]
+    fn incremental_test() {
+        let compile_dir = "target/testing/incremental_test";
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            incremental: true,
+            ..BuildConfig::default()
+        };
+        let files = |expr: &str| {
+            let mut src_code = SourceCode::new();
+            src_code.stmts.push(StmtGrp(vec![Statement {
+                expr: expr.to_string(),
+                semi: false,
+            }]));
+            vec![("lib".into(), src_code)].into_iter().collect()
+        };
+        let sessions = || {
+            let mut dirs = fs::read_dir(build_config.incremental_dir(compile_dir))
+                .unwrap()
+                .map(|e| e.unwrap().file_name())
+                .collect::<Vec<_>>();
+            dirs.sort();
+            dirs
+        };
+
+        build_compile_dir(compile_dir, &files("2+2"), &linking_config, &build_config).unwrap();
+        compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        let first = sessions();
+        assert!(!first.is_empty());
+
+        // edit a line and recompile, the same incremental cache is reused
+        build_compile_dir(compile_dir, &files("2+3"), &linking_config, &build_config).unwrap();
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        assert_eq!(sessions(), first);
+
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(5));
+    }
+
+    #[test]
+    fn try_operator_test() {
+        let compile_dir = "target/testing/try_operator_test";
+        let files = |expr: &str| {
+            let mut src_code = SourceCode::new();
+            src_code.stmts.push(StmtGrp(vec![
+                Statement {
+                    expr: format!("let x: i32 = {}.parse()?", expr),
+                    semi: true,
+                },
+                Statement {
+                    expr: "x + 1".to_string(),
+                    semi: false,
+                },
+            ]));
+            vec![("lib".into(), src_code)].into_iter().collect()
+        };
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files("\"3\""), &linking_config, &build_config).unwrap();
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+        drop(r); // unload the library
+
+        // the error is returned as an evaluation error, not data
+        build_compile_dir(compile_dir, &files("\"a\""), &linking_config, &build_config).unwrap();
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        match exec(path, "_lib_intern_eval", &()) {
+            Err(e) => assert_eq!(e, "invalid digit found in string"),
+            Ok(r) => panic!("expecting an error, got {:?}", r.0),
+        }
+    }
+
+    #[test]
+    fn compile_with_warnings_test() {
+        let compile_dir = "target/testing/compile_with_warnings_test";
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let unused = 1".to_string(),
+                semi: true,
+            },
+            Statement {
+                expr: "2+2".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        // touch the source to force a rebuild so warnings are emitted
+        let lib = format!("{}/src/lib.rs", compile_dir);
+        fs::write(&lib, fs::read_to_string(&lib).unwrap()).unwrap();
+
+        let mut lines = Vec::new();
+        let (path, warnings) =
+            compile_with_warnings(compile_dir, &linking_config, &build_config, |line| {
+                lines.push(line.to_string())
+            })
+            .unwrap();
+
+        assert!(path.exists());
+        assert!(warnings
+            .iter()
+            .any(|d| d.level == Level::Warning && d.message.contains("unused variable")));
+        // progress is still passed through
+        assert!(lines
+            .iter()
+            .any(|line| line.trim_start().starts_with("Finished")));
+        assert!(lines.iter().all(|line| !line.starts_with("warning")));
+        assert!(lines.iter().all(|line| !line.contains("unused")));
+
+        // errors are still passed through
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let a: u8 = \"a\"".to_string(),
+                semi: true,
+            },
+            Statement {
+                expr: "a".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+
+        let mut lines = Vec::new();
+        match compile_with_warnings(compile_dir, &linking_config, &build_config, |line| {
+            lines.push(line.to_string())
+        }) {
+            Err(CompilationError::CompileError(e)) => assert!(e.contains("mismatched types")),
+            r => panic!("expected a compile error: {:?}", r),
+        }
+        assert!(lines.iter().any(|line| line.contains("mismatched types")));
+    }
+
+    #[test]
+    fn compile_unsuppressed_warnings_test() {
+        let compile_dir = "target/testing/compile_unsuppressed_warnings_test";
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![
+            Statement {
+                expr: "let unused = 1".to_string(),
+                semi: true,
+            },
+            Statement {
+                expr: "2+2".to_string(),
+                semi: false,
+            },
+        ]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            suppress_warnings: false,
+            ..Default::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        // touch the source to force a rebuild so warnings are emitted
+        let lib = format!("{}/src/lib.rs", compile_dir);
+        fs::write(&lib, fs::read_to_string(&lib).unwrap()).unwrap();
+
+        let mut lines = Vec::new();
+        let path = compile(compile_dir, &linking_config, &build_config, |line| {
+            lines.push(line.to_string())
+        })
+        .unwrap();
+
+        assert!(path.exists());
+        assert!(lines
+            .iter()
+            .any(|line| line.starts_with("warning") && line.contains("unused variable")));
+    }
+
+    #[test]
+    fn build_script_test() {
+        let compile_dir = "target/testing/build_script_test";
+        let mut src_code = SourceCode::new();
+        src_code
+            .items
+            .push(("#[cfg(built)]\nfn built() -> i32 { 4 }".to_string(), false));
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "built()".to_string(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig {
+            build_script: Some(r#"fn main() { println!("cargo:rustc-cfg=built"); }"#.to_string()),
+            build_deps: vec![CrateType::parse_str("extern crate rand;").unwrap()],
+            ..BuildConfig::default()
+        };
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let cargotoml = fs::read_to_string(format!("{}/Cargo.toml", compile_dir)).unwrap();
+        assert!(cargotoml.contains("\n[build-dependencies]\nrand = \"*\"\n"));
+        assert!(fs::read_to_string(format!("{}/build.rs", compile_dir))
+            .unwrap()
+            .contains("cargo:rustc-cfg=built"));
+
+        let path = compile(compile_dir, &linking_config, &build_config, |_| ()).unwrap();
+        let r = exec(path, "_lib_intern_eval", &()).unwrap();
+        assert_eq!(r.0, Kserd::new_num(4));
+        drop(r);
+
+        // the build script is removed when not configured
+        let build_config = BuildConfig::default();
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        assert!(!Path::new(compile_dir).join("build.rs").exists());
+        assert!(compile(compile_dir, &linking_config, &build_config, |_| ()).is_err());
+    }
+
+    #[test]
+    fn type_of_test() {
+        let compile_dir = "target/testing/type_of_test";
+        let mut src_code = SourceCode::new();
+        src_code.stmts.push(StmtGrp(vec![Statement {
+            expr: "vec![1u8]".to_string(),
+            semi: false,
+        }]));
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+        let lib = Path::new("lib");
+
+        let ty = |expr| {
+            type_of(
+                compile_dir,
+                &files,
+                lib,
+                expr,
+                &linking_config,
+                &build_config,
+            )
+        };
+
+        assert!(ty("2 + 2").unwrap().contains("i32"));
+        assert_eq!(ty("\"x\"").unwrap(), "&str");
+        assert_eq!(ty("out0").unwrap(), "Vec<u8>");
+        assert_eq!(ty("(1.0, 'a')").unwrap(), "(f64, char)");
+        assert!(ty("not_defined").is_err());
+
+        // the expression is not saved
+        assert_eq!(files[lib].stmts.len(), 1);
+    }
+
+    #[test]
+    fn resolved_dependencies_test() {
+        let compile_dir = "target/testing/resolved_dependencies_test";
+        let mut src_code = SourceCode::new();
+        src_code
+            .crates
+            .push(CrateType::parse_str("extern crate fxhash;").unwrap());
+        let files = vec![("lib".into(), src_code)].into_iter().collect();
+        let linking_config = LinkingConfiguration::default();
+        let build_config = BuildConfig::default();
+
+        build_compile_dir(compile_dir, &files, &linking_config, &build_config).unwrap();
+        let deps = resolved_dependencies(compile_dir, &build_config).unwrap();
+
+        let fxhash = deps.iter().find(|d| d.name == "fxhash").unwrap();
+        let semver = fxhash.version.split('.').collect::<Vec<_>>();
+        assert_eq!(semver.len(), 3);
+        assert!(semver.iter().all(|x| x.parse::<u64>().is_ok()));
+        assert!(deps.iter().any(|d| d.name == "kserd"));
+        assert!(deps.iter().all(|d| d.name != build_config.lib_name));
+    }
 }
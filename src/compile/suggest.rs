@@ -0,0 +1,207 @@
+use super::CompilationError;
+
+/// An actionable suggestion parsed from a compilation failure.
+#[derive(Debug, PartialEq, Clone)]
+pub enum Suggestion {
+    /// A crate is missing, adding `extern crate NAME;` may fix the error.
+    AddCrate(String),
+    /// A name is misspelled, there is a similar name that could be used.
+    RenameTo {
+        /// The name used in the code.
+        from: String,
+        /// The similar name that exists.
+        to: String,
+    },
+}
+
+impl CompilationError {
+    /// Parse any actionable suggestions out of a compile error.
+    ///
+    /// Only [`CompileError`](CompilationError::CompileError) has suggestions.
+    pub fn suggestions(&self) -> Vec<Suggestion> {
+        match self {
+            CompilationError::CompileError(stderr) => suggestions(stderr),
+            _ => Vec::new(),
+        }
+    }
+}
+
+/// Parse the actionable suggestions out of the compiler's `stderr` output.
+///
+/// Missing crates (`E0432`, `E0433`, `E0463`) are suggested as [`Suggestion::AddCrate`], and
+/// similar names (`help: ... a similar name exists`) as [`Suggestion::RenameTo`].
+pub fn suggestions(stderr: &str) -> Vec<Suggestion> {
+    let mut suggestions = Vec::new();
+
+    for diagnostic in split_diagnostics(stderr) {
+        let header = diagnostic[0];
+
+        if header.starts_with("error[E0432]")
+            || header.starts_with("error[E0433]")
+            || header.starts_with("error[E0463]")
+        {
+            const PATTERNS: &[&str] = &[
+                "crate named `",
+                "unlinked crate `",
+                "undeclared crate or module `",
+                "can't find crate for `",
+            ];
+            let name = diagnostic
+                .iter()
+                .find_map(|line| PATTERNS.iter().find_map(|pat| ticked_after(line, pat)));
+            if let Some(name) = name {
+                let s = Suggestion::AddCrate(name.to_string());
+                if !suggestions.contains(&s) {
+                    suggestions.push(s);
+                }
+            }
+        }
+
+        if let Some(idx) = diagnostic
+            .iter()
+            .position(|line| line.contains("help:") && line.contains("similar name"))
+        {
+            let help = diagnostic[idx];
+            let from = ticked_after(header, "`");
+            // older compilers put the name on the help line, newer show a diff
+            let to = ticked_after(help, "there is a method `")
+                .or_else(|| ticked_after(help, "exists: `"))
+                .or_else(|| {
+                    let removed = diagnostic[idx..].iter().find_map(|l| diff_line(l, '-'))?;
+                    let added = diagnostic[idx..].iter().find_map(|l| diff_line(l, '+'))?;
+                    Some(changed_text(removed, added))
+                });
+
+            match (from, to) {
+                (Some(from), Some(to)) if !to.is_empty() && from != to => {
+                    suggestions.push(Suggestion::RenameTo {
+                        from: from.to_string(),
+                        to: to.to_string(),
+                    })
+                }
+                _ => (),
+            }
+        }
+    }
+
+    suggestions
+}
+
+/// Split the output into the lines of each diagnostic, which start with `error`.
+pub(super) fn split_diagnostics(stderr: &str) -> Vec<Vec<&str>> {
+    let mut diagnostics: Vec<Vec<&str>> = Vec::new();
+    for line in stderr.lines() {
+        if line.starts_with("error") || line.starts_with("warning") {
+            diagnostics.push(vec![line]);
+        } else if let Some(d) = diagnostics.last_mut() {
+            d.push(line);
+        }
+    }
+    diagnostics
+}
+
+/// The text between backticks which follows `pat`. `pat` should end with a backtick.
+fn ticked_after<'a>(line: &'a str, pat: &str) -> Option<&'a str> {
+    let start = line.find(pat)? + pat.len();
+    let len = line[start..].find('`')?;
+    Some(&line[start..start + len])
+}
+
+/// A line of a suggestion diff, ie `1 - let a = 1;`.
+fn diff_line(line: &str, sign: char) -> Option<&str> {
+    let line = line.trim_start();
+    let rest = line.trim_start_matches(|c: char| c.is_ascii_digit());
+    if rest.len() == line.len() {
+        return None; // no line number
+    }
+    let rest = rest.strip_prefix(' ')?.strip_prefix(sign)?;
+    Some(rest.strip_prefix(' ').unwrap_or(rest))
+}
+
+/// The identifier in `new` at the first position it differs from `old`.
+fn changed_text<'a>(old: &str, new: &'a str) -> &'a str {
+    let is_ident = |c: char| c.is_alphanumeric() || c == '_';
+
+    let diff = old
+        .char_indices()
+        .zip(new.chars())
+        .find(|((_, a), b)| a != b)
+        .map(|((i, _), _)| i)
+        .unwrap_or_else(|| old.len().min(new.len()));
+
+    let start = new[..diff]
+        .rfind(|c| !is_ident(c))
+        .map(|i| i + 1)
+        .unwrap_or(0);
+    let end = new[diff..]
+        .find(|c| !is_ident(c))
+        .map(|i| diff + i)
+        .unwrap_or(new.len());
+
+    &new[start..end]
+}
+
+#[test]
+fn suggestions_test() {
+    let stderr = r#"error[E0432]: unresolved import `rand`
+ --> src/lib.rs:2:5
+  |
+2 | use rand::Rng;
+  |     ^^^^ use of unresolved module or unlinked crate `rand`
+  |
+  = help: if you wanted to use a crate named `rand`, use `cargo add rand` to add it to your `Cargo.toml`
+
+error[E0425]: cannot find value `abd` in this scope
+ --> src/lib.rs:1:34
+  |
+1 | pub fn f() -> i32 { let abc = 1; abd }
+  |                                  ^^^
+  |
+help: a local variable with a similar name exists
+  |
+1 - pub fn f() -> i32 { let abc = 1; abd }
+1 + pub fn f() -> i32 { let abc = 1; abc }
+  |
+
+error[E0599]: no method named `lenn` found for struct `Vec<i32>` in the current scope
+ --> src/lib.rs:3:54
+  |
+help: there is a method `len` with a similar name
+  |
+3 - pub fn g() { let v: Vec<i32> = Vec::new(); let _ = v.lenn(); }
+3 + pub fn g() { let v: Vec<i32> = Vec::new(); let _ = v.len(); }
+  |
+
+error[E0425]: cannot find value `foo_bar` in this scope
+ --> src/lib.rs:4:5
+  |
+4 |     foo_bar
+  |     ^^^^^^^ help: a local variable with a similar name exists: `foo_baz`
+
+error[E0463]: can't find crate for `regex`
+
+error: aborting due to 5 previous errors
+"#;
+
+    assert_eq!(
+        suggestions(stderr),
+        vec![
+            Suggestion::AddCrate("rand".to_string()),
+            Suggestion::RenameTo {
+                from: "abd".to_string(),
+                to: "abc".to_string()
+            },
+            Suggestion::RenameTo {
+                from: "lenn".to_string(),
+                to: "len".to_string()
+            },
+            Suggestion::RenameTo {
+                from: "foo_bar".to_string(),
+                to: "foo_baz".to_string()
+            },
+            Suggestion::AddCrate("regex".to_string()),
+        ]
+    );
+
+    assert_eq!(suggestions("error: linking failed"), vec![]);
+}
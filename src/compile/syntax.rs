@@ -0,0 +1,84 @@
+use crate::code::{self, ModsMap};
+use crate::linking::LinkingConfiguration;
+use std::{error, fmt};
+
+/// Parse the source code that would be written to the compilation directory, without invoking
+/// `cargo`.
+///
+/// This is a fast check that catches syntax errors before the (relatively) slow compilation
+/// step. Only syntax is checked, type checking and borrow checking still require compilation.
+pub fn check_syntax(
+    mods_map: &ModsMap,
+    linking_config: &LinkingConfiguration,
+) -> Result<(), SyntaxError> {
+    let (src_code, _map) = code::construct_source_code(mods_map, linking_config);
+
+    syn::parse_file(&src_code).map(|_| ()).map_err(|e| {
+        let start = e.span().start();
+        SyntaxError {
+            msg: e.to_string(),
+            line: start.line,
+            column: start.column,
+        }
+    })
+}
+
+/// A syntax error found in the constructed source code.
+#[derive(Debug, PartialEq, Clone)]
+pub struct SyntaxError {
+    /// The parser error message.
+    pub msg: String,
+    /// The 1-based line in the constructed source code where the error starts.
+    pub line: usize,
+    /// The 0-based column in the constructed source code where the error starts.
+    pub column: usize,
+}
+
+impl error::Error for SyntaxError {}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "syntax error at line {} column {}: {}",
+            self.line, self.column, self.msg
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::code::{SourceCode, Statement, StmtGrp};
+
+    fn mods_map(expr: &str) -> ModsMap {
+        let mut code = SourceCode::new();
+        code.stmts.push(StmtGrp(vec![Statement {
+            expr: expr.to_string(),
+            semi: false,
+        }]));
+        vec![("lib".into(), code)].into_iter().collect()
+    }
+
+    #[test]
+    fn check_syntax_pass() {
+        let linking_config = LinkingConfiguration::default();
+        assert_eq!(check_syntax(&mods_map("2+2"), &linking_config), Ok(()));
+    }
+
+    #[test]
+    fn check_syntax_fail_with_location() {
+        // no compile directory is involved, the check is done purely in memory
+        let linking_config = LinkingConfiguration::default();
+        let err = check_syntax(&mods_map("2+"), &linking_config).unwrap_err();
+
+        // #[no_mangle]
+        // pub extern "C" fn _lib_intern_eval() -> kserd::Kserd<'static> {
+        // let out0 = 2+;
+        assert_eq!(err.line, 3);
+        assert_eq!(err.column, 13);
+        assert!(err
+            .to_string()
+            .starts_with("syntax error at line 3 column 13: "));
+    }
+}
@@ -0,0 +1,88 @@
+use std::process::Command;
+use std::sync::Mutex;
+
+/// The release channel of a Rust toolchain.
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum Channel {
+    /// A stable release, ie `1.40.0`.
+    Stable,
+    /// A beta release, ie `1.41.0-beta.1`.
+    Beta,
+    /// A nightly release, ie `1.42.0-nightly`. Allows `#![feature]` attributes.
+    Nightly,
+    /// A locally built compiler, ie `1.42.0-dev`.
+    Dev,
+}
+
+/// Detect the channel of the default toolchain, by running `rustc -vV`.
+///
+/// The result is cached, `rustc` is only run on the first call. Returns `None` if `rustc` could
+/// not be run or the output could not be parsed.
+///
+/// This can be used to warn before compiling snippets that use nightly-only features on stable.
+pub fn detect_toolchain_channel() -> Option<Channel> {
+    static CHANNEL: Mutex<Option<Option<Channel>>> = Mutex::new(None);
+
+    let mut channel = CHANNEL.lock().unwrap_or_else(|e| e.into_inner());
+    *channel.get_or_insert_with(|| {
+        let output = Command::new("rustc").arg("-vV").output().ok()?;
+        if output.status.success() {
+            parse_channel(&String::from_utf8_lossy(&output.stdout))
+        } else {
+            None
+        }
+    })
+}
+
+/// Parses the `release` line of `rustc -vV` output.
+fn parse_channel(verbose_version: &str) -> Option<Channel> {
+    let release = verbose_version
+        .lines()
+        .find(|line| line.starts_with("release: "))?["release: ".len()..]
+        .trim();
+
+    let channel = match release.find('-') {
+        None => Channel::Stable,
+        Some(idx) => match &release[idx + 1..] {
+            x if x.starts_with("beta") => Channel::Beta,
+            x if x.starts_with("nightly") => Channel::Nightly,
+            x if x.starts_with("dev") => Channel::Dev,
+            _ => return None,
+        },
+    };
+
+    Some(channel)
+}
+
+#[test]
+fn parse_channel_test() {
+    let output = |release| {
+        format!(
+            "rustc 1.42.0-nightly (3291ae339 2020-01-15)\n\
+             binary: rustc\n\
+             commit-hash: 3291ae33907f2e7ac9acd9e5c9e6c4a5a2a4d4f4\n\
+             commit-date: 2020-01-15\n\
+             host: x86_64-unknown-linux-gnu\n\
+             release: {}\n\
+             LLVM version: 9.0\n",
+            release
+        )
+    };
+
+    assert_eq!(parse_channel(&output("1.40.0")), Some(Channel::Stable));
+    assert_eq!(parse_channel(&output("1.41.0-beta.1")), Some(Channel::Beta));
+    assert_eq!(
+        parse_channel(&output("1.42.0-nightly")),
+        Some(Channel::Nightly)
+    );
+    assert_eq!(parse_channel(&output("1.42.0-dev")), Some(Channel::Dev));
+    assert_eq!(parse_channel(&output("1.42.0-foo")), None);
+    assert_eq!(parse_channel("rustc 1.40.0"), None);
+}
+
+#[test]
+fn detect_toolchain_channel_test() {
+    // the test environment will always have rustc available
+    assert!(detect_toolchain_channel().is_some());
+    assert_eq!(detect_toolchain_channel(), detect_toolchain_channel());
+}
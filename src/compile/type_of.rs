@@ -0,0 +1,104 @@
+use super::{build_compile_dir, compile, BuildConfig, CompilationError};
+use crate::code::{ModsMap, SourceCode, Statement, StmtGrp};
+use crate::linking::LinkingConfiguration;
+use std::path::Path;
+
+const TRAIT_NAME: &str = "__PapyrusTypeOf";
+
+/// Determine the inferred type of `expr` as if it was evaluated in the module `mod_path`, without
+/// executing it.
+///
+/// The expression is compiled with a bound on a trait that no type implements, and the type is
+/// parsed from the resulting compiler error. Unsuffixed literals take their default types, so
+/// `2 + 2` is an `i32`. Previous statements of the module are in scope, and the expression is not
+/// saved to `mods_map`.
+///
+/// The compilation directory is overwritten. If `expr` fails to compile, the compilation error is
+/// returned.
+pub fn type_of<P: AsRef<Path>>(
+    compile_dir: P,
+    mods_map: &ModsMap,
+    mod_path: &Path,
+    expr: &str,
+    linking_config: &LinkingConfiguration,
+    build_config: &BuildConfig,
+) -> Result<String, CompilationError> {
+    let compile_dir = compile_dir.as_ref();
+
+    let mut mods_map = mods_map.clone();
+    let src = mods_map
+        .entry(mod_path.to_path_buf())
+        .or_insert_with(SourceCode::new);
+    src.items.push((
+        format!(
+            "trait {0} {{}}\nfn __papyrus_type_of<T: {0}, F: FnOnce() -> T>(_: F) {{}}",
+            TRAIT_NAME
+        ),
+        false,
+    ));
+    src.stmts.push(StmtGrp(vec![Statement {
+        expr: format!("__papyrus_type_of(|| ({}))", expr),
+        semi: false,
+    }]));
+
+    build_compile_dir(compile_dir, &mods_map, linking_config, build_config)
+        .map_err(CompilationError::IOError)?;
+
+    let err = match compile(compile_dir, linking_config, build_config, |_| ()) {
+        Ok(_) => {
+            return Err(CompilationError::CompileError(format!(
+                "could not determine the type of `{}`",
+                expr
+            )))
+        }
+        Err(e) => e,
+    };
+
+    err.diagnostics()
+        .iter()
+        .find_map(|d| parse_type(&d.message))
+        .map(default_numeric_types)
+        .ok_or(err)
+}
+
+/// Parse the type out of the unsatisfied trait bound message.
+fn parse_type(message: &str) -> Option<&str> {
+    let suffix = format!(": {}` is not satisfied", TRAIT_NAME);
+    if let Some(ty) = message
+        .strip_prefix("the trait bound `")
+        .and_then(|s| s.strip_suffix(suffix.as_str()))
+    {
+        return Some(ty);
+    }
+
+    let prefix = format!("the trait `{}` is not implemented for `", TRAIT_NAME);
+    message
+        .strip_prefix(prefix.as_str())
+        .and_then(|s| s.strip_suffix('`'))
+}
+
+/// Unconstrained numeric literals are reported before the defaults are applied.
+fn default_numeric_types(ty: &str) -> String {
+    ty.replace("{integer}", "i32").replace("{float}", "f64")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_type_test() {
+        assert_eq!(
+            parse_type("the trait bound `&str: __PapyrusTypeOf` is not satisfied"),
+            Some("&str")
+        );
+        assert_eq!(
+            parse_type("the trait `__PapyrusTypeOf` is not implemented for `Vec<u8>`"),
+            Some("Vec<u8>")
+        );
+        assert_eq!(parse_type("mismatched types"), None);
+
+        assert_eq!(default_numeric_types("Vec<{integer}>"), "Vec<i32>");
+        assert_eq!(default_numeric_types("({float}, u8)"), "(f64, u8)");
+    }
+}
@@ -0,0 +1,118 @@
+use super::{build_compile_dir, compile, exec, BuildConfig, CompilationError};
+use crate::code::ModsMap;
+use crate::linking::LinkingConfiguration;
+use ::kserd::Kserd;
+use libloading::Library;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use std::{error, fmt, io};
+
+/// A named variant compiled by [`compile_variants`].
+#[derive(Debug)]
+pub struct Variant {
+    /// The name of the variant, which is also the name of the compiled library.
+    pub name: String,
+    /// The path to the compiled library.
+    pub lib_file: PathBuf,
+    /// How long the variant took to compile.
+    pub compile_time: Duration,
+    has_data: bool,
+    loadedlibs: Vec<Library>,
+}
+
+impl Variant {
+    /// Evaluate the root module of the variant, returning the output and how long evaluation
+    /// took.
+    ///
+    /// Variants linked with a data type cannot be run, as there is no data to pass through. The
+    /// loaded library is kept in memory until the variant is dropped.
+    pub fn run(&mut self) -> Result<(Kserd<'static>, Duration), RunError> {
+        if self.has_data {
+            return Err(RunError::HasData);
+        }
+
+        let mut fn_name = String::new();
+        crate::code::eval_fn_name(
+            &crate::code::into_mod_path_vec(Path::new("lib")),
+            &mut fn_name,
+        );
+
+        let start = Instant::now();
+        let (kserd, lib) =
+            exec(&self.lib_file, &fn_name, &()).map_err(|e| RunError::Eval(e.into_owned()))?;
+        let elapsed = start.elapsed();
+        self.loadedlibs.push(lib);
+
+        Ok((kserd, elapsed))
+    }
+}
+
+/// Error running a [`Variant`].
+#[derive(Debug, PartialEq)]
+pub enum RunError {
+    /// The variant is linked with a data type, so there is no data to pass through.
+    HasData,
+    /// The evaluation failed, such as by panicking.
+    Eval(String),
+}
+
+impl error::Error for RunError {}
+
+impl fmt::Display for RunError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunError::HasData => write!(f, "cannot run a variant which is linked with a data type"),
+            RunError::Eval(msg) => write!(f, "evaluation failed: {}", msg),
+        }
+    }
+}
+
+/// Compile each variant into its own library for comparison, such as benchmarking two versions
+/// of a snippet.
+///
+/// Each variant is built in `dir/<name>` and the library is named after the variant, so the names
+/// must be valid identifiers. The [`output_path`](BuildConfig::output_path) is not used, so the
+/// variants do not overwrite each other. The compilation time of each variant is recorded, and
+/// results are returned in the same order as `variants`.
+pub fn compile_variants<P: AsRef<Path>>(
+    variants: Vec<(String, ModsMap)>,
+    dir: P,
+    linking_config: &LinkingConfiguration,
+    build_config: &BuildConfig,
+) -> Vec<Result<Variant, CompilationError>> {
+    let dir = dir.as_ref();
+
+    variants
+        .into_iter()
+        .map(|(name, mods_map)| {
+            if syn::parse_str::<syn::Ident>(&name).is_err() {
+                return Err(CompilationError::IOError(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("`{}` is not a valid variant name", name),
+                )));
+            }
+
+            let compile_dir = dir.join(&name);
+            let build_config = BuildConfig {
+                lib_name: name.clone(),
+                output_path: None,
+                ..build_config.clone()
+            };
+
+            build_compile_dir(&compile_dir, &mods_map, linking_config, &build_config)
+                .map_err(CompilationError::IOError)?;
+
+            let start = Instant::now();
+            let lib_file = compile(&compile_dir, linking_config, &build_config, |_| ())?;
+            let compile_time = start.elapsed();
+
+            Ok(Variant {
+                name,
+                lib_file,
+                compile_time,
+                has_data: linking_config.data_type.is_some(),
+                loadedlibs: Vec::new(),
+            })
+        })
+        .collect()
+}
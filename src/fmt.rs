@@ -1,3 +1,5 @@
+use crate::compile::Edition;
+
 /// Code snippet formatting error.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum FormatError {
@@ -72,6 +74,57 @@ pub fn format(code_snippet: &str) -> Result<String, FormatError> {
     }
 }
 
+/// Format the source code of a whole file, such as the generated library source, as Rust of the
+/// `edition`.
+///
+/// Unlike [`format`](fn@format), the lines are kept. If `rustfmt` is not installed the source is
+/// returned unchanged, but source which `rustfmt` fails to format is an error.
+///
+/// # Example
+/// ```rust
+/// use papyrus::compile::Edition;
+///
+/// let src = "fn a( )->i32{1}";
+/// let fmtd = papyrus::fmt::format_source(src, Edition::E2018).unwrap();
+/// assert_eq!(&fmtd, "fn a() -> i32 {\n    1\n}\n");
+/// ```
+pub fn format_source(src: &str, edition: Edition) -> Result<String, FormatError> {
+    format_source_with("rustfmt", src, edition)
+}
+
+fn format_source_with(program: &str, src: &str, edition: Edition) -> Result<String, FormatError> {
+    use std::{io::Write, process::*};
+
+    let mut child = match Command::new(program)
+        .args(["--edition", edition.as_str()])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+    {
+        Ok(child) => child,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(src.to_string()),
+        Err(_) => return Err(FormatError::Io),
+    };
+
+    // write on a separate thread so a large source does not block on a full stdout pipe
+    let mut stdin = child.stdin.take().expect("stdin has been set");
+    let input = src.to_string();
+    let writer = std::thread::spawn(move || stdin.write_all(input.as_bytes()));
+
+    let output = child.wait_with_output().map_err(|_| FormatError::Io)?;
+    writer
+        .join()
+        .map_err(|_| FormatError::Io)?
+        .map_err(|_| FormatError::Io)?;
+
+    if output.status.success() {
+        String::from_utf8(output.stdout).map_err(|_| FormatError::StrConvertFailed)
+    } else {
+        Err(FormatError::RustfmtFailure)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -107,4 +160,39 @@ mod tests {
         let ans = s.as_ref().map(|x| x.as_str());
         assert_eq!(ans, Err(&FormatError::RustfmtFailure));
     }
+
+    #[test]
+    fn test_format_source() {
+        let src = "fn  a( )->i32{\n1}\nstruct   B ;\n  fn   c() {\nlet x = a( ) ;\n        x}";
+        assert_eq!(
+            format_source(src, Edition::E2018)
+                .as_ref()
+                .map(|x| x.as_str()),
+            Ok("fn a() -> i32 {\n    1\n}\nstruct B;\nfn c() {\n    let x = a();\n    x\n}\n")
+        );
+
+        assert_eq!(
+            format_source("fn a( {", Edition::E2018),
+            Err(FormatError::RustfmtFailure)
+        );
+
+        // async is only a keyword from the 2018 edition
+        let src_async = "async fn a( ) {}";
+        assert_eq!(
+            format_source(src_async, Edition::E2018)
+                .as_ref()
+                .map(|x| x.as_str()),
+            Ok("async fn a() {}\n")
+        );
+        assert_eq!(
+            format_source(src_async, Edition::E2015),
+            Err(FormatError::RustfmtFailure)
+        );
+
+        // missing rustfmt leaves the source unchanged
+        assert_eq!(
+            format_source_with("papyrus-missing-rustfmt", src, Edition::E2018).as_ref(),
+            Ok(&src.to_string())
+        );
+    }
 }
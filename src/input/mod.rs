@@ -7,6 +7,7 @@ mod tests;
 
 pub use self::parse::parse_command;
 pub use self::parse::parse_program;
+pub use self::parse::{parse_header, Header};
 
 /// Possible results from reading input from `InputReader`
 #[derive(Debug, PartialEq)]
@@ -25,6 +26,56 @@ pub enum InputResult {
     InputError(String),
 }
 
+/// The kind of a line of input.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum InputKind {
+    /// A command, prefixed with `:`.
+    Command,
+    /// Module-level items, such as `fn`, `struct`, or `extern crate`.
+    Item,
+    /// A statement, which ends with a semi-colon.
+    Statement,
+    /// An expression, which has its value printed.
+    Expression,
+    /// An empty line.
+    Empty,
+    /// Needs more input; i.e. there is an unclosed delimiter.
+    Incomplete,
+    /// The input could not be parsed.
+    Invalid,
+}
+
+/// Classify a line of input.
+///
+/// If the input contains items and statements, the kind of the last statement is used.
+///
+/// # Example
+/// ```rust
+/// use papyrus::input::{classify_input, InputKind};
+///
+/// assert_eq!(classify_input("fn f() {}"), InputKind::Item);
+/// assert_eq!(classify_input("let x = 1;"), InputKind::Statement);
+/// assert_eq!(classify_input("2 + 2"), InputKind::Expression);
+/// assert_eq!(classify_input(":help"), InputKind::Command);
+/// ```
+pub fn classify_input(line: &str) -> InputKind {
+    if line.trim().is_empty() {
+        InputKind::Empty
+    } else if is_command(line) {
+        InputKind::Command
+    } else {
+        match parse_program(line) {
+            InputResult::Program(input) => match input.stmts.last() {
+                Some(stmt) if stmt.semi => InputKind::Statement,
+                Some(_) => InputKind::Expression,
+                None => InputKind::Item,
+            },
+            InputResult::More => InputKind::Incomplete,
+            _ => InputKind::Invalid,
+        }
+    }
+}
+
 /// Parse `input` and `line` and determine what `InputResult`.
 pub fn determine_result(input: &str, line: &str, treat_as_cmd: bool) -> InputResult {
     if input.is_empty() {
@@ -33,6 +84,8 @@ pub fn determine_result(input: &str, line: &str, treat_as_cmd: bool) -> InputRes
 
     let res = if treat_as_cmd || is_command(line) {
         parse_command(line)
+    } else if !is_input_complete(input) {
+        InputResult::More
     } else {
         // check if the final statement ends with a semi
         match parse_program(input) {
@@ -50,6 +103,151 @@ pub fn determine_result(input: &str, line: &str, treat_as_cmd: bool) -> InputRes
     res
 }
 
+/// The indentation to start the line following `prev_line` with, when more input is required.
+///
+/// The leading whitespace of `prev_line` is kept, with an extra `indent` level added if
+/// `prev_line` leaves a delimiter open.
+///
+/// # Example
+/// ```rust
+/// use papyrus::input::next_line_indent;
+///
+/// assert_eq!(next_line_indent("fn f() {", "    "), "    ");
+/// assert_eq!(next_line_indent("    let a = 1;", "    "), "    ");
+/// assert_eq!(next_line_indent("    match a {", "    "), "        ");
+/// ```
+pub fn next_line_indent(prev_line: &str, indent: &str) -> String {
+    let trimmed = prev_line.trim_start();
+    let mut s = prev_line[..prev_line.len() - trimmed.len()].to_string();
+
+    let depth = trimmed.chars().fold(0isize, |depth, ch| match ch {
+        '{' | '(' | '[' => depth + 1,
+        '}' | ')' | ']' => depth - 1,
+        _ => depth,
+    });
+
+    if depth > 0 {
+        s.push_str(indent);
+    }
+
+    s
+}
+
+/// A lightweight check of whether `src` closes all of its delimiters, without parsing it.
+///
+/// Braces, parentheses, and brackets must balance, and string literals, character literals, and
+/// block comments must be terminated. Delimiters inside literals and comments are not counted.
+/// Input with more closing than opening delimiters is complete, as more input will not fix it.
+///
+/// # Example
+/// ```rust
+/// use papyrus::input::is_input_complete;
+///
+/// assert!(is_input_complete("fn foo() { 1 }"));
+/// assert!(!is_input_complete("fn foo() {"));
+/// assert!(is_input_complete(r#"let s = "{";"#));
+/// assert!(!is_input_complete("let s = \"abc"));
+/// assert!(is_input_complete("let a = 1; // {"));
+/// ```
+pub fn is_input_complete(src: &str) -> bool {
+    let mut chars = src.chars().peekable();
+    let mut depth = 0isize;
+
+    while let Some(ch) = chars.next() {
+        match ch {
+            '{' | '(' | '[' => depth += 1,
+            '}' | ')' | ']' => depth -= 1,
+            '/' if chars.peek() == Some(&'/') => {
+                chars.by_ref().find(|&c| c == '\n');
+            }
+            '/' if chars.peek() == Some(&'*') => {
+                chars.next();
+                let mut nesting = 1;
+                while nesting > 0 {
+                    match chars.next() {
+                        Some('*') if chars.peek() == Some(&'/') => {
+                            chars.next();
+                            nesting -= 1;
+                        }
+                        Some('/') if chars.peek() == Some(&'*') => {
+                            chars.next();
+                            nesting += 1;
+                        }
+                        Some(_) => (),
+                        None => return false,
+                    }
+                }
+            }
+            '"' if !skip_string(&mut chars) => return false,
+            'r' if matches!(chars.peek(), Some('#') | Some('"')) => {
+                let mut hashes = 0;
+                while chars.next_if_eq(&'#').is_some() {
+                    hashes += 1;
+                }
+                // an identifier, such as `r#type`
+                if chars.next_if_eq(&'"').is_none() {
+                    continue;
+                }
+                if !skip_raw_string(&mut chars, hashes) {
+                    return false;
+                }
+            }
+            '\'' => skip_char_literal(&mut chars),
+            _ => (),
+        }
+    }
+
+    depth <= 0
+}
+
+/// Skips the rest of a string literal, returning if it was terminated.
+fn skip_string(chars: &mut std::iter::Peekable<std::str::Chars>) -> bool {
+    while let Some(ch) = chars.next() {
+        match ch {
+            '\\' => {
+                chars.next();
+            }
+            '"' => return true,
+            _ => (),
+        }
+    }
+    false
+}
+
+/// Skips the rest of a raw string literal with `hashes` number of `#`, returning if it was
+/// terminated.
+fn skip_raw_string(chars: &mut std::iter::Peekable<std::str::Chars>, hashes: usize) -> bool {
+    while let Some(ch) = chars.next() {
+        if ch == '"' {
+            let mut closing = 0;
+            while closing < hashes && chars.next_if_eq(&'#').is_some() {
+                closing += 1;
+            }
+            if closing == hashes {
+                return true;
+            }
+        }
+    }
+    false
+}
+
+/// Skips a character literal, a lifetime (`'a`) is left as is.
+fn skip_char_literal(chars: &mut std::iter::Peekable<std::str::Chars>) {
+    let mut lookahead = chars.clone();
+    match lookahead.next() {
+        Some('\\') => {
+            chars.next();
+            chars.next();
+            chars.by_ref().find(|&c| c == '\'');
+        }
+        Some(_) if lookahead.next() == Some('\'') => {
+            chars.next();
+            chars.next();
+        }
+        _ => (),
+    }
+}
+
 fn is_command(line: &str) -> bool {
     line.starts_with(crate::CMD_PREFIX)
 }
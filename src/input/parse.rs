@@ -17,9 +17,284 @@ pub fn parse_command(line: &str) -> InputResult {
 }
 
 /// Parses a line of input as a program.
+///
+/// The input can start with the same directives as a [script header](parse_header), in any
+/// order. `//! papyrus: crate NAME = "VERSION"` directives pin the version of the crate, adding
+/// it if it is not declared. `//! papyrus: feature "crate_name/feature"` directives enable the
+/// feature on the referenced crate, which must be declared with `extern crate` or a `crate`
+/// directive in the same input. A `//! papyrus: release` directive sets [`Input::release`].
 pub fn parse_program(code: &str) -> InputResult {
     debug!("parse program: {}", code);
 
+    let (directives, code) = match take_directives(code) {
+        Ok(x) => x,
+        Err(e) => return InputResult::InputError(e),
+    };
+    let Directives {
+        crates: versions,
+        features,
+        release,
+    } = directives;
+
+    match parse_program_with_semi_insertion(code.trim_start()) {
+        InputResult::Program(mut input) => {
+            for krate in versions {
+                match input
+                    .crates
+                    .iter_mut()
+                    .find(|c| c.cargo_name == krate.cargo_name)
+                {
+                    Some(c) => {
+                        c.version = krate.version;
+                        c.source = krate.source;
+                    }
+                    None => input.crates.push(krate),
+                }
+            }
+            if let Err(e) = add_features(&mut input.crates, features, "input") {
+                return InputResult::InputError(e);
+            }
+            input.release = release;
+            InputResult::Program(input)
+        }
+        x => x,
+    }
+}
+
+/// The crates and options declared in a script header, see [`parse_header`].
+#[derive(Debug, PartialEq, Clone, Default)]
+pub struct Header {
+    /// The declared crates, with their versions.
+    pub crates: Vec<CrateType>,
+    /// Compile with optimisations.
+    pub release: bool,
+}
+
+/// Parses a leading block of header directives, returning the header and the remaining code.
+///
+/// This allows a self-contained snippet to declare what it needs. A header is made of contiguous
+/// `//! papyrus:` lines at the start of the code, in any order, which can be:
+/// - `crate NAME = "VERSION"`: depend on the crate, with the version requirement,
+/// - `crate NAME = { path = "PATH" }` or `crate NAME = { git = "URL", rev = "REV" }`: depend on a
+///   local or git crate, see [`CrateSource`](crate::code::CrateSource). A `version` can also be
+///   given,
+/// - `feature "crate_name/feature"`: enable the feature on a crate declared in the header,
+/// - `release`: compile with optimisations.
+///
+/// The header stops at the first line which is not a `//! papyrus:` directive. An unknown
+/// directive is an error.
+///
+/// # Example
+/// ```rust
+/// use papyrus::input::parse_header;
+///
+/// let (header, code) = parse_header("//! papyrus: crate rand = \"0.7\"\nrand::random::<u8>()").unwrap();
+/// assert_eq!(header.crates[0].cargo_name, "rand");
+/// assert_eq!(header.crates[0].version.as_deref(), Some("0.7"));
+/// assert_eq!(code, "rand::random::<u8>()");
+/// ```
+pub fn parse_header(code: &str) -> Result<(Header, &str), String> {
+    let (directives, code) = take_directives(code)?;
+    let Directives {
+        mut crates,
+        features,
+        release,
+    } = directives;
+
+    add_features(&mut crates, features, "header")?;
+
+    Ok((Header { crates, release }, code))
+}
+
+/// Parses `NAME = "VERSION"`, or `NAME = { KEY = "VALUE", ... }` with `path`, `git`, `rev`, and
+/// `version` keys.
+fn parse_crate_directive(krate: &str) -> Result<CrateType, String> {
+    let err = || {
+        format!(
+            "crate directive expects NAME = \"VERSION\", found: {}",
+            krate.trim()
+        )
+    };
+
+    let mut split = krate.splitn(2, '=');
+    let (name, version) = match (split.next(), split.next()) {
+        (Some(name), Some(version)) => (name.trim(), version.trim().trim_matches('"')),
+        _ => return Err(err()),
+    };
+    if name.is_empty() || version.is_empty() || name.contains(' ') {
+        return Err(err());
+    }
+
+    let mut c = CrateType::parse_str(&format!("extern crate {};", name.replace("-", "_")))
+        .map_err(|_| err())?;
+
+    match version.strip_prefix('{').and_then(|s| s.strip_suffix('}')) {
+        Some(table) => {
+            let (version, source) = parse_crate_source(table)
+                .map_err(|e| format!("invalid crate directive for `{}`: {}", name, e))?;
+            c.version = version;
+            c.source = source;
+        }
+        None => c.version = Some(version.to_string()),
+    }
+
+    Ok(c)
+}
+
+/// Parses the inside of a `{ path = "PATH" }` or `{ git = "URL", rev = "REV" }` table, returning
+/// the version requirement, if any, and the source.
+fn parse_crate_source(table: &str) -> Result<(Option<String>, code::CrateSource), String> {
+    let mut version = None;
+    let mut path = None;
+    let mut git = None;
+    let mut rev = None;
+
+    for field in table.split(',').map(str::trim).filter(|s| !s.is_empty()) {
+        let mut split = field.splitn(2, '=');
+        let (key, value) = match (split.next(), split.next()) {
+            (Some(key), Some(value)) => (key.trim(), value.trim()),
+            _ => return Err(format!("expecting KEY = \"VALUE\", found: {}", field)),
+        };
+        let value = value
+            .strip_prefix('"')
+            .and_then(|s| s.strip_suffix('"'))
+            .ok_or_else(|| format!("expecting a quoted value for `{}`", key))?
+            .to_string();
+
+        match key {
+            "version" => version = Some(value),
+            "path" => path = Some(value),
+            "git" => git = Some(value),
+            "rev" => rev = Some(value),
+            _ => return Err(format!("unknown key `{}`", key)),
+        }
+    }
+
+    let source = match (path, git) {
+        (Some(_), Some(_)) => return Err("expecting one of `path` or `git`".to_string()),
+        (Some(path), None) if rev.is_none() => {
+            // relative to where papyrus is run, not the compilation directory
+            let path = std::env::current_dir()
+                .map(|dir| dir.join(&path))
+                .unwrap_or_else(|_| path.into());
+            code::CrateSource::Path(path.canonicalize().unwrap_or(path))
+        }
+        (None, Some(url)) => code::CrateSource::Git { url, rev },
+        (Some(_), None) => return Err("`rev` requires a `git` url".to_string()),
+        (None, None) if rev.is_some() => return Err("`rev` requires a `git` url".to_string()),
+        (None, None) => code::CrateSource::Registry,
+    };
+
+    Ok((version, source))
+}
+
+/// A `(crate, feature)` pair.
+type CrateFeature = (String, String);
+
+/// Parses the program, inserting a trailing semi-colon if the input is a `let` statement that is
+/// missing one. Trailing expressions and items are not altered.
+fn parse_program_with_semi_insertion(code: &str) -> InputResult {
+    match parse_program_inner(code) {
+        InputResult::InputError(e) => {
+            let code = format!("{};", code.trim_end());
+            match parse_program_inner(&code) {
+                InputResult::Program(input)
+                    if input
+                        .stmts
+                        .last()
+                        .map(|s| s.semi && s.expr.starts_with("let "))
+                        .unwrap_or(false) =>
+                {
+                    InputResult::Program(input)
+                }
+                _ => InputResult::InputError(e),
+            }
+        }
+        x => x,
+    }
+}
+
+/// The leading `//! papyrus:` directives of some code.
+#[derive(Default)]
+struct Directives {
+    crates: Vec<CrateType>,
+    features: Vec<CrateFeature>,
+    release: bool,
+}
+
+/// Splits off the leading directive lines, in any order, returning the directives and the
+/// remaining code. Whitespace before a directive is skipped.
+fn take_directives(code: &str) -> Result<(Directives, &str), String> {
+    const PREFIX: &str = "//! papyrus:";
+
+    let mut directives = Directives::default();
+    let mut code = code;
+
+    while let Some(directive) = code.trim_start().strip_prefix(PREFIX) {
+        let (line, rem) = match directive.find('\n') {
+            Some(idx) => (&directive[..idx], &directive[idx + 1..]),
+            None => (directive, ""),
+        };
+
+        let directive = line.trim();
+        if directive == "release" {
+            directives.release = true;
+        } else if let Some(krate) = directive.strip_prefix("crate ") {
+            directives.crates.push(parse_crate_directive(krate)?);
+        } else if let Some(feature) = directive.strip_prefix("feature ") {
+            directives.features.push(parse_feature_directive(feature)?);
+        } else {
+            return Err(format!("unknown papyrus directive: {}", directive));
+        }
+
+        code = rem;
+    }
+
+    Ok((directives, code))
+}
+
+/// Parses `"crate_name/feature"`.
+fn parse_feature_directive(feature: &str) -> Result<CrateFeature, String> {
+    let feature = feature.trim().trim_matches('"');
+
+    let mut split = feature.splitn(2, '/');
+    match (split.next(), split.next()) {
+        (Some(krate), Some(feature)) if !krate.is_empty() && !feature.is_empty() => {
+            Ok((krate.to_string(), feature.to_string()))
+        }
+        _ => Err(format!(
+            "feature directive expects \"crate_name/feature\", found: {}",
+            feature
+        )),
+    }
+}
+
+/// Enables each feature on its crate, which must be one of `crates`. `within` names where the
+/// crates were declared, for the error message.
+fn add_features(
+    crates: &mut [CrateType],
+    features: Vec<CrateFeature>,
+    within: &str,
+) -> Result<(), String> {
+    for (krate, feature) in features {
+        match crates
+            .iter_mut()
+            .find(|c| c.cargo_name == krate.replace("_", "-"))
+        {
+            Some(c) => c.features.push(feature),
+            None => {
+                return Err(format!(
+                    "feature directive references crate `{}` which is not in the {}",
+                    krate, within
+                ))
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn parse_program_inner(code: &str) -> InputResult {
     let reterr = |e: syn::Error| {
         let e = e.to_string();
         if &e == "LexError" {
@@ -44,6 +319,7 @@ pub fn parse_program(code: &str) -> InputResult {
                     items: items,
                     stmts: vec![],
                     crates: vec![],
+                    release: false,
                 })
             })
             .unwrap_or_else(reterr);
@@ -100,6 +376,7 @@ pub fn parse_program(code: &str) -> InputResult {
                 items: items,
                 stmts: stmts,
                 crates: crates,
+                release: false,
             })
         })
         .unwrap_or_else(reterr)
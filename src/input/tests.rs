@@ -20,7 +20,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![("fn b() {}".to_string(), false)],
             stmts: vec![],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     ); // Item::Fn
     assert_eq!(
@@ -28,7 +29,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![("#[derive(Debug)] struct A { u: u32, }".to_string(), false)],
             stmts: vec![],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     ); // Item::Struct
     assert_eq!(
@@ -36,7 +38,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![],
             stmts: vec![],
-            crates: vec![CrateType::parse_str(&"extern crate rand as r;").unwrap()]
+            crates: vec![CrateType::parse_str(&"extern crate rand as r;").unwrap()],
+            release: false
         })
     ); // Item::ExternCrate
     assert_eq!(
@@ -44,7 +47,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![("impl Eq for MyStruct {}".to_string(), false)],
             stmts: vec![],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     ); // Item::Impl
     assert_eq!(
@@ -52,7 +56,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![("use something::a::crate;".to_string(), false)],
             stmts: vec![],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     ); // Item::Use
     assert_eq!(
@@ -60,7 +65,8 @@ fn test_items() {
         InputResult::Program(Input {
             items: vec![("#![feature(test)]".to_string(), true)],
             stmts: vec![],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     ); // Item::Use
 }
@@ -77,7 +83,8 @@ fn test_exprs() {
                 expr: "2 + 2".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -88,7 +95,8 @@ fn test_exprs() {
                 expr: "2 + 2".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::Macro
@@ -100,7 +108,8 @@ fn test_exprs() {
                 expr: "println!(\"hello\")".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -111,7 +120,8 @@ fn test_exprs() {
                 expr: "println!(\"hello\")".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::Tuple
@@ -123,7 +133,8 @@ fn test_exprs() {
                 expr: "()".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -134,7 +145,8 @@ fn test_exprs() {
                 expr: "()".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::Call
@@ -146,7 +158,8 @@ fn test_exprs() {
                 expr: "f()".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -157,7 +170,8 @@ fn test_exprs() {
                 expr: "f()".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // LET
@@ -169,7 +183,8 @@ fn test_exprs() {
                 expr: "let a = 1".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::ForLoop
@@ -181,7 +196,8 @@ fn test_exprs() {
                 expr: "for i in 0..3 {}".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::Path
@@ -193,7 +209,8 @@ fn test_exprs() {
                 expr: "b".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -204,7 +221,8 @@ fn test_exprs() {
                 expr: "b".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     // Expr::MethodCall
@@ -216,7 +234,8 @@ fn test_exprs() {
                 expr: "std::env::current_dir()".to_string(),
                 semi: false
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
     assert_eq!(
@@ -227,7 +246,8 @@ fn test_exprs() {
                 expr: "std::env::current_dir()".to_string(),
                 semi: true
             }],
-            crates: vec![]
+            crates: vec![],
+            release: false
         })
     );
 }
@@ -255,7 +275,8 @@ fn test_determine_result() {
                 expr: "2 + 2".to_string(),
                 semi: false,
             }],
-            crates: Vec::new()
+            crates: Vec::new(),
+            release: false
         })
     );
     assert_eq!(
@@ -271,8 +292,280 @@ fn fail_parse_program() {
         parse_program("extern crate "),
         InputResult::InputError("unexpected end of input, expected identifier".to_string())
     );
+    // inserting a semi-colon does not fix other errors
     assert_eq!(
-        parse_program("let a = 1"),
+        parse_program("let a = 1 2"),
         InputResult::InputError("expected `;`".to_string())
     );
 }
+
+#[test]
+fn test_feature_directives() {
+    let r = parse_program("//! papyrus: feature \"rand/std\"\n//! papyrus: feature \"rand/alloc\"\nextern crate rand;");
+    match r {
+        InputResult::Program(input) => {
+            assert_eq!(input.crates.len(), 1);
+            assert_eq!(
+                input.crates[0].features,
+                vec!["std".to_string(), "alloc".to_string()]
+            );
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    assert_eq!(
+        parse_program("//! papyrus: feature \"rand/std\"\n2+2"),
+        InputResult::InputError(
+            "feature directive references crate `rand` which is not in the input".to_string()
+        )
+    );
+    assert_eq!(
+        parse_program("//! papyrus: feature \"rand\"\nextern crate rand;"),
+        InputResult::InputError(
+            "feature directive expects \"crate_name/feature\", found: rand".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_crate_version_directives() {
+    let r = parse_program(
+        "//! papyrus: crate rand = \"0.7\"\n//! papyrus: feature \"rand/std\"\nextern crate rand;",
+    );
+    match r {
+        InputResult::Program(input) => {
+            assert_eq!(input.crates.len(), 1);
+            assert_eq!(input.crates[0].version.as_deref(), Some("0.7"));
+            assert_eq!(input.crates[0].features, vec!["std".to_string()]);
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    // an undeclared crate is added
+    match parse_program("//! papyrus: crate serde_json = \"1\"\n2+2") {
+        InputResult::Program(input) => {
+            assert_eq!(input.crates.len(), 1);
+            assert_eq!(input.crates[0].src_line, "extern crate serde_json;");
+            assert_eq!(input.crates[0].cargo_name, "serde-json");
+            assert_eq!(input.crates[0].version.as_deref(), Some("1"));
+            assert_eq!(input.stmts.len(), 1);
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    // without a version the crate is unpinned
+    match parse_program("extern crate rand;") {
+        InputResult::Program(input) => assert_eq!(input.crates[0].version, None),
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    assert_eq!(
+        parse_program("//! papyrus: crate rand\nextern crate rand;"),
+        InputResult::InputError(
+            "crate directive expects NAME = \"VERSION\", found: rand".to_string()
+        )
+    );
+}
+
+#[test]
+fn test_crate_source_directives() {
+    let r = parse_program(
+        "//! papyrus: crate rand = { git = \"https://github.com/rust-random/rand\", rev = \"abc\" }\nextern crate rand;",
+    );
+    match r {
+        InputResult::Program(input) => {
+            assert_eq!(input.crates.len(), 1);
+            assert_eq!(input.crates[0].version, None);
+            assert_eq!(
+                input.crates[0].source,
+                CrateSource::Git {
+                    url: "https://github.com/rust-random/rand".to_string(),
+                    rev: Some("abc".to_string()),
+                }
+            );
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    // relative paths are against the current directory
+    let (header, _) = parse_header(
+        "//! papyrus: crate a = { path = \"test-resources/external_crate\", version = \"0.1\" }",
+    )
+    .unwrap();
+    let expected = std::env::current_dir()
+        .unwrap()
+        .join("test-resources/external_crate")
+        .canonicalize()
+        .unwrap();
+    assert_eq!(header.crates[0].source, CrateSource::Path(expected));
+    assert_eq!(header.crates[0].version.as_deref(), Some("0.1"));
+
+    assert_eq!(
+        parse_header("//! papyrus: crate a = { path = \"a\", git = \"b\" }"),
+        Err("invalid crate directive for `a`: expecting one of `path` or `git`".to_string())
+    );
+    assert_eq!(
+        parse_header("//! papyrus: crate a = { branch = \"b\" }"),
+        Err("invalid crate directive for `a`: unknown key `branch`".to_string())
+    );
+    assert_eq!(
+        parse_header("//! papyrus: crate a = { rev = \"b\" }"),
+        Err("invalid crate directive for `a`: `rev` requires a `git` url".to_string())
+    );
+}
+
+#[test]
+fn test_parse_header() {
+    let code = r#"//! papyrus: crate rand = "0.7"
+//! papyrus: crate serde_json = "1.0.40"
+//! papyrus: release
+//! papyrus: feature "rand/std"
+extern crate rand;"#;
+
+    let (header, rem) = parse_header(code).unwrap();
+    assert_eq!(header.crates.len(), 2);
+    assert_eq!(header.crates[0].cargo_name, "rand");
+    assert_eq!(header.crates[0].src_line, "extern crate rand;");
+    assert_eq!(header.crates[0].version.as_deref(), Some("0.7"));
+    assert_eq!(header.crates[1].cargo_name, "serde-json");
+    assert_eq!(header.crates[1].src_line, "extern crate serde_json;");
+    assert_eq!(header.crates[0].features, vec!["std".to_string()]);
+    assert_eq!(header.crates[1].version.as_deref(), Some("1.0.40"));
+    assert!(header.release);
+    assert_eq!(rem, "extern crate rand;");
+
+    let (header, rem) = parse_header("2+2").unwrap();
+    assert_eq!(header, Header::default());
+    assert_eq!(rem, "2+2");
+
+    assert_eq!(
+        parse_header("//! papyrus: crate rand"),
+        Err("crate directive expects NAME = \"VERSION\", found: rand".to_string())
+    );
+    assert_eq!(
+        parse_header("//! papyrus: feature \"rand/std\"\nextern crate rand;"),
+        Err("feature directive references crate `rand` which is not in the header".to_string())
+    );
+    assert_eq!(
+        parse_header("//! papyrus: optimise"),
+        Err("unknown papyrus directive: optimise".to_string())
+    );
+}
+
+#[test]
+fn test_program_directives_any_order() {
+    let code = "//! papyrus: feature \"rand/std\"\n//! papyrus: release\n//! papyrus: crate rand = \"0.7\"\n2+2";
+    match parse_program(code) {
+        InputResult::Program(input) => {
+            assert!(input.release);
+            assert_eq!(input.crates.len(), 1);
+            assert_eq!(input.crates[0].version.as_deref(), Some("0.7"));
+            assert_eq!(input.crates[0].features, vec!["std".to_string()]);
+            assert_eq!(input.stmts.len(), 1);
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    match parse_program("2+2") {
+        InputResult::Program(input) => assert!(!input.release),
+        x => panic!("expecting program, got {:?}", x),
+    }
+}
+
+#[test]
+fn test_next_line_indent() {
+    assert_eq!(next_line_indent("", "    "), "");
+    assert_eq!(next_line_indent("fn f() {", "    "), "    ");
+    assert_eq!(next_line_indent("fn f() {", "\t"), "\t");
+    assert_eq!(next_line_indent("    let a = (", "    "), "        ");
+    assert_eq!(next_line_indent("    let a = 1;", "    "), "    ");
+    assert_eq!(next_line_indent("    }", "    "), "    ");
+    assert_eq!(next_line_indent("  f(a, b)", "    "), "  ");
+}
+
+#[test]
+fn test_classify_input() {
+    assert_eq!(classify_input("fn f() {}"), InputKind::Item);
+    assert_eq!(classify_input("struct A;"), InputKind::Item);
+    assert_eq!(classify_input("extern crate rand;"), InputKind::Item);
+    assert_eq!(classify_input("let x = 1;"), InputKind::Statement);
+    assert_eq!(classify_input("println!(\"hi\");"), InputKind::Statement);
+    assert_eq!(classify_input("2 + 2"), InputKind::Expression);
+    assert_eq!(classify_input("fn f() {} f()"), InputKind::Expression);
+    assert_eq!(classify_input(":help"), InputKind::Command);
+    assert_eq!(classify_input("  "), InputKind::Empty);
+    assert_eq!(classify_input("fn f() {"), InputKind::Incomplete);
+}
+
+#[test]
+fn test_semi_insertion() {
+    let stmts = |code| match parse_program(code) {
+        InputResult::Program(input) => input.stmts,
+        x => panic!("expecting program, got {:?}", x),
+    };
+
+    let s = stmts("let x = 1");
+    assert_eq!(s.len(), 1);
+    assert_eq!(&s[0].expr, "let x = 1");
+    assert!(s[0].semi);
+
+    let s = stmts("let x = 1; let y = x");
+    assert_eq!(s.len(), 2);
+    assert!(s[1].semi);
+
+    let s = stmts("2 + 2");
+    assert!(!s[0].semi);
+
+    match parse_program("fn f(){}") {
+        InputResult::Program(input) => {
+            assert!(input.stmts.is_empty());
+            assert_eq!(input.items.len(), 1);
+        }
+        x => panic!("expecting program, got {:?}", x),
+    }
+
+    assert_eq!(classify_input("let x = 1"), InputKind::Statement);
+    // still an error if the semi-colon does not fix it
+    match parse_program("let x = ") {
+        InputResult::InputError(_) | InputResult::More => (),
+        x => panic!("expecting error, got {:?}", x),
+    }
+}
+
+#[test]
+fn is_input_complete_test() {
+    assert!(is_input_complete(""));
+    assert!(is_input_complete("fn foo() { (1, [2]) }"));
+    assert!(!is_input_complete("fn foo() {"));
+    assert!(!is_input_complete("let a = ("));
+    assert!(!is_input_complete("let a = vec!["));
+    // unbalanced the other way is left to the parser
+    assert!(is_input_complete("}"));
+
+    // strings
+    assert!(is_input_complete(r#"let s = "{";"#));
+    assert!(is_input_complete(r#"let s = "\"{";"#));
+    assert!(!is_input_complete(r#"let s = "abc"#));
+    assert!(!is_input_complete("let s = \"abc\n"));
+    assert!(is_input_complete(r###"let s = r#"{"}"#;"###));
+    assert!(!is_input_complete(r###"let s = r#"{"  "###));
+    assert!(is_input_complete("let r#type = 1;"));
+
+    // chars and lifetimes
+    assert!(is_input_complete("let c = '{';"));
+    assert!(is_input_complete(r"let c = '\'';"));
+    assert!(is_input_complete(r"let c = '\u{7b}';"));
+    assert!(!is_input_complete("fn f<'a>(x: &'a str) {"));
+    assert!(is_input_complete("fn f<'a>(x: &'a str) {}"));
+
+    // comments
+    assert!(is_input_complete("let a = 1; // {"));
+    assert!(!is_input_complete("// }\nfn f() {"));
+    assert!(is_input_complete("/* { /* } */ */"));
+    assert!(!is_input_complete("/* {"));
+
+    assert_eq!(
+        determine_result("let s = \"{", "let s = \"{", false),
+        InputResult::More
+    );
+}
@@ -3,7 +3,7 @@
 //! [![Rust Documentation](https://img.shields.io/badge/api-rustdoc-blue.svg)](https://docs.rs/papyrus)
 //! [![codecov](https://codecov.io/gh/kurtlawrence/papyrus/branch/master/graph/badge.svg)](https://codecov.io/gh/kurtlawrence/papyrus)
 //! [![Rustc Version
-//! 1.36+](https://img.shields.io/badge/rustc-1.36+-blue.svg)](https://blog.rust-lang.org/2019/07/04/Rust-1.36.0.html)
+//! 1.64+](https://img.shields.io/badge/rustc-1.64+-blue.svg)](https://blog.rust-lang.org/2022/09/22/Rust-1.64.0.html)
 //!
 //! A rust REPL and script running tool.
 //!
@@ -25,7 +25,7 @@ extern crate log;
 
 #[cfg(feature = "runnable")]
 #[macro_use]
-extern crate crossterm;
+pub extern crate crossterm;
 
 /// Build a repl instance with the default terminal.
 /// If a type is specfied (ie `repl!(String)`) then the repl will be bounded to use
@@ -193,6 +193,21 @@
 //! it will still be using the consistent `kserd` dependency, although an astute user might try to
 //! implement `::kserd::ToKserd` which would break! At least at this point it is easy to back out
 //! changes in the temporary REPL session.
+//!
+//! ## Exposing Dependencies
+//! An alternative to re-exporting dependencies from the library is to expose them directly from the
+//! library's build artifacts, using [`Extern::expose_dependency`]. The dependency's `rlib` is found
+//! in the `deps` folder and passed to the compiler with an `--extern` flag, such that the REPL code
+//! can use the crate (eg `rand::random()`) without declaring it as a REPL dependency.
+//!
+//! There are some caveats with this approach:
+//! - The `rlib` must have been built by the _same compiler version_ that compiles the REPL code,
+//!   otherwise compilation fails with an incompatible crate error.
+//! - The version and feature set are that of the library's build, and cannot be altered by the
+//!   REPL. Rebuilding the library can leave more than one `rlib` for a dependency in the `deps`
+//!   folder, in which case exposing the dependency fails as the correct one cannot be chosen.
+//! - The REPL should not also declare the dependency (with `extern crate`), as this creates the
+//!   dependency duplication described above.
 
 use std::collections::HashSet;
 use std::path::{Path, PathBuf};
@@ -229,6 +244,17 @@ pub struct LinkingConfiguration {
     /// It is sometimes necessary to have injected code, especially to solve dependency duplication
     /// issues. See [`the _linking_ module for a description`](crate::linking).
     pub persistent_module_code: String,
+
+    /// The async runtime to drive the evaluation function with.
+    ///
+    /// If set, the statements are wrapped in an `async` block which is run to completion on the
+    /// runtime, allowing snippets to use `.await`. The runtime crate is added as a dependency.
+    pub runtime: Option<AsyncRuntime>,
+
+    /// The `use` statements injected at the top of each module, after the persistent module code.
+    ///
+    /// Defaults to `None`, which injects nothing.
+    pub prelude: Option<PreludeConfig>,
 }
 
 impl Default for LinkingConfiguration {
@@ -238,11 +264,32 @@ impl Default for LinkingConfiguration {
             mutable: false,
             external_libs: HashSet::new(),
             persistent_module_code: String::new(),
+            runtime: None,
+            prelude: None,
         }
     }
 }
 
 impl LinkingConfiguration {
+    /// A builder for the configuration, starting from the default.
+    ///
+    /// # Example
+    /// ```rust,no_run
+    /// # use papyrus::linking::LinkingConfiguration;
+    /// let config = LinkingConfiguration::builder()
+    ///     .rlib("target/debug/libmy_crate.rlib")
+    ///     .rlib_with_alias("target/debug/libother_crate.rlib", "other")
+    ///     .build()
+    ///     .unwrap();
+    /// assert_eq!(config.external_libs.len(), 2);
+    /// ```
+    pub fn builder() -> LinkingConfigurationBuilder {
+        LinkingConfigurationBuilder {
+            config: LinkingConfiguration::default(),
+            err: None,
+        }
+    }
+
     /// Set the data type. Must be fully qualified from the crate level.
     ///
     /// ## Unsafety
@@ -275,6 +322,187 @@ impl LinkingConfiguration {
     }
 }
 
+/// Builds a [`LinkingConfiguration`], see [`LinkingConfiguration::builder`].
+///
+/// Adding an `rlib` validates the path, the first error is returned from
+/// [`build`](LinkingConfigurationBuilder::build).
+pub struct LinkingConfigurationBuilder {
+    config: LinkingConfiguration,
+    err: Option<io::Error>,
+}
+
+impl LinkingConfigurationBuilder {
+    /// Link the external library.
+    pub fn external(mut self, lib: Extern) -> Self {
+        self.config.external_libs.insert(lib);
+        self
+    }
+
+    /// Link the `lib*.rlib` library at the path, see [`Extern::new`].
+    pub fn rlib<P: AsRef<Path>>(self, rlib_path: P) -> Self {
+        self.try_external(Extern::new(rlib_path))
+    }
+
+    /// Link the `lib*.rlib` library at the path with an alias for the crate name, see
+    /// [`Extern::with_alias`].
+    pub fn rlib_with_alias<P: AsRef<Path>>(self, rlib_path: P, alias: &'static str) -> Self {
+        self.try_external(Extern::with_alias(rlib_path, alias))
+    }
+
+    /// Set the data type, see [`LinkingConfiguration::with_data`].
+    ///
+    /// # Safety
+    /// This **must** match the type that is passed through.
+    pub unsafe fn data_type(mut self, type_name: &str) -> Self {
+        self.config = self.config.with_data(type_name);
+        self
+    }
+
+    /// Pass the data through as a mutable reference.
+    pub fn mutable(mut self, mutable: bool) -> Self {
+        self.config.mutable = mutable;
+        self
+    }
+
+    /// Set the [`persistent_module_code`](LinkingConfiguration::persistent_module_code).
+    pub fn persistent_module_code<S: Into<String>>(mut self, code: S) -> Self {
+        self.config.persistent_module_code = code.into();
+        self
+    }
+
+    /// Set the async [`runtime`](LinkingConfiguration::runtime).
+    pub fn runtime(mut self, runtime: AsyncRuntime) -> Self {
+        self.config.runtime = Some(runtime);
+        self
+    }
+
+    /// Set the [`prelude`](LinkingConfiguration::prelude).
+    pub fn prelude(mut self, prelude: PreludeConfig) -> Self {
+        self.config.prelude = Some(prelude);
+        self
+    }
+
+    /// The configuration, or the first error adding a library.
+    pub fn build(self) -> io::Result<LinkingConfiguration> {
+        match self.err {
+            Some(e) => Err(e),
+            None => Ok(self.config),
+        }
+    }
+
+    fn try_external(mut self, lib: io::Result<Extern>) -> Self {
+        match lib {
+            Ok(lib) => self.external(lib),
+            Err(e) => {
+                self.err.get_or_insert(e);
+                self
+            }
+        }
+    }
+}
+
+/// The items brought into scope in each module with `use` statements.
+///
+/// The default prelude is `std::collections::*` and `std::io::Write`.
+///
+/// # Example
+/// ```rust
+/// # use papyrus::linking::PreludeConfig;
+/// let prelude = PreludeConfig::new(vec!["std::fmt::Write", "std::rc::{Rc, Weak}"]).unwrap();
+/// assert_eq!(prelude.uses(), &["std::fmt::Write", "std::rc::{Rc, Weak}"]);
+///
+/// assert!(PreludeConfig::new(vec!["std::fmt::"]).is_err());
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PreludeConfig {
+    uses: Vec<String>,
+}
+
+impl PreludeConfig {
+    /// A prelude of the use paths, in order. Each path is what follows the `use` keyword, ie
+    /// `std::io::Write`.
+    ///
+    /// Returns an error if a path is not a valid use path.
+    pub fn new<I, S>(uses: I) -> Result<Self, String>
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        let uses = uses
+            .into_iter()
+            .map(|path| {
+                let path = path.into().trim().to_string();
+                syn::parse_str::<syn::UseTree>(&path)
+                    .map(|_| path.clone())
+                    .map_err(|_| format!("`{}` is not a valid use path", path))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(PreludeConfig { uses })
+    }
+
+    /// The use paths, in order.
+    pub fn uses(&self) -> &[String] {
+        &self.uses
+    }
+
+    /// Write the `use` statements to the buffer, each on its own line.
+    pub(crate) fn construct_uses(&self, buf: &mut String) {
+        for path in &self.uses {
+            buf.push_str("use ");
+            buf.push_str(path);
+            buf.push_str(";\n");
+        }
+    }
+
+    /// The length of [`construct_uses`](PreludeConfig::construct_uses).
+    pub(crate) fn construct_uses_length(&self) -> usize {
+        self.uses.iter().map(|path| 4 + path.len() + 2).sum()
+    }
+}
+
+impl Default for PreludeConfig {
+    fn default() -> Self {
+        PreludeConfig {
+            uses: vec![
+                "std::collections::*".to_string(),
+                "std::io::Write".to_string(),
+            ],
+        }
+    }
+}
+
+/// An async runtime that can drive the evaluation of `async` snippets.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AsyncRuntime {
+    /// The [`tokio`](https://docs.rs/tokio) runtime.
+    Tokio,
+    /// The [`async-std`](https://docs.rs/async-std) runtime.
+    AsyncStd,
+}
+
+impl AsyncRuntime {
+    /// The `Cargo.toml` dependency line of the runtime crate.
+    pub fn dependency(self) -> &'static str {
+        match self {
+            AsyncRuntime::Tokio => r#"tokio = { version = "1", features = [ "full" ] }"#,
+            AsyncRuntime::AsyncStd => r#"async-std = "1""#,
+        }
+    }
+
+    /// The code which opens the block that is run to completion on the runtime.
+    ///
+    /// The block is closed with `})`.
+    pub fn block_on_start(self) -> &'static str {
+        match self {
+            AsyncRuntime::Tokio => {
+                "tokio::runtime::Runtime::new().expect(\"failed to start tokio runtime\").block_on(async move {\n"
+            }
+            AsyncRuntime::AsyncStd => "async_std::task::block_on(async move {\n",
+        }
+    }
+}
+
 /// Represents an externally linked library.
 ///
 /// The structure holds a path to an `lib*.rlib` library. The path
@@ -285,6 +513,8 @@ pub struct Extern {
     /// Path to rlib.
     path: PathBuf,
     alias: Option<&'static str>,
+    /// Exposed dependencies, the crate name and rlib path.
+    exposed: Vec<(String, PathBuf)>,
 }
 
 impl Extern {
@@ -381,7 +611,11 @@ impl Extern {
             ));
         }
 
-        let e = Extern { path, alias };
+        let e = Extern {
+            path,
+            alias,
+            exposed: Vec::new(),
+        };
 
         Ok(e)
     }
@@ -408,6 +642,50 @@ impl Extern {
         self.path.parent().unwrap().join("deps") // this has been validated already.
     }
 
+    /// Expose a dependency of the library to the REPL code.
+    ///
+    /// The dependency `rlib` (`lib*-<hash>.rlib`) is found in the `deps` folder, and is linked with
+    /// an `--extern` flag. Errors if the `rlib` is not found, or more than one is found. See the
+    /// [_Exposing Dependencies_](crate::linking#exposing-dependencies) caveats.
+    pub fn expose_dependency(mut self, crate_name: &str) -> io::Result<Self> {
+        let name = crate_name.replace('-', "_");
+        let prefix = format!("lib{}-", name);
+
+        let mut found = fs::read_dir(self.deps_path())?
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| {
+                path.file_name()
+                    .and_then(|s| s.to_str())
+                    .map(|s| s.starts_with(&prefix) && s.ends_with(".rlib"))
+                    .unwrap_or(false)
+            })
+            .collect::<Vec<_>>();
+
+        match found.len() {
+            0 => Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("did not find dependency '{}' in deps folder", crate_name),
+            )),
+            1 => {
+                self.exposed.push((name, found.remove(0)));
+                Ok(self)
+            }
+            _ => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "found more than one rlib for dependency '{}' in deps folder",
+                    crate_name
+                ),
+            )),
+        }
+    }
+
+    /// The exposed dependencies, as the crate name and `rlib` path.
+    pub fn exposed_dependencies(&self) -> &[(String, PathBuf)] {
+        &self.exposed
+    }
+
     /// Append the buffer with the code representation.
     pub fn construct_code_str(&self, buf: &mut String) {
         buf.push_str("extern crate "); // 13
@@ -463,6 +741,47 @@ fn get_rlib_path(crate_name: &str) -> io::Result<PathBuf> {
 mod tests {
     use super::*;
 
+    #[test]
+    fn linking_configuration_builder_test() {
+        let config = unsafe {
+            LinkingConfiguration::builder()
+                .rlib("test-resources/external_kserd/target/debug/libexternal_kserd.rlib")
+                .rlib_with_alias(
+                    "test-resources/external_crate/target/debug/libexternal_crate.rlib",
+                    "ext",
+                )
+                .data_type("external_crate::MyStruct")
+        }
+        .mutable(true)
+        .build()
+        .unwrap();
+
+        let mut libs = config
+            .external_libs
+            .iter()
+            .map(|lib| (lib.lib_name(), lib.alias()))
+            .collect::<Vec<_>>();
+        libs.sort();
+        assert_eq!(
+            libs,
+            vec![("external_crate", Some("ext")), ("external_kserd", None)]
+        );
+        assert_eq!(
+            config.data_type.as_deref(),
+            Some("external_crate::MyStruct")
+        );
+        assert!(config.mutable);
+
+        // the first invalid path is returned
+        let err = LinkingConfiguration::builder()
+            .rlib("test-resources/missing/libmissing.rlib")
+            .rlib("Cargo.toml")
+            .build()
+            .err()
+            .unwrap();
+        assert_eq!(err.kind(), io::ErrorKind::NotFound);
+    }
+
     #[test]
     fn get_rlib_path_test() {
         use std::error::Error;
@@ -478,6 +797,7 @@ mod tests {
         let mut e = Extern {
             path: PathBuf::from("libsome_lib.rlib"),
             alias: None,
+            exposed: Vec::new(),
         };
 
         let mut s = String::new();
@@ -496,4 +816,23 @@ mod tests {
         assert_eq!(&s, ans);
         assert_eq!(e.construct_code_str_length(), ans.len());
     }
+
+    #[test]
+    fn expose_dependency_test() {
+        let e = Extern::new("test-resources/external_kserd/target/debug/libexternal_kserd.rlib")
+            .unwrap()
+            .expose_dependency("rand")
+            .unwrap();
+        let exposed = e.exposed_dependencies();
+        assert_eq!(exposed.len(), 1);
+        assert_eq!(&exposed[0].0, "rand");
+        // does not match rand_core
+        let file = exposed[0].1.file_name().unwrap().to_str().unwrap();
+        assert!(file.starts_with("librand-"));
+
+        let e = Extern::new("test-resources/external_kserd/target/debug/libexternal_kserd.rlib")
+            .unwrap()
+            .expose_dependency("serde");
+        assert_eq!(e.err().map(|e| e.kind()), Some(io::ErrorKind::NotFound));
+    }
 }
@@ -82,6 +82,7 @@
 //!                     lock.flush().unwrap();
 //!                 }
 //!                 OutputChange::NewLine => writeln!(&mut stdout, "").unwrap(),
+//!                 OutputChange::Patch { .. } => (), // not sent by Output
 //!             }
 //!         }
 //!     })
@@ -208,6 +209,7 @@
 //!                     output.push('\n');
 //!                     pos = output.len();
 //!                 }
+//!                 OutputChange::Patch { .. } => (), // not sent by Output
 //!             }
 //!         }
 //!     })
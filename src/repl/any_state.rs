@@ -22,53 +22,24 @@ impl<S, D> Repl<S, D> {
 
     /// The prompt.
     ///
-    /// Includes the module name, the editing/mutating state, the command path, the input symbol,
+    /// The prompt is rendered using the [`Prompt`] set with
+    /// [`with_prompt`](ReplData::with_prompt), which defaults to [`DefaultPrompt`]. The default
+    /// includes the module name, the editing/mutating state, the command path, the input symbol,
     /// _and the trailing space_. It also includes the colouring byte sequences if specified.
     pub fn prompt(&self, colour: bool) -> String {
-        let mod_path = format!("[{}]", self.data.current_mod.display());
-
-        let cmdtree_path = self.data.cmdtree.path();
-
-        let m = if self.data.linking.mutable {
-            "-mut"
-        } else {
-            ""
-        };
-
-        let e = if let Some(ei) = self.data.editing {
-            format!(
-                "-editing-{}{}",
-                match ei.editing {
-                    Editing::Crate => "crate",
-                    Editing::Item => "item",
-                    Editing::Stmt => "stmt",
-                },
-                ei.index
-            )
-        } else {
-            String::new()
+        let data = &self.data;
+        let ctx = PromptContext {
+            mod_path: data.current_mod(),
+            cmdtree_path: data.cmdtree.path(),
+            mutable: data.linking.mutable,
+            editing: data.editing,
+            more: self.more,
+            colour,
+            prompt_colour: data.prompt_colour,
+            counter: data.current_src().stmts.len(),
         };
 
-        let pcolour = self.data.prompt_colour;
-
-        match (self.more, colour) {
-            (true, true) => format!(
-                "{} {}{}{}.> ",
-                mod_path.color(pcolour),
-                cmdtree_path.color(pcolour),
-                m.bright_red(),
-                e.bright_red()
-            ),
-            (false, true) => format!(
-                "{} {}{}{}=> ",
-                mod_path.color(pcolour),
-                cmdtree_path.color(pcolour),
-                m.bright_red(),
-                e.bright_red()
-            ),
-            (true, false) => format!("{} {}{}{}.> ", mod_path, cmdtree_path, m, e),
-            (false, false) => format!("{} {}{}{}=> ", mod_path, cmdtree_path, m, e),
-        }
+        data.prompt.render(&ctx)
     }
 }
 
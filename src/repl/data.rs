@@ -1,5 +1,9 @@
 use super::*;
 use crate::code::{ModsMap, SourceCode};
+use crate::compile::CompileCache;
+
+/// The number of compiled libraries the REPL keeps in its compile cache.
+const COMPILE_CACHE_CAPACITY: usize = 10;
 
 impl<Data> Default for ReplData<Data> {
     fn default() -> Self {
@@ -15,12 +19,20 @@ impl<Data> Default for ReplData<Data> {
             current_mod: lib_path,
             prompt_colour: Color::Cyan,
             out_colour: Color::BrightGreen,
+            prompt: Box::new(DefaultPrompt),
+            auto_indent: Some("    ".to_string()),
             compilation_dir: default_compile_dir(),
             linking: LinkingConfiguration::default(),
+            build_config: BuildConfig {
+                cache: Some(CompileCache::with_capacity(COMPILE_CACHE_CAPACITY)),
+                ..BuildConfig::default()
+            },
             editing: None,
             editing_src: None,
             loadedlibs: VecDeque::new(),
             loaded_libs_size_limit: 0,
+            session_logger: None,
+            compile_listener: None,
         };
 
         r.with_cmdtree_builder(Builder::new("papyrus"))
@@ -55,11 +67,130 @@ impl<Data> ReplData<Data> {
         self
     }
 
+    /// Set the prompt renderer. The default is [`DefaultPrompt`].
+    ///
+    /// The prompt is drawn before the input, and its width is used to lay out the input.
+    pub fn with_prompt<P: Prompt + Send + 'static>(&mut self, prompt: P) -> &mut Self {
+        self.prompt = Box::new(prompt);
+        self
+    }
+
+    /// Record the session with the logger, replacing any existing logger.
+    ///
+    /// Each evaluated input is logged, along with the generated source code, compilation errors,
+    /// and the output.
+    pub fn with_session_logger(&mut self, logger: SessionLogger) -> &mut Self {
+        self.session_logger = Some(logger);
+        self
+    }
+
+    /// The session logger, if set.
+    pub fn session_logger(&self) -> Option<&SessionLogger> {
+        self.session_logger.as_ref()
+    }
+
+    /// Set the compilation configuration.
+    pub fn with_build_config(&mut self, config: BuildConfig) -> &mut Self {
+        self.build_config = config;
+        self
+    }
+
+    /// Apply a script header, adding its crates to the current mod and setting its build options.
+    ///
+    /// A crate which is already referenced has its version replaced. See
+    /// [`parse_header`](crate::input::parse_header).
+    pub fn with_header(&mut self, header: crate::input::Header) -> &mut Self {
+        let crate::input::Header { crates, release } = header;
+
+        let src = self
+            .mods_map
+            .get_mut(&self.current_mod)
+            .expect("current mod should always exist");
+        for c in crates {
+            match src.crates.iter_mut().find(|x| x.src_line == c.src_line) {
+                Some(existing) => {
+                    existing.version = c.version;
+                    existing.source = c.source;
+                }
+                None => src.crates.push(c),
+            }
+        }
+
+        if release {
+            self.build_config.release = true;
+        }
+
+        self
+    }
+
+    /// Remove the compiled artifacts in the compilation directory, returning the bytes freed.
+    ///
+    /// See [`compile::clean_compile_dir`](crate::compile::clean_compile_dir). This is the same as
+    /// the `clean` command.
+    pub fn clean_compilation_dir(&self) -> io::Result<u64> {
+        crate::compile::clean_compile_dir(&self.compilation_dir)
+    }
+
+    /// The current compilation configuration.
+    pub fn build_config(&self) -> &BuildConfig {
+        &self.build_config
+    }
+
     /// The current mod that is being repl'd on.
     pub fn current_mod(&self) -> &Path {
         self.current_mod.as_path()
     }
 
+    /// Set the current mod that is being repl'd on, creating it (and any parents) if necessary.
+    ///
+    /// The path is relative to the crate root, such as `a/b`, or `lib` for the root. Each component
+    /// must be a valid identifier. Subsequent inputs are evaluated in this mod. This is the same
+    /// as the `mod switch` command.
+    pub fn set_current_mod<P: AsRef<Path>>(&mut self, path: P) -> io::Result<&mut Self> {
+        let path = path.as_ref();
+        let valid = path.components().count() > 0
+            && path.iter().all(|x| {
+                x.to_str()
+                    .map(|s| syn::parse_str::<syn::Ident>(s).is_ok())
+                    .unwrap_or(false)
+            });
+
+        if !valid {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("{} is not a valid module path", path.display()),
+            ));
+        }
+
+        crate::cmds::switch_module(self, path);
+        Ok(self)
+    }
+
+    /// The name of the function that evaluates the current mod.
+    pub fn eval_fn_name(&self) -> String {
+        let mut fn_name = String::new();
+        crate::code::eval_fn_name(
+            &crate::code::into_mod_path_vec(self.current_mod()),
+            &mut fn_name,
+        );
+        fn_name
+    }
+
+    /// The inferred type of `expr` if it were evaluated in the current mod, without executing it.
+    ///
+    /// This compiles the current source code in the compilation directory, see
+    /// [`compile::type_of`](crate::compile::type_of).
+    pub fn type_of(&self, expr: &str) -> Result<String, crate::compile::CompilationError> {
+        crate::compile::type_of(
+            &self.compilation_dir,
+            &self.mods_map,
+            self.current_mod(),
+            expr,
+            &self.linking,
+            &self.build_config,
+        )
+    }
+
     /// The current source code, this is short hand for
     /// `self.mods_map().get(self.current_mod()).unwrap()`.
     pub fn current_src(&self) -> &SourceCode {
@@ -68,6 +199,26 @@ impl<Data> ReplData<Data> {
             .expect("thin shouldn't fail, always should exist.")
     }
 
+    /// The item definitions of the current mod in the order they were defined, one per line.
+    ///
+    /// Only the items are listed, not the statements which are evaluated.
+    pub fn items_source(&self) -> String {
+        self.current_src()
+            .items
+            .iter()
+            .map(|(item, _)| item.as_str())
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// The files that would be written to the compilation directory, without writing them.
+    ///
+    /// See [`compile::generate_project_files`](crate::compile::generate_project_files). This is
+    /// the same as the `code` command.
+    pub fn project_files(&self) -> Vec<(PathBuf, String)> {
+        crate::compile::generate_project_files(&self.mods_map, &self.linking, &self.build_config)
+    }
+
     /// The current file map, mappings of modules to source code.
     pub fn mods_map(&self) -> &ModsMap {
         &self.mods_map
@@ -105,3 +256,71 @@ impl<Data> ReplData<Data> {
         self
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn set_current_mod_test() {
+        let mut data = ReplData::<()>::default();
+        assert_eq!(data.eval_fn_name(), "_lib_intern_eval");
+
+        data.set_current_mod("a/b").unwrap();
+        assert_eq!(data.current_mod(), Path::new("a/b"));
+        assert!(data.mods_map().contains_key(Path::new("a")));
+        assert_eq!(data.eval_fn_name(), "_a_b_intern_eval");
+
+        data.set_current_mod("lib").unwrap();
+        assert_eq!(data.eval_fn_name(), "_lib_intern_eval");
+
+        assert!(data.set_current_mod("a/fn").is_err());
+        assert!(data.set_current_mod("a-b").is_err());
+        assert!(data.set_current_mod("").is_err());
+        assert_eq!(data.current_mod(), Path::new("lib"));
+    }
+
+    #[test]
+    fn project_files_test() {
+        let mut data = ReplData::<()>::default();
+        data.mods_map
+            .get_mut(Path::new("lib"))
+            .unwrap()
+            .items
+            .push(("fn a() -> i32 { 1 }".to_string(), false));
+
+        let files = data.project_files();
+        let find = |path: &str| {
+            files
+                .iter()
+                .find(|(p, _)| p == Path::new(path))
+                .map(|(_, contents)| contents.as_str())
+                .unwrap()
+        };
+        assert!(find("src/lib.rs").contains("fn a() -> i32 { 1 }"));
+        assert!(find("Cargo.toml").contains("[package]"));
+    }
+
+    #[test]
+    fn items_source_test() {
+        use crate as papyrus;
+
+        let dir = "target/testing/items_source_test";
+        let script = format!("{}/script.papyrus", dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            &script,
+            "fn a() -> i32 { 1 }\nlet x = a();\nstruct B;\nx + 1",
+        )
+        .unwrap();
+
+        let mut repl = repl!();
+        repl.data.with_compilation_dir(dir).unwrap();
+        assert_eq!(repl.data.items_source(), "");
+
+        let (repl, results) = repl.run_script(&script, &mut ());
+        results.unwrap();
+
+        assert_eq!(repl.data.items_source(), "fn a() -> i32 { 1 }\nstruct B;");
+    }
+}
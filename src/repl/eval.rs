@@ -1,7 +1,7 @@
 use super::*;
 use crate::{
     cmds::{self, CommandResult},
-    code::{self, Input, SourceCode, StmtGrp},
+    code::{Input, SourceCode, StmtGrp},
     compile,
 };
 use std::borrow::{Borrow, BorrowMut};
@@ -45,6 +45,17 @@ impl<D> Repl<Evaluate, D> {
         self.state.output.listen()
     }
 
+    /// Begin listening to the lines of compiler output of the evaluation.
+    ///
+    /// While listening, the lines are sent to the receiver rather than written to the output, so
+    /// compiler progress can be shown separately from the output of the code. The channel
+    /// disconnects once the evaluation finishes.
+    pub fn compile_listen(&mut self) -> crossbeam_channel::Receiver<String> {
+        let (tx, rx) = crossbeam_channel::unbounded();
+        self.data.compile_listener = Some(tx);
+        rx
+    }
+
     /// Close the sender side of the output channel.
     pub fn close_channel(&mut self) {
         self.state.output.close()
@@ -129,7 +140,7 @@ where
         InputResult::Program(input) => {
             Ok(data.handle_program(input, &mut output, obtain_mut_data, obtain_brw_data))
         }
-        InputResult::InputError(err) => Ok(EvalOutput::Print(Cow::Owned(err))),
+        InputResult::InputError(err) => Ok(EvalOutput::Error(Cow::Owned(err))),
         InputResult::Eof => Err(Signal::Exit),
         _ => Ok(EvalOutput::Print(Cow::Borrowed(""))),
     };
@@ -139,6 +150,8 @@ where
         Err(sig) => (EvalOutput::Print(Cow::Borrowed("")), sig),
     };
 
+    data.compile_listener = None; // disconnect the listener
+
     data.linking.mutable = keep_mutating; // always cancel a mutating block on evaluation??
                                           // the alternative would be to keep alive on compilation failures, might not for now though.
                                           // this would have to be individually handled in each match arm and it, rather let the user
@@ -226,6 +239,14 @@ impl<D> ReplData<D> {
         Fbrw: FnOnce() -> Rbrw,
         Rbrw: Deref<Target = D>,
     {
+        if self.session_logger.is_some() {
+            self.log_session("input", &logger::input_src(&input));
+        }
+
+        if input.release {
+            self.build_config.release = true;
+        }
+
         let (nitems, ncrates) = (input.items.len(), input.crates.len());
 
         let has_stmts = input.stmts.len() > 0;
@@ -298,21 +319,48 @@ impl<D> ReplData<D> {
             }
         };
 
+        // check syntax, this is faster than waiting for compilation to fail
+        if let Err(e) = compile::check_syntax(&self.mods_map, &self.linking) {
+            maybe_pop_input(self); // failed so don't save
+            return EvalOutput::Error(Cow::Owned(e.to_string()));
+        }
+
         // build directory
-        let res = compile::build_compile_dir(&self.compilation_dir, &self.mods_map, &self.linking);
+        let res = compile::build_compile_dir(
+            &self.compilation_dir,
+            &self.mods_map,
+            &self.linking,
+            &self.build_config,
+        );
         if let Err(e) = res {
             maybe_pop_input(self); // failed so don't save
-            return EvalOutput::Print(Cow::Owned(format!(
+            return EvalOutput::Error(Cow::Owned(format!(
                 "failed to build compile directory: {}",
                 e
             )));
         }
 
+        if self.session_logger.is_some() {
+            let (src, _) = crate::code::construct_source_code(&self.mods_map, &self.linking);
+            self.log_session("source", &src);
+        }
+
         // compile
-        let lib_file = compile::compile(&self.compilation_dir, &self.linking, |line| {
-            writer.erase_last_line();
-            writer.write_str(line);
-        });
+        let listener = self.compile_listener.as_ref();
+        let lib_file = compile::compile(
+            &self.compilation_dir,
+            &self.linking,
+            &self.build_config,
+            |line| match listener {
+                Some(tx) => {
+                    tx.send(line.to_owned()).ok();
+                }
+                None => {
+                    writer.erase_last_line();
+                    writer.write_str(line);
+                }
+            },
+        );
 
         writer.erase_last_line();
 
@@ -320,7 +368,9 @@ impl<D> ReplData<D> {
             Ok(f) => f,
             Err(e) => {
                 maybe_pop_input(self); // failed so don't save
-                return EvalOutput::Print(Cow::Owned(format!("{}", e)));
+                let e = e.to_string();
+                self.log_session("compile error", &e);
+                return EvalOutput::Error(Cow::Owned(e));
             }
         };
 
@@ -332,8 +382,7 @@ impl<D> ReplData<D> {
                 // happen
                 let lib_file = compile::unshackle_library_file(lib_file);
 
-                let mut fn_name = String::new();
-                code::eval_fn_name(&code::into_mod_path_vec(self.current_mod()), &mut fn_name);
+                let fn_name = self.eval_fn_name();
 
                 if self.linking.mutable {
                     let mut r = obtain_mut_data();
@@ -347,6 +396,8 @@ impl<D> ReplData<D> {
             };
             match exec_res {
                 Ok((kserd, lib)) => {
+                    self.log_session("output", &kserd.to_string());
+
                     // store vec, maybe
                     add_to_limit_vec(&mut self.loadedlibs, lib, self.loaded_libs_size_limit);
 
@@ -360,7 +411,8 @@ impl<D> ReplData<D> {
                 }
                 Err(e) => {
                     maybe_pop_input(self); // failed so don't save
-                    EvalOutput::Print(Cow::Borrowed(e))
+                    self.log_session("error", &e);
+                    EvalOutput::Error(e)
                 }
             }
         } else {
@@ -374,6 +426,7 @@ impl<D> ReplData<D> {
             items,
             crates,
             stmts,
+            release: _,
         } = input;
 
         let src = self.get_current_file_mut();
@@ -433,3 +486,28 @@ fn vec_limited_testing() {
     add_to_limit_vec(&mut vec, 2, 1);
     assert_eq!(&vec, &[2]);
 }
+
+#[test]
+fn compile_listen_test() {
+    use crate as papyrus;
+
+    let mut repl = repl!();
+    repl.data
+        .with_compilation_dir("target/testing/compile_listen_test")
+        .unwrap();
+    repl.line_input("2 + 2");
+    let mut eval = match repl.read() {
+        ReadResult::Eval(eval) => eval,
+        _ => panic!("expecting eval"),
+    };
+
+    let progress = eval.compile_listen();
+    let EvalResult { repl, .. } = eval.eval(&mut ());
+
+    // cargo always reports it has finished
+    let lines = progress.try_iter().collect::<Vec<_>>();
+    assert!(lines.iter().any(|line| line.contains("Finished")));
+    assert!(progress.recv().is_err());
+    let (repl, _) = repl.print();
+    assert!(!repl.output().contains("Finished"));
+}
@@ -0,0 +1,135 @@
+use super::*;
+use std::io::Write;
+
+/// Records a REPL session to a log file, for reproducing issues.
+///
+/// Each evaluated input is logged, along with the generated source code, any compilation errors,
+/// and the output. Once the log file exceeds `max_bytes` it is rotated: the file is renamed with a
+/// `.1` extension appended (replacing any previous rotation) and a new log file is started.
+///
+/// Set the logger with [`ReplData::with_session_logger`].
+#[derive(Debug)]
+pub struct SessionLogger {
+    path: PathBuf,
+    max_bytes: u64,
+    file: fs::File,
+}
+
+impl SessionLogger {
+    /// Log to the file at `path`, appending if it exists.
+    pub fn new<P: AsRef<Path>>(path: P, max_bytes: u64) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = open_log(&path)?;
+        Ok(SessionLogger {
+            path,
+            max_bytes,
+            file,
+        })
+    }
+
+    /// The path of the log file.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The path the log file is moved to when rotated.
+    pub fn rotated_path(&self) -> PathBuf {
+        let mut p = self.path.clone().into_os_string();
+        p.push(".1");
+        p.into()
+    }
+
+    /// Write an entry with a heading, ie `input`.
+    pub fn log(&mut self, heading: &str, contents: &str) -> io::Result<()> {
+        writeln!(self.file, "--- {}", heading)?;
+        writeln!(self.file, "{}", contents)?;
+        self.file.flush()?;
+
+        if self.file.metadata()?.len() > self.max_bytes {
+            self.rotate()?;
+        }
+
+        Ok(())
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        fs::rename(&self.path, self.rotated_path())?;
+        self.file = open_log(&self.path)?;
+        Ok(())
+    }
+}
+
+fn open_log(path: &Path) -> io::Result<fs::File> {
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+impl<D> ReplData<D> {
+    /// Log an entry to the session logger, if there is one.
+    pub(super) fn log_session(&mut self, heading: &str, contents: &str) {
+        if let Some(logger) = self.session_logger.as_mut() {
+            if let Err(e) = logger.log(heading, contents) {
+                error!("failed to write to session log: {}", e);
+            }
+        }
+    }
+}
+
+/// The input as source code, crates first, then items, then statements.
+pub(super) fn input_src(input: &crate::code::Input) -> String {
+    let crates = input.crates.iter().map(|c| c.src_line.clone());
+    let items = input.items.iter().map(|i| i.0.clone());
+    let stmts = Some(crate::code::StmtGrp(input.stmts.clone()))
+        .filter(|grp| !grp.0.is_empty())
+        .map(|grp| grp.src_line());
+
+    crates
+        .chain(items)
+        .chain(stmts)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as papyrus;
+
+    #[test]
+    fn session_logger_test() {
+        let dir = "target/testing/session_logger_test";
+        let script = format!("{}/script.papyrus", dir);
+        let log = format!("{}/session.log", dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::remove_file(&log).ok();
+        fs::write(&script, "let a = 1;\na + 1\na * 10").unwrap();
+
+        let mut repl = repl!();
+        repl.data
+            .with_compilation_dir(dir)
+            .unwrap()
+            .with_session_logger(SessionLogger::new(&log, 1_000_000).unwrap());
+
+        let (mut repl, results) = repl.run_script(&script, &mut ());
+        results.unwrap();
+
+        let contents = fs::read_to_string(&log).unwrap();
+        assert!(contents.contains("--- input\nlet a = 1; a + 1\n"));
+        assert!(contents.contains("--- input\na * 10\n"));
+        assert!(contents.contains("--- source\n"));
+        assert!(contents.contains("--- output\n2\n"));
+        assert!(contents.contains("--- output\n10\n"));
+
+        // rotates once the cap is exceeded
+        repl.data
+            .with_session_logger(SessionLogger::new(&log, 100).unwrap());
+        fs::write(&script, "a + 2").unwrap();
+        let (repl, results) = repl.run_script(&script, &mut ());
+        results.unwrap();
+
+        let logger = repl.data.session_logger().unwrap();
+        let rotated = fs::read_to_string(logger.rotated_path()).unwrap();
+        let current = fs::read_to_string(logger.path()).unwrap();
+        assert!(current.len() <= 100);
+        assert!(format!("{}{}", rotated, current).contains("--- output\n3\n"));
+    }
+}
@@ -4,12 +4,16 @@
 mod any_state;
 mod data;
 mod eval;
+mod logger;
 mod print;
+mod prompt;
 mod read;
+mod script;
 
 use crate::{
     cmds::CommandResult,
     code::ModsMap,
+    compile::BuildConfig,
     input::InputResult,
     linking::{self, LinkingConfiguration},
     output::{self, Output},
@@ -56,6 +60,14 @@ pub struct ReplData<Data> {
     pub prompt_colour: Color,
     /// The colour of the out component. ie `[out0]`.
     pub out_colour: Color,
+    /// Renders the prompt. Defaults to [`DefaultPrompt`].
+    prompt: Box<dyn Prompt + Send>,
+
+    /// The indentation inserted on a new line when more input is required, `None` to disable
+    /// auto-indenting. Defaults to four spaces.
+    ///
+    /// Used by the terminal interface, see [`next_line_indent`](crate::input::next_line_indent).
+    pub auto_indent: Option<String>,
 
     /// The directory for which compilation is done within.
     /// Defaults to `$HOME/.papyrus/`.
@@ -64,6 +76,9 @@ pub struct ReplData<Data> {
     /// The external crate linking configuration,
     linking: LinkingConfiguration,
 
+    /// The compilation configuration.
+    build_config: BuildConfig,
+
     /// Flag for editing a statement, item, or crate.
     ///
     /// If a value is set when an evaluation starts, the input buffer
@@ -96,8 +111,18 @@ pub struct ReplData<Data> {
     /// The default is to keep the size limit at zero, thus ensuring no libraries are kept in
     /// memory. This is recommended unless issues are arising from esoteric use cases.
     pub loaded_libs_size_limit: usize,
+
+    /// Records the session to a log file.
+    session_logger: Option<SessionLogger>,
+
+    /// Receives the lines of compiler output while evaluating, rather than the output.
+    compile_listener: Option<crossbeam_channel::Sender<String>>,
 }
 
+pub use self::logger::SessionLogger;
+pub use self::prompt::{DefaultPrompt, Prompt, PromptContext};
+pub use self::script::ScriptError;
+
 /// Repl read state.
 #[derive(Debug)]
 pub struct Read {
@@ -129,6 +154,8 @@ enum EvalOutput {
     /// If there is data, then it should be prefixed with `[out#]`.
     Data(Kserd<'static>),
     Print(Cow<'static, str>),
+    /// The evaluation failed, the message is printed.
+    Error(Cow<'static, str>),
 }
 
 /// Represents an evaluating result. Signal should be checked and handled.
@@ -46,7 +46,7 @@ impl<D> Repl<Print, D> {
 
                 kserd = Some((num, k));
             }
-            EvalOutput::Print(print) => {
+            EvalOutput::Print(print) | EvalOutput::Error(print) => {
                 if print.len() > 0 {
                     // only write if there is something to write.
                     output.write_line(&print);
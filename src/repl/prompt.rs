@@ -0,0 +1,99 @@
+use super::*;
+
+/// The state of the REPL which is available when rendering the prompt.
+#[derive(Debug, Clone)]
+pub struct PromptContext<'a> {
+    /// The current module path, ie `lib`.
+    pub mod_path: &'a Path,
+    /// The current command tree path.
+    pub cmdtree_path: &'a str,
+    /// The REPL is in a mutating state.
+    pub mutable: bool,
+    /// The statement, item, or crate being edited, if any.
+    pub editing: Option<EditingIndex>,
+    /// More input is required to complete the input buffer.
+    pub more: bool,
+    /// The prompt should include colouring byte sequences.
+    pub colour: bool,
+    /// The colour of the prompt region.
+    pub prompt_colour: Color,
+    /// The number of statement groups in the current module, which is the index of the next
+    /// `[out#]`.
+    pub counter: usize,
+}
+
+/// Renders the prompt which is drawn before the input.
+///
+/// Any `Fn(&PromptContext) -> String` closure implements `Prompt`.
+///
+/// # Example
+/// ```rust
+/// # use papyrus::repl::{PromptContext, Repl};
+/// let mut repl = Repl::<_, ()>::default();
+/// repl.data
+///     .with_prompt(|ctx: &PromptContext| format!("[{}] {}> ", ctx.counter, ctx.mod_path.display()));
+///
+/// assert_eq!(repl.prompt(false), "[0] lib> ");
+/// ```
+pub trait Prompt {
+    /// Render the prompt, including the trailing space.
+    fn render(&self, ctx: &PromptContext) -> String;
+}
+
+impl<F: Fn(&PromptContext) -> String> Prompt for F {
+    fn render(&self, ctx: &PromptContext) -> String {
+        self(ctx)
+    }
+}
+
+/// The default prompt, ie `[lib] papyrus=> `.
+///
+/// Includes the module name, the editing/mutating state, the command path, the input symbol,
+/// _and the trailing space_.
+#[derive(Debug, Default, Copy, Clone)]
+pub struct DefaultPrompt;
+
+impl Prompt for DefaultPrompt {
+    fn render(&self, ctx: &PromptContext) -> String {
+        let mod_path = format!("[{}]", ctx.mod_path.display());
+
+        let cmdtree_path = ctx.cmdtree_path;
+
+        let m = if ctx.mutable { "-mut" } else { "" };
+
+        let e = if let Some(ei) = ctx.editing {
+            format!(
+                "-editing-{}{}",
+                match ei.editing {
+                    Editing::Crate => "crate",
+                    Editing::Item => "item",
+                    Editing::Stmt => "stmt",
+                },
+                ei.index
+            )
+        } else {
+            String::new()
+        };
+
+        let pcolour = ctx.prompt_colour;
+
+        match (ctx.more, ctx.colour) {
+            (true, true) => format!(
+                "{} {}{}{}.> ",
+                mod_path.color(pcolour),
+                cmdtree_path.color(pcolour),
+                m.bright_red(),
+                e.bright_red()
+            ),
+            (false, true) => format!(
+                "{} {}{}{}=> ",
+                mod_path.color(pcolour),
+                cmdtree_path.color(pcolour),
+                m.bright_red(),
+                e.bright_red()
+            ),
+            (true, false) => format!("{} {}{}{}.> ", mod_path, cmdtree_path, m, e),
+            (false, false) => format!("{} {}{}{}=> ", mod_path, cmdtree_path, m, e),
+        }
+    }
+}
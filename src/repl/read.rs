@@ -25,6 +25,9 @@ impl<D> Repl<Read, D> {
     ///
     /// A line is considered if more input is required, the previous input stacked.
     /// Only overwrites the most recent buffer.
+    ///
+    /// The input can contain newlines, such as a pasted block of code, which is read as a single
+    /// input.
     pub fn line_input(&mut self, input: &str) {
         self.state.output.replace_line_input(input);
     }
@@ -0,0 +1,237 @@
+use super::*;
+
+impl<D> Repl<Read, D> {
+    /// Runs the file at `path` as a sequence of REPL inputs.
+    ///
+    /// Each line is read as if it were typed, so inputs spanning multiple lines (such as unclosed
+    /// delimiters or trailing semi-colons) are accumulated before being evaluated. State is
+    /// preserved between inputs. The data of each evaluation that produces data is returned, in
+    /// order.
+    ///
+    /// The script can start with a header of crate and option directives, see
+    /// [`parse_header`](crate::input::parse_header). The header is applied with
+    /// [`ReplData::with_header`] and is not evaluated. A leading shebang line (`#!/usr/bin/env
+    /// papyrus`) is skipped, so scripts can be made executable. Error line numbers are of the
+    /// script file, including the skipped lines.
+    ///
+    /// Running stops at the first input that fails to evaluate, or if an exit is signalled. The
+    /// repl is always returned.
+    pub fn run_script<P: AsRef<Path>>(
+        self,
+        path: P,
+        app_data: &mut D,
+    ) -> (Self, Result<Vec<Kserd<'static>>, ScriptError>) {
+        let script = match fs::read_to_string(path) {
+            Ok(s) => s,
+            Err(e) => return (self, Err(ScriptError::Io(e))),
+        };
+
+        let body = strip_shebang(&script);
+        let (header, code) = match crate::input::parse_header(body) {
+            Ok(x) => x,
+            Err(msg) => {
+                // the failing directive is the first that does not parse on its own
+                let shebang_lines = script[..script.len() - body.len()].lines().count();
+                let idx = body
+                    .lines()
+                    .position(|line| crate::input::parse_header(line).is_err())
+                    .unwrap_or(0);
+                let line = shebang_lines + idx + 1;
+                return (self, Err(ScriptError::Eval { line, msg }));
+            }
+        };
+        let header_lines = script[..script.len() - code.len()].lines().count();
+
+        let mut repl = self;
+        repl.data.with_header(header);
+        let mut results = Vec::new();
+
+        for (idx, line) in code.lines().enumerate() {
+            repl.line_input(line);
+
+            let eval = match repl.read() {
+                ReadResult::Read(r) => {
+                    repl = r;
+                    continue;
+                }
+                ReadResult::Eval(e) => e,
+            };
+
+            let EvalResult {
+                repl: print,
+                signal,
+            } = eval.eval(app_data);
+
+            let err = match &print.state.data {
+                EvalOutput::Error(e) => Some(e.to_string()),
+                _ => None,
+            };
+
+            let (read, data) = print.print();
+            repl = read;
+
+            if let Some(msg) = err {
+                let line = header_lines + idx + 1;
+                return (repl, Err(ScriptError::Eval { line, msg }));
+            }
+
+            results.extend(data.map(|x| x.1));
+
+            if let Signal::Exit = signal {
+                break;
+            }
+        }
+
+        (repl, Ok(results))
+    }
+}
+
+/// The script without a leading shebang line. An inner attribute (`#![...]`) is not a shebang.
+fn strip_shebang(script: &str) -> &str {
+    match script.strip_prefix("#!") {
+        Some(rest) if !rest.trim_start().starts_with('[') => {
+            rest.split_once('\n').map_or("", |(_, code)| code)
+        }
+        _ => script,
+    }
+}
+
+/// Error running a script.
+#[derive(Debug)]
+pub enum ScriptError {
+    /// Reading the script file failed.
+    Io(io::Error),
+    /// An input failed to evaluate.
+    Eval {
+        /// The 1-based line of the script at which the failing input was evaluated.
+        line: usize,
+        /// The failure message.
+        msg: String,
+    },
+}
+
+impl std::error::Error for ScriptError {}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScriptError::Io(e) => write!(f, "failed to read script: {}", e),
+            ScriptError::Eval { line, msg } => write!(f, "script failed at line {}: {}", line, msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate as papyrus;
+
+    #[test]
+    fn run_script_test() {
+        let dir = "target/testing/run_script_test";
+        let script = format!("{}/script.papyrus", dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&script, "let a = 1;\na + 1\n\na * 10").unwrap();
+
+        let mut repl = repl!();
+        repl.data.with_compilation_dir(dir).unwrap();
+
+        let (repl, results) = repl.run_script(&script, &mut ());
+        let results = results.unwrap();
+
+        assert_eq!(results, vec![Kserd::new_num(2), Kserd::new_num(10)]);
+        assert_eq!(repl.data.current_src().stmts.len(), 2);
+
+        // failures stop the script
+        fs::write(&script, "b + 1\n2").unwrap();
+        let (_, results) = repl.run_script(&script, &mut ());
+        match results {
+            Err(ScriptError::Eval { line: 1, .. }) => (),
+            x => panic!("expecting eval error on line 1, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn run_script_shebang_test() {
+        assert_eq!(strip_shebang("#!/usr/bin/env papyrus\n1"), "1");
+        assert_eq!(strip_shebang("#!/usr/bin/env papyrus"), "");
+        assert_eq!(
+            strip_shebang("#![allow(unused)]\n1"),
+            "#![allow(unused)]\n1"
+        );
+
+        let dir = "target/testing/run_script_shebang_test";
+        let script = format!("{}/script.papyrus", dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(&script, "#!/usr/bin/env papyrus\nlet a = 2;\na * 2\nb").unwrap();
+
+        let mut repl = repl!();
+        repl.data.with_compilation_dir(dir).unwrap();
+
+        // the line numbers include the shebang
+        let (repl, results) = repl.run_script(&script, &mut ());
+        match results {
+            Err(ScriptError::Eval { line: 4, .. }) => (),
+            x => panic!("expecting eval error on line 4, got {:?}", x),
+        }
+        assert_eq!(repl.data.current_src().stmts.len(), 1);
+
+        fs::write(&script, "#!/usr/bin/env papyrus\n10 * 2").unwrap();
+        let (repl, results) = repl.run_script(&script, &mut ());
+        assert_eq!(results.unwrap(), vec![Kserd::new_num(20)]);
+
+        // as do header errors
+        fs::write(
+            &script,
+            "#!/usr/bin/env papyrus\n//! papyrus: release\n//! papyrus: crate fxhash =\n1",
+        )
+        .unwrap();
+        let (_, results) = repl.run_script(&script, &mut ());
+        match results {
+            Err(ScriptError::Eval { line: 3, .. }) => (),
+            x => panic!("expecting eval error on line 3, got {:?}", x),
+        }
+    }
+
+    #[test]
+    fn run_script_header_test() {
+        let dir = "target/testing/run_script_header_test";
+        let script = format!("{}/script.papyrus", dir);
+        fs::create_dir_all(dir).unwrap();
+        fs::write(
+            &script,
+            "//! papyrus: crate fxhash = \"0.2\"\n//! papyrus: crate byteorder = \"1\"\n//! papyrus: release\nfxhash::hash64(&<byteorder::LE as byteorder::ByteOrder>::read_u16(&[1, 0]))\nb",
+        )
+        .unwrap();
+
+        let mut repl = repl!();
+        repl.data.with_compilation_dir(dir).unwrap();
+
+        let (repl, results) = repl.run_script(&script, &mut ());
+
+        // the header is not evaluated, the line numbers are of the script
+        match results {
+            Err(ScriptError::Eval { line: 5, .. }) => (),
+            x => panic!("expecting eval error on line 5, got {:?}", x),
+        }
+
+        let data = &repl.data;
+        assert!(data.build_config().release);
+        let crates = &data.current_src().crates;
+        assert_eq!(crates.len(), 2);
+        assert_eq!(crates[0].cargo_name, "fxhash");
+        assert_eq!(crates[0].version.as_deref(), Some("0.2"));
+
+        let cargotoml = fs::read_to_string(format!("{}/Cargo.toml", dir)).unwrap();
+        assert!(cargotoml.contains("\nfxhash = \"0.2\"\n"));
+        assert!(cargotoml.contains("\nbyteorder = \"1\"\n"));
+
+        let lib = fs::read_to_string(format!("{}/src/lib.rs", dir)).unwrap();
+        assert!(!lib.contains("papyrus:"));
+        assert_eq!(data.current_src().stmts.len(), 1);
+        assert!(data
+            .build_config()
+            .lib_path(dir)
+            .starts_with(format!("{}/target/release", dir)));
+    }
+}
@@ -0,0 +1,256 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The default maximum number of history entries.
+const MAX_LEN: usize = 1000;
+
+/// The input history, navigated with the `Up` and `Down` arrow keys.
+///
+/// The history is loaded from, and saved to, a newline delimited file so it persists across
+/// sessions. Newlines within an entry, such as a pasted block, are escaped as `\n`. Consecutive
+/// identical entries are only stored once, and once the history exceeds its maximum length the
+/// oldest entries are dropped.
+///
+/// Set the history with [`RunCallbacks::with_history`](super::RunCallbacks::with_history).
+#[derive(Debug, Clone)]
+pub struct History {
+    entries: Vec<String>,
+    path: Option<PathBuf>,
+    max_len: usize,
+    /// The index of the navigated entry, equal to the length when not navigating.
+    idx: usize,
+}
+
+impl History {
+    /// An in-memory history which is not saved.
+    pub fn new() -> Self {
+        History {
+            entries: Vec::new(),
+            path: None,
+            max_len: MAX_LEN,
+            idx: 0,
+        }
+    }
+
+    /// Load the history from the file at `path`, which is also where it is saved to.
+    ///
+    /// A missing file is an empty history.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let contents = match fs::read_to_string(&path) {
+            Ok(s) => s,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e),
+        };
+
+        let mut history = History {
+            path: Some(path),
+            ..History::new()
+        };
+        for line in contents.lines() {
+            history.push(unescape(line));
+        }
+
+        Ok(history)
+    }
+
+    /// `$HOME/.papyrus/history`
+    pub fn default_path() -> PathBuf {
+        dirs::home_dir()
+            .unwrap_or_default()
+            .join(".papyrus")
+            .join("history")
+    }
+
+    /// Set the maximum number of entries kept, dropping the oldest entries if exceeded. The
+    /// default is 1000.
+    pub fn with_max_len(mut self, max_len: usize) -> Self {
+        self.max_len = max_len;
+        self.truncate();
+        self
+    }
+
+    /// The entries, oldest first.
+    pub fn entries(&self) -> &[String] {
+        &self.entries
+    }
+
+    /// Add an entry and stop navigating. Blank lines and repeats of the latest entry are ignored.
+    pub fn push(&mut self, line: String) {
+        if !line.trim().is_empty() && self.entries.last() != Some(&line) {
+            self.entries.push(line);
+            self.truncate();
+        }
+        self.reset();
+    }
+
+    /// Navigate to the previous (older) entry. Returns `None` if there are no older entries.
+    pub fn prev(&mut self) -> Option<&str> {
+        if self.idx == 0 {
+            return None;
+        }
+        self.idx -= 1;
+        self.entries.get(self.idx).map(|s| s.as_str())
+    }
+
+    /// Navigate to the next (newer) entry. Returns `None` once past the latest entry, where
+    /// navigation stops.
+    #[allow(clippy::should_implement_trait)]
+    pub fn next(&mut self) -> Option<&str> {
+        self.idx = (self.idx + 1).min(self.entries.len());
+        self.entries.get(self.idx).map(|s| s.as_str())
+    }
+
+    /// The index of the most recent entry before the index `before` which contains `query`.
+    pub fn search(&self, query: &str, before: usize) -> Option<usize> {
+        self.entries[..before.min(self.entries.len())]
+            .iter()
+            .rposition(|e| e.contains(query))
+    }
+
+    /// Entries are being navigated.
+    pub fn is_navigating(&self) -> bool {
+        self.idx < self.entries.len()
+    }
+
+    /// Stop navigating, the next [`prev`](History::prev) is the latest entry.
+    pub fn reset(&mut self) {
+        self.idx = self.entries.len();
+    }
+
+    /// Write the entries to the history file, creating its directory if necessary. An in-memory
+    /// history is not saved.
+    pub fn save(&self) -> io::Result<()> {
+        let path = match &self.path {
+            Some(p) => p,
+            None => return Ok(()),
+        };
+
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+
+        let mut contents = self
+            .entries
+            .iter()
+            .map(|e| escape(e))
+            .collect::<Vec<_>>()
+            .join("\n");
+        contents.push('\n');
+        fs::write(path, contents)
+    }
+
+    fn truncate(&mut self) {
+        if self.entries.len() > self.max_len {
+            let excess = self.entries.len() - self.max_len;
+            self.entries.drain(..excess);
+        }
+        self.idx = self.idx.min(self.entries.len());
+    }
+}
+
+/// Escape an entry onto a single line.
+fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn unescape(line: &str) -> String {
+    let mut s = String::with_capacity(line.len());
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            match chars.next() {
+                Some('n') => s.push('\n'),
+                Some(c) => s.push(c),
+                None => s.push('\\'),
+            }
+        } else {
+            s.push(c);
+        }
+    }
+    s
+}
+
+impl Default for History {
+    fn default() -> Self {
+        History::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn history_navigation_test() {
+        let mut history = History::new();
+        assert_eq!(history.prev(), None);
+        assert_eq!(history.next(), None);
+
+        history.push("let a = 1;".to_string());
+        history.push("let a = 1;".to_string()); // de-duplicated
+        history.push("  ".to_string()); // ignored
+        history.push("a + 1".to_string());
+        assert_eq!(history.entries(), &["let a = 1;", "a + 1"]);
+
+        assert!(!history.is_navigating());
+        assert_eq!(history.prev(), Some("a + 1"));
+        assert_eq!(history.prev(), Some("let a = 1;"));
+        assert_eq!(history.prev(), None);
+        assert_eq!(history.next(), Some("a + 1"));
+        assert!(history.is_navigating());
+        assert_eq!(history.next(), None);
+        assert!(!history.is_navigating());
+        assert_eq!(history.next(), None);
+
+        // pushing stops navigating
+        history.prev();
+        history.push("a * 2".to_string());
+        assert_eq!(history.prev(), Some("a * 2"));
+
+        assert_eq!(history.search("a", 3), Some(2));
+        assert_eq!(history.search("+", 3), Some(1));
+        assert_eq!(history.search("+", 1), None);
+        assert_eq!(history.search("a", 10), Some(2));
+
+        // capped
+        let history = history.with_max_len(2);
+        assert_eq!(history.entries(), &["a + 1", "a * 2"]);
+    }
+
+    #[test]
+    fn history_save_load_test() {
+        let dir = Path::new("target/testing/history_save_load_test");
+        fs::remove_dir_all(dir).ok();
+        let path = dir.join("history");
+
+        let mut history = History::load(&path).unwrap();
+        assert!(history.entries().is_empty());
+        for i in 0..5 {
+            history.push(format!("{} + 1", i));
+        }
+        history.save().unwrap();
+
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.entries().len(), 5);
+        assert_eq!(&history.entries()[4], "4 + 1");
+
+        let history = History::load(&path).unwrap().with_max_len(3);
+        history.save().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "2 + 1\n3 + 1\n4 + 1\n");
+
+        // in-memory is not saved
+        History::new().save().unwrap();
+
+        // multi-line entries round trip
+        let mut history = History::load(&path).unwrap();
+        history.push("fn a() {\n    \"\\n\"\n}".to_string());
+        history.push("a()".to_string());
+        history.save().unwrap();
+        let history = History::load(&path).unwrap();
+        assert_eq!(history.entries().len(), 5);
+        assert_eq!(&history.entries()[3], "fn a() {\n    \"\\n\"\n}");
+        assert_eq!(&history.entries()[4], "a()");
+    }
+}
@@ -1,10 +1,13 @@
-use super::map_xterm_err;
+use super::{map_xterm_err, History};
 use crate::output::OutputChange;
-use crossbeam_channel::{unbounded, Receiver};
+use crossbeam_channel::{bounded, Receiver, Sender, TrySendError};
 use crossterm as xterm;
 use std::{
+    collections::VecDeque,
     fmt,
     io::{self, stdout, Stdout, Write},
+    sync::atomic::{AtomicBool, Ordering},
+    time::Duration,
 };
 use xterm::{
     cursor::*,
@@ -15,83 +18,562 @@ use xterm::{
     },
     style::Print,
     terminal::{Clear, ClearType},
+    QueueableCommand,
 };
 
+static PLAIN_RENDER: AtomicBool = AtomicBool::new(false);
+
+/// How the terminal interface is rendered.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum RenderMode {
+    /// In-place redraws using cursor movement and colours. This is the default.
+    Terminal,
+    /// Append-only, line based, output with no escape sequences or cursor movement.
+    ///
+    /// Input is not drawn as it is typed, rather it is echoed as a line once accepted. Suitable for
+    /// screen readers, logging, and sinks that do not support ANSI. The REPL switches to this mode
+    /// when stdout is not a terminal.
+    Plain,
+}
+
+/// Set the global render mode of the terminal interface.
+pub fn set_render_mode(mode: RenderMode) {
+    PLAIN_RENDER.store(mode == RenderMode::Plain, Ordering::Relaxed)
+}
+
+/// The global render mode of the terminal interface.
+pub fn render_mode() -> RenderMode {
+    if PLAIN_RENDER.load(Ordering::Relaxed) {
+        RenderMode::Plain
+    } else {
+        RenderMode::Terminal
+    }
+}
+
 /// Terminal screen interface.
 ///
 /// It is as its own struct as there is specific configuration and key handling for moving around the
 /// interface.
-pub struct Screen(Receiver<Event>);
+pub struct Screen {
+    events: Receiver<Event>,
+    cancel: CancelHandle,
+    editor: LineEditor,
+    /// Where the input is drawn, stdout unless set with [`Screen::with_writer`].
+    wtr: Box<dyn Write + Send>,
+    raw_mode: bool,
+    /// Read pasted blocks of code as a single input.
+    ///
+    /// Terminals send a paste as the typed characters, so an `Enter` which is followed by more
+    /// input within [`PASTE_INTERVAL`] is read as a newline in the input instead. This is enabled
+    /// for terminal screens, and disabled for screens from a receiver or stdin lines.
+    paste_detection: bool,
+}
+
+/// A handle to cancel reading input, from any thread.
+///
+/// Cancelling stops the REPL's read loop even while it is waiting on terminal input, such as on
+/// application shutdown. The handle is cheap to clone. Set it with
+/// [`RunCallbacks::with_cancel_handle`](super::RunCallbacks::with_cancel_handle).
+#[derive(Clone)]
+pub struct CancelHandle {
+    tx: Sender<()>,
+    rx: Receiver<()>,
+}
+
+impl CancelHandle {
+    /// A new handle.
+    pub fn new() -> Self {
+        let (tx, rx) = bounded(1);
+        CancelHandle { tx, rx }
+    }
+
+    /// Cancel reading. If a read is not in progress, the next read is cancelled.
+    pub fn cancel(&self) {
+        self.tx.try_send(()).ok(); // already cancelled if full
+    }
+}
+
+impl Default for CancelHandle {
+    fn default() -> Self {
+        CancelHandle::new()
+    }
+}
+
+/// How [`read_until`] returned.
+#[derive(Debug, PartialEq)]
+pub enum ReadOutcome {
+    /// A stop event was received, or the event source disconnected, in which case the last event
+    /// is a `Ctrl+C`.
+    Stopped(Event),
+    /// Reading was cancelled with a [`CancelHandle`].
+    Cancelled,
+    /// `Ctrl+D` was pressed on an empty input, signalling the end of input.
+    Eof,
+}
+
+/// The time within which input following an `Enter` is considered part of the same paste.
+pub const PASTE_INTERVAL: Duration = Duration::from_millis(10);
+
+/// The default number of terminal events that are buffered.
+pub const EVENT_BUFFER_CAPACITY: usize = 1024;
+
+/// An error creating a [`Screen`].
+#[derive(Debug)]
+pub enum ScreenError {
+    /// Raw mode could not be enabled, or terminal events could not be read, such as in some IDE
+    /// integrated terminals or CI. Input can be read from stdin lines instead, see
+    /// [`Screen::from_stdin_lines`].
+    RawModeUnavailable(io::Error),
+    /// The event buffer thread could not be spawned.
+    Io(io::Error),
+}
+
+impl std::error::Error for ScreenError {}
+
+impl fmt::Display for ScreenError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ScreenError::RawModeUnavailable(e) => {
+                write!(f, "terminal raw mode is unavailable: {}", e)
+            }
+            ScreenError::Io(e) => write!(f, "io error occurred: {}", e),
+        }
+    }
+}
+
+impl From<ScreenError> for io::Error {
+    fn from(e: ScreenError) -> Self {
+        match e {
+            ScreenError::RawModeUnavailable(e) | ScreenError::Io(e) => e,
+        }
+    }
+}
 
 impl Screen {
-    pub fn new() -> io::Result<Self> {
-        let (tx, rx) = unbounded();
+    /// A terminal screen, buffering at most [`EVENT_BUFFER_CAPACITY`] events.
+    pub fn new() -> Result<Self, ScreenError> {
+        Screen::with_capacity(EVENT_BUFFER_CAPACITY)
+    }
+
+    /// Buffer at most `capacity` terminal events, see [`buffer_event`] for the policy when the
+    /// buffer is full.
+    ///
+    /// Raw mode and reading events are checked first, returning
+    /// [`ScreenError::RawModeUnavailable`] if the terminal does not support them.
+    pub fn with_capacity(capacity: usize) -> Result<Self, ScreenError> {
+        Screen::with_event_source(
+            capacity,
+            || xterm::terminal::enable_raw_mode().and_then(|_| xterm::terminal::disable_raw_mode()),
+            xterm::event::poll,
+            xterm::event::read,
+        )
+    }
+
+    /// Buffer the events `read` from a source, polling for events with `poll`.
+    fn with_event_source<P, R>(
+        capacity: usize,
+        check_raw_mode: impl FnOnce() -> xterm::Result<()>,
+        mut poll: P,
+        mut read: R,
+    ) -> Result<Self, ScreenError>
+    where
+        P: FnMut(Duration) -> xterm::Result<bool> + Send + 'static,
+        R: FnMut() -> xterm::Result<Event> + Send + 'static,
+    {
+        let unavailable = |e, msg| ScreenError::RawModeUnavailable(map_xterm_err(e, msg));
+        check_raw_mode().map_err(|e| unavailable(e, "enabling raw mode"))?;
+        poll(Duration::from_millis(0)).map_err(|e| unavailable(e, "reading events"))?;
+
+        let (tx, rx) = bounded(capacity);
         std::thread::Builder::new()
             .name("terminal-event-buffer".into())
             .spawn(move || loop {
-                match xterm::event::poll(std::time::Duration::from_millis(5)) {
+                match poll(Duration::from_millis(5)) {
                     Ok(true) => {
-                        if xterm::event::read()
-                            .ok()
-                            .and_then(|ev| tx.send(ev).ok())
-                            .is_none()
-                        {
+                        let buffered = read().map(|ev| buffer_event(&tx, ev)).unwrap_or(false);
+                        if !buffered {
                             break;
                         }
                     }
                     Ok(false) => {}
                     Err(_) => break,
                 }
+            })
+            .map_err(ScreenError::Io)?;
+
+        Ok(Screen {
+            raw_mode: true,
+            paste_detection: true,
+            ..Screen::from_receiver(rx)
+        })
+    }
+
+    /// Read input from the lines of stdin rather than terminal events, for terminals where raw
+    /// mode is unavailable.
+    ///
+    /// Each line is sent as its characters followed by an `Enter`. The end of stdin is an
+    /// interrupt.
+    pub fn from_stdin_lines() -> io::Result<Self> {
+        use io::BufRead;
+
+        let (tx, rx) = bounded(EVENT_BUFFER_CAPACITY);
+        std::thread::Builder::new()
+            .name("stdin-line-reader".into())
+            .spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    let line = match line {
+                        Ok(line) => line,
+                        Err(_) => break,
+                    };
+                    let sent = line_events(&line).all(|ev| tx.send(ev).is_ok());
+                    if !sent {
+                        break;
+                    }
+                }
             })?;
-        Ok(Screen(rx))
+        Ok(Screen::from_receiver(rx))
+    }
+
+    /// The terminal needs to be in raw mode to read from this screen.
+    pub fn raw_mode(&self) -> bool {
+        self.raw_mode
+    }
+
+    /// Use events from the receiver rather than the terminal.
+    ///
+    /// This can be used to drive the interface with a deterministic sequence of events. The events
+    /// are [`crossterm`](crate::crossterm) events, sent on a `crossbeam_channel`.
+    pub fn from_receiver(rx: Receiver<Event>) -> Self {
+        Screen {
+            events: rx,
+            cancel: CancelHandle::new(),
+            editor: LineEditor::new(),
+            wtr: Box::new(stdout()),
+            raw_mode: false,
+            paste_detection: false,
+        }
+    }
+
+    /// Draw the input to `wtr` rather than stdout.
+    ///
+    /// With [`Screen::from_receiver`] this captures what a sequence of events draws.
+    pub fn with_writer<W: Write + Send + 'static>(mut self, wtr: W) -> Self {
+        self.wtr = Box::new(wtr);
+        self
+    }
+
+    /// Cancel reads with `handle`.
+    pub fn with_cancel_handle(mut self, handle: CancelHandle) -> Self {
+        self.cancel = handle;
+        self
+    }
+
+    /// Navigate `history` with the `Up` and `Down` keys.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.editor = self.editor.with_history(history);
+        self
+    }
+
+    /// Keep at most `max` completion candidates, see [`LineEditor::with_max_completions`].
+    pub fn with_max_completions(mut self, max: usize) -> Self {
+        self.editor = self.editor.with_max_completions(max);
+        self
+    }
+
+    /// The input history.
+    pub fn history(&self) -> &History {
+        self.editor.history()
+    }
+
+    /// Add an accepted line to the input history.
+    pub fn push_history(&mut self, line: String) {
+        self.editor.push_history(line);
+    }
+
+    /// Complete the input after a `Tab` stopped [`read_until`], drawing the completion.
+    ///
+    /// Tabbing on the same input cycles through the completions, otherwise `completions` is called
+    /// for new completions of the input.
+    pub fn complete<F, I>(
+        &mut self,
+        initial: (u16, u16),
+        buf: &mut InputBuffer,
+        completions: F,
+    ) -> io::Result<()>
+    where
+        F: FnOnce() -> I,
+        I: IntoIterator<Item = CItem>,
+    {
+        const TAB: Event = Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code: Tab,
+        });
+
+        let prev_lines_covered =
+            text_lines_covered(initial.0 as usize, term_width_nofail(), &buf.buf);
+
+        let editor = &mut self.editor;
+        editor.begin(std::mem::take(buf));
+        let mut action = editor.handle_event(TAB);
+        if action == LineEditorAction::Complete {
+            action = editor.complete(completions());
+        }
+        *buf = editor.take();
+
+        if action == LineEditorAction::Redraw {
+            overwrite_text(
+                &mut self.wtr,
+                initial.0 + 1,
+                prev_lines_covered.saturating_sub(1) as u16,
+                buf.display_text(),
+            )
+            .ok();
+        }
+
+        Ok(())
+    }
+}
+
+/// The key events of typing `line`, followed by an `Enter`.
+///
+/// Tabs are typed as characters rather than `Tab` keys, as they are not completion requests.
+fn line_events(line: &str) -> impl Iterator<Item = Event> + '_ {
+    let key = |code| {
+        Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code,
+        })
+    };
+    line.chars()
+        .map(move |c| key(Char(c)))
+        .chain(Some(key(Enter)))
+}
+
+/// Send an event to the event buffer.
+///
+/// Low priority events (mouse and resize) are dropped if the buffer is full, such that a flood of
+/// these events is coalesced. Other events wait for space. Returns `false` if the receiver has
+/// disconnected.
+fn buffer_event(tx: &Sender<Event>, ev: Event) -> bool {
+    match ev {
+        Mouse(_) | Resize(_, _) => match tx.try_send(ev) {
+            Ok(()) | Err(TrySendError::Full(_)) => true,
+            Err(TrySendError::Disconnected(_)) => false,
+        },
+        ev => tx.send(ev).is_ok(),
+    }
+}
+
+/// The most recently killed (cut) text, which can be yanked (pasted) back.
+///
+/// `Ctrl+K` kills to the end of the line and `Ctrl+W`/`Ctrl+Delete` kill words, `Ctrl+Y` yanks the
+/// most recent kill and `Alt+Y` cycles through older kills. Consecutive kills are joined into one
+/// entry, so repeated `Ctrl+W` presses yank back as a whole.
+#[derive(Debug, Clone)]
+pub struct KillRing {
+    entries: VecDeque<String>,
+    capacity: usize,
+}
+
+impl KillRing {
+    /// A ring holding the last 10 kills.
+    pub fn new() -> Self {
+        KillRing::with_capacity(10)
+    }
+
+    /// A ring holding the last `capacity` kills.
+    pub fn with_capacity(capacity: usize) -> Self {
+        KillRing {
+            entries: VecDeque::with_capacity(capacity),
+            capacity,
+        }
+    }
+
+    /// Add a new entry, dropping the oldest if full. Empty strings are ignored.
+    pub fn push(&mut self, s: String) {
+        if s.is_empty() || self.capacity == 0 {
+            return;
+        }
+        if self.entries.len() == self.capacity {
+            self.entries.pop_back();
+        }
+        self.entries.push_front(s);
+    }
+
+    /// Join `s` to the most recent entry, in front of it if the kill was `backward`. Pushes a new
+    /// entry if the ring is empty.
+    pub fn append(&mut self, s: String, backward: bool) {
+        match self.entries.front_mut() {
+            Some(e) if backward => e.insert_str(0, &s),
+            Some(e) => e.push_str(&s),
+            None => self.push(s),
+        }
+    }
+
+    /// The entry to yank, the most recent unless rotated.
+    pub fn yank(&self) -> Option<&str> {
+        self.entries.front().map(|s| s.as_str())
+    }
+
+    /// Move the yank to the next older entry, wrapping around to the most recent.
+    pub fn rotate(&mut self) {
+        if let Some(e) = self.entries.pop_front() {
+            self.entries.push_back(e);
+        }
+    }
+}
+
+impl Default for KillRing {
+    fn default() -> Self {
+        KillRing::new()
+    }
+}
+
+/// The default maximum number of undo states kept.
+const UNDO_LEN: usize = 200;
+
+/// The behaviour of an [`InputBuffer`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputBufferConfig {
+    /// The maximum number of characters, further inserts are ignored. Defaults to `None`.
+    pub max_len: Option<usize>,
+    /// Draw each character as the mask, such as `*` for a password. The contents are unchanged.
+    /// Defaults to `None`.
+    pub mask: Option<char>,
+    /// The maximum number of edits which can be undone. Defaults to 200.
+    pub undo_len: usize,
+}
+
+impl Default for InputBufferConfig {
+    fn default() -> Self {
+        InputBufferConfig {
+            max_len: None,
+            mask: None,
+            undo_len: UNDO_LEN,
+        }
     }
 }
 
+/// The line of input being edited, and the cursor position within it.
+///
+/// Positions are character indices, not byte indices. Edits can be undone with
+/// [`undo`](InputBuffer::undo).
+#[derive(Default)]
 pub struct InputBuffer {
+    config: InputBufferConfig,
     buf: Vec<char>,
     pos: usize,
+    /// The buffer contents have changed since the dirty flag was last taken.
+    dirty: bool,
+    /// The `(buf, pos)` states before each edit, most recent last.
+    undo: VecDeque<(Vec<char>, usize)>,
+    redo: Vec<(Vec<char>, usize)>,
+    /// The position the next character insert continues the current undo group from.
+    insert_group: Option<usize>,
 }
 
 impl InputBuffer {
+    /// An empty buffer.
     pub fn new() -> Self {
+        InputBuffer::with_config(InputBufferConfig::default())
+    }
+
+    /// An empty buffer with the behaviour set by `config`.
+    pub fn with_config(config: InputBufferConfig) -> Self {
         Self {
+            config,
             buf: Vec::new(),
             pos: 0,
+            dirty: false,
+            undo: VecDeque::new(),
+            redo: Vec::new(),
+            insert_group: None,
         }
     }
 
+    /// The contents.
     pub fn buffer(&self) -> String {
         self.buf.iter().collect()
     }
 
+    /// The character position of the cursor.
+    pub fn pos(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves the cursor to the character position `pos`, saturating at the end of the buffer.
+    pub fn set_pos(&mut self, pos: usize) {
+        self.pos = pos.min(self.buf.len());
+    }
+
+    /// The character at the character index `idx`, if in range.
+    pub fn char_at(&self, idx: usize) -> Option<char> {
+        self.buf.get(idx).copied()
+    }
+
     /// Number of characters.
     pub fn ch_len(&self) -> usize {
         self.buf.len()
     }
 
+    /// Inserts a character at the position, moving the position past it.
+    ///
+    /// Consecutive inserts are undone together.
     pub fn insert(&mut self, ch: char) {
-        self.buf.insert(self.pos, ch);
-        self.pos += 1;
+        if self.is_full() {
+            return;
+        }
+        if self.insert_group != Some(self.pos) {
+            self.checkpoint();
+        }
+        self.insert_char(ch);
+        self.insert_group = Some(self.pos);
     }
 
+    /// Inserts text at the position, moving the position past it.
     pub fn insert_str(&mut self, s: &str) {
+        if s.is_empty() || self.is_full() {
+            return;
+        }
+        self.checkpoint();
         for c in s.chars() {
-            self.insert(c);
+            if self.is_full() {
+                break;
+            }
+            self.insert_char(c);
         }
     }
 
+    /// The buffer has reached the [`max_len`](InputBufferConfig::max_len).
+    fn is_full(&self) -> bool {
+        self.config
+            .max_len
+            .map(|max| self.buf.len() >= max)
+            .unwrap_or(false)
+    }
+
+    fn insert_char(&mut self, ch: char) {
+        self.buf.insert(self.pos, ch);
+        self.pos += 1;
+        self.dirty = true;
+    }
+
     /// Removes from _start_ of position.
     pub fn backspace(&mut self) {
         if self.pos > 0 {
+            self.checkpoint();
             self.pos -= 1;
             self.buf.remove(self.pos);
+            self.dirty = true;
         }
     }
 
     /// Removes from _end_ of position.
     pub fn delete(&mut self) {
         if self.pos < self.buf.len() {
+            self.checkpoint();
             self.buf.remove(self.pos);
+            self.dirty = true;
         }
     }
 
@@ -111,38 +593,309 @@ impl InputBuffer {
         n
     }
 
+    /// Moves the position to the start of the previous word. Returns the number moved.
+    ///
+    /// Whitespace is skipped, then a run of word characters (alphanumeric or `_`) _or_ a run of
+    /// punctuation, so punctuation such as `(`, `.`, or `::` are their own stops.
+    pub fn move_word_left(&mut self) -> usize {
+        let start = self.pos;
+        while self.pos > 0 && self.buf[self.pos - 1].is_whitespace() {
+            self.pos -= 1;
+        }
+        if let Some(class) = self.pos.checked_sub(1).map(|i| char_class(self.buf[i])) {
+            while self.pos > 0 && char_class(self.buf[self.pos - 1]) == class {
+                self.pos -= 1;
+            }
+        }
+        start - self.pos
+    }
+
+    /// Moves the position to the end of the next word. Returns the number moved.
+    ///
+    /// Word boundaries are the same as [`move_word_left`](InputBuffer::move_word_left).
+    pub fn move_word_right(&mut self) -> usize {
+        let start = self.pos;
+        let len = self.buf.len();
+        while self.pos < len && self.buf[self.pos].is_whitespace() {
+            self.pos += 1;
+        }
+        if let Some(&ch) = self.buf.get(self.pos) {
+            let class = char_class(ch);
+            while self.pos < len && char_class(self.buf[self.pos]) == class {
+                self.pos += 1;
+            }
+        }
+        self.pos - start
+    }
+
+    /// Removes the word before the position, like `Ctrl+W` in a shell. Returns the number removed.
+    ///
+    /// Whitespace before the position is removed along with the word, word boundaries are the same
+    /// as [`move_word_left`](InputBuffer::move_word_left).
+    pub fn delete_word_back(&mut self) -> usize {
+        self.kill_word_back().chars().count()
+    }
+
+    /// Removes the word after the position. Returns the number removed.
+    ///
+    /// Whitespace after the position is removed along with the word, word boundaries are the same
+    /// as [`move_word_left`](InputBuffer::move_word_left).
+    pub fn delete_word_forward(&mut self) -> usize {
+        self.kill_word_forward().chars().count()
+    }
+
+    /// Removes the word before the position, returning the removed text.
+    ///
+    /// See [`delete_word_back`](InputBuffer::delete_word_back).
+    pub fn kill_word_back(&mut self) -> String {
+        let end = self.pos;
+        let start = end - self.move_word_left();
+        self.pos = end;
+        self.remove(start..end)
+    }
+
+    /// Removes the word after the position, returning the removed text.
+    ///
+    /// See [`delete_word_forward`](InputBuffer::delete_word_forward).
+    pub fn kill_word_forward(&mut self) -> String {
+        let start = self.pos;
+        let end = start + self.move_word_right();
+        self.pos = start;
+        self.remove(start..end)
+    }
+
+    /// Removes from the position to the end of the buffer, returning the removed text.
+    pub fn kill_to_end(&mut self) -> String {
+        self.remove(self.pos..self.buf.len())
+    }
+
+    /// Replace the contents, moving the position to the end.
+    fn replace(&mut self, s: &str) {
+        self.checkpoint();
+        let max = self.config.max_len.unwrap_or(usize::MAX);
+        self.buf = s.chars().take(max).collect();
+        self.pos = self.buf.len();
+        self.dirty = true;
+    }
+
+    /// Removes the characters in `range`, leaving the position at the start of the range.
+    fn remove(&mut self, range: std::ops::Range<usize>) -> String {
+        if range.is_empty() {
+            return String::new();
+        }
+        self.checkpoint();
+        self.pos = range.start;
+        self.dirty = true;
+        self.buf.drain(range).collect()
+    }
+
+    /// Moves the position to the next occurrence of `ch` after the position.
+    /// Returns if `ch` was found, the position is unchanged if not.
+    pub fn find_forward(&mut self, ch: char) -> bool {
+        let start = (self.pos + 1).min(self.buf.len());
+        match self.buf[start..].iter().position(|&c| c == ch) {
+            Some(idx) => {
+                self.pos = start + idx;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Moves the position to the previous occurrence of `ch` before the position.
+    /// Returns if `ch` was found, the position is unchanged if not.
+    pub fn find_backward(&mut self, ch: char) -> bool {
+        match self.buf[..self.pos].iter().rposition(|&c| c == ch) {
+            Some(idx) => {
+                self.pos = idx;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Shortens the buffer to `ch_pos` characters, moving the position back if past the end.
     pub fn truncate(&mut self, ch_pos: usize) {
+        if ch_pos < self.buf.len() {
+            self.checkpoint();
+            self.dirty = true;
+        }
         self.buf.truncate(ch_pos);
         if self.pos > self.buf.len() {
             self.pos = self.buf.len()
         }
     }
+
+    /// Returns if the buffer contents have changed since the last call, and clears the flag.
+    ///
+    /// Cursor movements do not set the flag.
+    pub fn take_dirty(&mut self) -> bool {
+        std::mem::replace(&mut self.dirty, false)
+    }
+
+    /// Handles an `Enter` in a multi-line block, returning if the input should be submitted.
+    ///
+    /// A new line is started, unless the position is at the end of a blank last line and the
+    /// block closes its delimiters (see [`is_input_complete`](crate::input::is_input_complete)),
+    /// in which case the blank line is removed and the block submitted. Input without newlines is
+    /// always submitted, the REPL asks for more input if it is incomplete.
+    pub fn submit_block(&mut self) -> bool {
+        let last_newline = match self.buf.iter().rposition(|&c| c == '\n') {
+            Some(idx) => idx,
+            None => return true,
+        };
+
+        let blank = self.buf[last_newline + 1..]
+            .iter()
+            .all(|c| c.is_whitespace());
+        let end = self.pos == self.buf.len();
+        if blank && end && crate::input::is_input_complete(&self.buffer()) {
+            self.truncate(last_newline);
+            true
+        } else {
+            self.insert('\n');
+            false
+        }
+    }
+
+    /// Restores the contents and position from before the last edit. Returns if there was an edit
+    /// to undo.
+    pub fn undo(&mut self) -> bool {
+        match self.undo.pop_back() {
+            Some(state) => {
+                let current = self.restore_state(state);
+                self.redo.push(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Reapplies the last undone edit. Returns if there was an edit to redo.
+    ///
+    /// Any edit after an undo clears the edits to redo.
+    pub fn redo(&mut self) -> bool {
+        match self.redo.pop() {
+            Some(state) => {
+                let current = self.restore_state(state);
+                self.undo.push_back(current);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Saves the state before an edit, ending any insert group.
+    fn checkpoint(&mut self) {
+        if self.config.undo_len == 0 {
+            return;
+        }
+        if self.undo.len() == self.config.undo_len {
+            self.undo.pop_front();
+        }
+        self.undo.push_back((self.buf.clone(), self.pos));
+        self.redo.clear();
+        self.insert_group = None;
+    }
+
+    /// Sets the contents and position, returning the replaced state.
+    fn restore_state(&mut self, (buf, pos): (Vec<char>, usize)) -> (Vec<char>, usize) {
+        self.insert_group = None;
+        self.dirty = true;
+        let pos = std::mem::replace(&mut self.pos, pos);
+        (std::mem::replace(&mut self.buf, buf), pos)
+    }
+}
+
+/// Classes of characters used for word movement.
+#[derive(PartialEq)]
+enum CharClass {
+    Whitespace,
+    Word,
+    Punctuation,
+}
+
+fn char_class(ch: char) -> CharClass {
+    if ch.is_whitespace() {
+        CharClass::Whitespace
+    } else if ch.is_alphanumeric() || ch == '_' {
+        CharClass::Word
+    } else {
+        CharClass::Punctuation
+    }
+}
+
+impl InputBuffer {
+    /// The contents as drawn in raw mode, where a newline does not return the cursor to the start
+    /// of the line.
+    fn display_text(&self) -> String {
+        self.to_string().replace('\n', "\r\n")
+    }
 }
 
+/// Displays the contents, masked if there is a [`mask`](InputBufferConfig::mask).
 impl fmt::Display for InputBuffer {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        for ch in &self.buf {
-            write!(f, "{}", ch)?;
+        for &ch in &self.buf {
+            match self.config.mask {
+                Some(mask) if ch != '\n' => write!(f, "{}", mask)?,
+                _ => write!(f, "{}", ch)?,
+            }
         }
         Ok(())
     }
 }
 
+/// A completion item.
 pub struct CItem {
+    /// The text to insert.
     pub matchstr: String,
+    /// The character position in the input the text replaces from.
     pub input_chpos: usize,
 }
 
-#[derive(Default)]
 pub struct CompletionWriter {
     input_line: String,
     completions: Vec<CItem>,
     completion_idx: usize,
+    max_candidates: usize,
+    /// The candidates' common prefix should be written before cycling.
+    extend_prefix: bool,
+    /// The common prefix written, the next completion is the first candidate.
+    prefix: Option<String>,
 }
 
 impl CompletionWriter {
     pub fn new() -> Self {
-        Default::default()
+        CompletionWriter {
+            input_line: String::new(),
+            completions: Vec::new(),
+            completion_idx: 0,
+            max_candidates: usize::MAX,
+            extend_prefix: false,
+            prefix: None,
+        }
+    }
+
+    /// Keep at most `max` completion candidates. Candidates past the cap are not consumed from
+    /// the completions iterator.
+    pub fn with_max_candidates(mut self, max: usize) -> Self {
+        self.max_candidates = max;
+        self
+    }
+
+    /// Clear the completions, keeping the configuration.
+    fn clear(&mut self) {
+        self.input_line.clear();
+        self.completions.clear();
+        self.completion_idx = 0;
+        self.extend_prefix = false;
+        self.prefix = None;
+    }
+
+    /// The completion candidates.
+    pub fn candidates(&self) -> impl Iterator<Item = &str> {
+        self.completions.iter().map(|c| c.matchstr.as_str())
     }
 
     pub fn is_same_input(&self, line: &str) -> bool {
@@ -150,6 +903,13 @@ impl CompletionWriter {
     }
 
     pub fn next_completion(&mut self) {
+        // the first candidate follows the common prefix, unless it is the common prefix
+        if let Some(prefix) = self.prefix.take() {
+            if self.completions.first().map(|c| c.matchstr != prefix) == Some(true) {
+                return;
+            }
+        }
+
         let idx = self.completion_idx + 1;
         let idx = if idx >= self.completions.len() {
             0
@@ -161,69 +921,444 @@ impl CompletionWriter {
 
     pub fn new_completions<I: Iterator<Item = CItem>>(&mut self, completions: I) {
         self.completions.clear();
-        for c in completions {
-            self.completions.push(c)
-        }
+        self.completions
+            .extend(completions.take(self.max_candidates));
         self.completion_idx = 0;
+        self.extend_prefix = self.completions.len() > 1;
+        self.prefix = None;
     }
 
-    pub fn overwrite_completion(
-        &mut self,
-        initial: (u16, u16),
-        buf: &mut InputBuffer,
-    ) -> io::Result<()> {
-        let completion = self.completions.get(self.completion_idx);
-
-        if let Some(CItem {
-            matchstr,
-            input_chpos,
-        }) = completion
-        {
-            let prev_lines_covered =
-                lines_covered(initial.0 as usize, term_width_nofail(), buf.ch_len());
-            buf.truncate(*input_chpos);
-            buf.insert_str(matchstr);
-            let buf = buf.buffer();
-            overwrite_text(
-                initial.0 + 1,
-                prev_lines_covered.saturating_sub(1) as u16,
-                &buf,
-            )
-            .ok();
-            self.input_line = buf;
+    /// Writes the current completion into the buffer, without drawing.
+    /// Returns if there was a completion to write.
+    ///
+    /// For new completions with multiple candidates, the common prefix of the candidates is
+    /// written first if it extends the input.
+    fn apply_completion(&mut self, buf: &mut InputBuffer) -> bool {
+        if std::mem::take(&mut self.extend_prefix) {
+            let prefix = common_prefix(&self.completions);
+            let chpos = self.completions[0].input_chpos;
+            if prefix.chars().count() > buf.ch_len().saturating_sub(chpos) {
+                buf.truncate(chpos);
+                buf.insert_str(&prefix);
+                self.input_line = buf.buffer();
+                self.prefix = Some(prefix);
+                return true;
+            }
         }
 
-        Ok(())
+        match self.completions.get(self.completion_idx) {
+            Some(CItem {
+                matchstr,
+                input_chpos,
+            }) => {
+                buf.truncate(*input_chpos);
+                buf.insert_str(matchstr);
+                self.input_line = buf.buffer();
+                true
+            }
+            None => false,
+        }
     }
 }
 
-fn apply_event_to_buf(mut buf: InputBuffer, event: Event) -> (InputBuffer, bool) {
-    const NOMOD: KeyModifiers = KeyModifiers::empty();
-    macro_rules! nomod {
-        ($code:ident) => {
-            KeyEvent {
-                modifiers: NOMOD,
-                code: $code,
-            }
+impl Default for CompletionWriter {
+    fn default() -> Self {
+        CompletionWriter::new()
+    }
+}
+
+/// The longest common prefix of the candidates' text. Candidates which replace from different
+/// positions have no common prefix.
+fn common_prefix(candidates: &[CItem]) -> String {
+    let (first, rest) = match candidates.split_first() {
+        Some(x) => x,
+        None => return String::new(),
+    };
+    if rest.iter().any(|c| c.input_chpos != first.input_chpos) {
+        return String::new();
+    }
+
+    let mut len = first.matchstr.len();
+    for c in rest {
+        len = first
+            .matchstr
+            .char_indices()
+            .zip(c.matchstr.chars())
+            .take_while(|((_, a), b)| a == b)
+            .last()
+            .map(|((idx, ch), _)| (idx + ch.len_utf8()).min(len))
+            .unwrap_or(0);
+    }
+
+    first.matchstr[..len].to_string()
+}
+
+/// The action a front-end should take after a [`LineEditor`] has handled an event.
+#[derive(Debug, PartialEq)]
+pub enum LineEditorAction {
+    /// Nothing has changed.
+    None,
+    /// The line or cursor has changed and should be redrawn.
+    Redraw,
+    /// The line has been submitted. The editor is cleared for the next line.
+    ///
+    /// An `Enter` within an unfinished block of lines starts a new line instead, see
+    /// [`InputBuffer::submit_block`].
+    Submit(String),
+    /// The line has been cancelled (`Ctrl+C`).
+    Cancel,
+    /// `Ctrl+D` was pressed on an empty line, signalling the end of input.
+    Eof,
+    /// Completions are requested for the current line.
+    /// Supply them with [`LineEditor::complete`].
+    Complete,
+}
+
+/// A line editor composing the input buffer, completion, history, and kill ring state.
+///
+/// The editor does not draw to the terminal, rather it returns a [`LineEditorAction`] for each
+/// event which the front-end acts on. This gives a single entry point for key handling.
+#[derive(Default)]
+pub struct LineEditor {
+    buf: InputBuffer,
+    completer: CompletionWriter,
+    char_search: Option<bool>,
+    kill_ring: KillRing,
+    history: History,
+    /// The in-progress input while navigating the history.
+    history_draft: Option<String>,
+    // consecutive kills are joined, consecutive yanks can be rotated
+    last_kill: Option<bool>,
+    last_yank: Option<std::ops::Range<usize>>,
+    search: Option<ReverseSearch>,
+}
+
+impl LineEditor {
+    /// A new, empty, line editor.
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Keep at most `max` completion candidates supplied to [`complete`](LineEditor::complete).
+    /// By default all the candidates are kept.
+    pub fn with_max_completions(mut self, max: usize) -> Self {
+        self.completer = self.completer.with_max_candidates(max);
+        self
+    }
+
+    /// Navigate `history` with the `Up` and `Down` keys, and search it with `Ctrl+R`.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = history;
+        self
+    }
+
+    /// The input history.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Add an accepted line to the input history.
+    pub fn push_history(&mut self, line: String) {
+        self.history_draft = None;
+        self.history.push(line);
+    }
+
+    /// The current line.
+    pub fn line(&self) -> String {
+        self.buf.buffer()
+    }
+
+    /// The completion candidates supplied to [`complete`](LineEditor::complete), which can be
+    /// listed once the input has been extended to their common prefix.
+    pub fn candidates(&self) -> Vec<&str> {
+        self.completer.candidates().collect()
+    }
+
+    /// The character position of the cursor.
+    pub fn position(&self) -> usize {
+        self.buf.pos
+    }
+
+    /// Insert text at the cursor position, such as an auto-indent.
+    pub fn insert_str(&mut self, s: &str) {
+        self.buf.insert_str(s)
+    }
+
+    /// Stash the in-progress input, leaving the editor empty.
+    ///
+    /// This allows bailing out of a partially written input, say to run a quick command, and
+    /// restoring it later with [`unstash`](LineEditor::unstash).
+    pub fn stash(&mut self) -> InputBuffer {
+        self.completer.clear();
+        let buf = self.take();
+        self.begin(InputBuffer::new());
+        buf
+    }
+
+    /// Restore a stashed input, replacing the current input. The cursor position is restored.
+    pub fn unstash(&mut self, buf: InputBuffer) {
+        self.completer.clear();
+        self.begin(buf);
+        self.buf.dirty = true; // needs to be redrawn
+    }
+
+    /// Start editing `buf`, keeping the completions so tabbing on the same input cycles them.
+    fn begin(&mut self, buf: InputBuffer) {
+        self.buf = buf;
+        self.char_search = None;
+        self.last_kill = None;
+        self.last_yank = None;
+        self.search = None;
+    }
+
+    /// Take the input, restoring the original input if a search is unfinished.
+    fn take(&mut self) -> InputBuffer {
+        if let Some(search) = self.search.take() {
+            self.buf = search.restore();
+        }
+        std::mem::take(&mut self.buf)
+    }
+
+    /// Handle a terminal event.
+    pub fn handle_event(&mut self, event: Event) -> LineEditorAction {
+        const NOMOD: KeyModifiers = KeyModifiers::empty();
+
+        if self.handle_search(&event) {
+            return LineEditorAction::Redraw;
+        }
+
+        let pos = self.buf.pos;
+
+        match event {
+            ev if eof_event(&ev) && self.buf.ch_len() == 0 => return LineEditorAction::Eof,
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Enter,
+            }) => {
+                self.last_kill = None;
+                self.last_yank = None;
+                if self.buf.submit_block() {
+                    self.completer.clear();
+                    return LineEditorAction::Submit(self.take().buffer());
+                }
+            }
+            Key(KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: Char('c'),
+            }) => return LineEditorAction::Cancel,
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Tab,
+            }) => {
+                self.last_kill = None;
+                self.last_yank = None;
+                if !self.completer.is_same_input(&self.buf.buffer()) {
+                    return LineEditorAction::Complete;
+                }
+                self.completer.next_completion();
+                self.completer.apply_completion(&mut self.buf);
+            }
+            ev => self.edit(ev),
+        }
+
+        if self.buf.take_dirty() || self.buf.pos != pos {
+            LineEditorAction::Redraw
+        } else {
+            LineEditorAction::None
+        }
+    }
+
+    /// Supply the completions for the current line, after a [`LineEditorAction::Complete`].
+    ///
+    /// If there are multiple completions which share a prefix longer than what has been typed,
+    /// the line is extended to the prefix. Otherwise the first completion is written into the
+    /// line. Further `Tab` events cycle through the completions.
+    pub fn complete<I: IntoIterator<Item = CItem>>(&mut self, completions: I) -> LineEditorAction {
+        self.completer.new_completions(completions.into_iter());
+        if self.completer.apply_completion(&mut self.buf) {
+            self.buf.take_dirty();
+            LineEditorAction::Redraw
+        } else {
+            LineEditorAction::None
+        }
+    }
+
+    /// Handle the event if a reverse search is in progress, returning if it was consumed.
+    ///
+    /// The search display is written in place of the input until the search finishes. Events
+    /// which finish the search without being part of it are not consumed.
+    fn handle_search(&mut self, event: &Event) -> bool {
+        let mut s = match self.search.take() {
+            Some(s) => s,
+            None => return false,
+        };
+
+        let action = s.handle_event(event, &self.history);
+        match action {
+            SearchAction::Continue => {
+                self.buf.replace(&s.display(&self.history));
+                self.search = Some(s);
+            }
+            SearchAction::Restore => self.buf = s.restore(),
+            SearchAction::Accept | SearchAction::AcceptAndHandle => {
+                self.buf = s.accept(&self.history)
+            }
+        }
+
+        action != SearchAction::AcceptAndHandle
+    }
+
+    /// Apply an editing event to the input: moving, searching, history navigation, killing and
+    /// yanking, and inserting.
+    fn edit(&mut self, event: Event) {
+        let buf = &mut self.buf;
+        let kill = kill_event(&event);
+        let yank = yank_event(&event);
+        let last_kill = self.last_kill.take();
+        let last_yank = self.last_yank.take();
+
+        if let Some(forward) = self.char_search.take() {
+            if let Key(KeyEvent { code: Char(c), .. }) = event {
+                if forward {
+                    buf.find_forward(c);
+                } else {
+                    buf.find_backward(c);
+                }
+            }
+        } else if let Some(forward) = char_search_event(&event) {
+            self.char_search = Some(forward);
+        } else if search_event(&event) {
+            let s = ReverseSearch::new(std::mem::take(buf));
+            buf.replace(&s.display(&self.history));
+            self.search = Some(s);
+        } else if let Some(up) = history_event(&event) {
+            // the in-progress input is kept to return to once past the latest entry
+            if up && !self.history.is_navigating() {
+                self.history_draft = Some(buf.buffer());
+            }
+            let entry = if up {
+                self.history.prev()
+            } else {
+                self.history.next()
+            };
+            match entry {
+                Some(entry) => buf.replace(entry),
+                None if !up => {
+                    if let Some(draft) = self.history_draft.take() {
+                        buf.replace(&draft);
+                    }
+                }
+                None => (),
+            }
+        } else if let Some(kill) = kill {
+            let (killed, backward) = match kill {
+                Kill::WordBack => (buf.kill_word_back(), true),
+                Kill::WordForward => (buf.kill_word_forward(), false),
+                Kill::ToEnd => (buf.kill_to_end(), false),
+            };
+            if last_kill == Some(backward) {
+                self.kill_ring.append(killed, backward);
+            } else {
+                self.kill_ring.push(killed);
+            }
+        } else if let Some(rotate) = yank {
+            // rotating replaces the previous yank, and does nothing without one
+            if let (true, Some(prev)) = (rotate, last_yank.clone()) {
+                buf.remove(prev);
+                self.kill_ring.rotate();
+            }
+            if !rotate || last_yank.is_some() {
+                if let Some(s) = self.kill_ring.yank() {
+                    let start = buf.pos;
+                    buf.insert_str(s);
+                    self.last_yank = Some(start..buf.pos);
+                }
+            }
+        } else {
+            self.buf = apply_event_to_buf(std::mem::take(buf), event);
+        }
+
+        self.last_kill = kill.map(|k| k == Kill::WordBack);
+    }
+}
+
+fn apply_event_to_buf(mut buf: InputBuffer, event: Event) -> InputBuffer {
+    const NOMOD: KeyModifiers = KeyModifiers::empty();
+    macro_rules! nomod {
+        ($code:ident) => {
+            KeyEvent {
+                modifiers: NOMOD,
+                code: $code,
+            }
         };
     }
 
-    let cmd = match event {
+    match event {
         Key(nomod!(Left)) => {
             buf.move_pos_left(1);
-            false
         }
         Key(nomod!(Right)) => {
             buf.move_pos_right(1);
-            false
         }
-        Key(nomod!(Backspace)) => {
-            buf.backspace();
-            true
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Left,
+        }) => {
+            buf.move_word_left();
         }
-        Key(nomod!(Delete)) => {
-            buf.delete();
-            true
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Right,
+        }) => {
+            buf.move_word_right();
+        }
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('w'),
+        })
+        | Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Backspace,
+        }) => {
+            buf.delete_word_back();
+        }
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Delete,
+        }) => {
+            buf.delete_word_forward();
+        }
+        Key(nomod!(Home))
+        | Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('a'),
+        }) => buf.pos = 0,
+        Key(nomod!(End))
+        | Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('e'),
+        }) => buf.pos = buf.buf.len(),
+        Key(nomod!(Backspace)) => buf.backspace(),
+        Key(nomod!(Delete))
+        | Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('d'),
+        }) => buf.delete(),
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('z'),
+        }) => {
+            buf.undo();
+        }
+        Key(KeyEvent {
+            modifiers,
+            code: Char('z'),
+        })
+        | Key(KeyEvent {
+            modifiers,
+            code: Char('Z'),
+        }) if modifiers == KeyModifiers::CONTROL | KeyModifiers::SHIFT => {
+            buf.redo();
         }
         Key(KeyEvent {
             modifiers: NOMOD,
@@ -232,97 +1367,783 @@ fn apply_event_to_buf(mut buf: InputBuffer, event: Event) -> (InputBuffer, bool)
         | Key(KeyEvent {
             modifiers: KeyModifiers::SHIFT,
             code: Char(c),
-        }) => {
-            buf.insert(c);
-            true
+        }) => buf.insert(c),
+        _ => (),
+    }
+
+    buf
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum Kill {
+    WordBack,
+    WordForward,
+    ToEnd,
+}
+
+/// A reverse incremental search over the history, like `Ctrl+R` in `bash`.
+///
+/// Matches are substrings of the history entries, searching most recent first.
+struct ReverseSearch {
+    query: String,
+    /// The index of the matched history entry.
+    found: Option<usize>,
+    /// The last search for the query failed.
+    failed: bool,
+    /// The input before searching.
+    original: InputBuffer,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq)]
+enum SearchAction {
+    /// Keep searching.
+    Continue,
+    /// Accept the match.
+    Accept,
+    /// Restore the original input.
+    Restore,
+    /// Accept the match, and handle the event as normal input.
+    AcceptAndHandle,
+}
+
+impl ReverseSearch {
+    fn new(original: InputBuffer) -> Self {
+        ReverseSearch {
+            query: String::new(),
+            found: None,
+            failed: false,
+            original,
         }
-        _ => false,
-    };
+    }
+
+    fn handle_event(&mut self, event: &Event, history: &History) -> SearchAction {
+        const NOMOD: KeyModifiers = KeyModifiers::empty();
+        let len = history.entries().len();
+
+        if search_event(event) {
+            // cycle to older matches
+            let before = self.found.unwrap_or(len);
+            self.search(history, before);
+            return SearchAction::Continue;
+        }
+
+        match event {
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Char(c),
+            })
+            | Key(KeyEvent {
+                modifiers: KeyModifiers::SHIFT,
+                code: Char(c),
+            }) => {
+                // narrowing can keep the current match
+                self.query.push(*c);
+                let before = self.found.map(|i| i + 1).unwrap_or(len);
+                self.search(history, before);
+                SearchAction::Continue
+            }
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Backspace,
+            }) => {
+                self.query.pop();
+                self.found = None;
+                self.search(history, len);
+                SearchAction::Continue
+            }
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Enter,
+            }) => SearchAction::Accept,
+            Key(KeyEvent {
+                modifiers: NOMOD,
+                code: Esc,
+            }) => SearchAction::Restore,
+            _ => SearchAction::AcceptAndHandle,
+        }
+    }
+
+    /// Search for the query in the entries before the index `before`, keeping the current match
+    /// if not found.
+    fn search(&mut self, history: &History, before: usize) {
+        if self.query.is_empty() {
+            self.failed = false;
+            return;
+        }
+
+        match history.search(&self.query, before) {
+            Some(idx) => {
+                self.found = Some(idx);
+                self.failed = false;
+            }
+            None => self.failed = true,
+        }
+    }
+
+    fn matched<'a>(&self, history: &'a History) -> Option<&'a str> {
+        self.found
+            .and_then(|i| history.entries().get(i))
+            .map(|s| s.as_str())
+    }
+
+    /// The search prompt and the matched entry, ie ``(reverse-i-search)`a': let a = 1;``.
+    fn display(&self, history: &History) -> String {
+        format!(
+            "({}reverse-i-search)`{}': {}",
+            if self.failed { "failed " } else { "" },
+            self.query,
+            self.matched(history).unwrap_or_default()
+        )
+    }
+
+    /// The matched entry, with the position at the start of the match. If there is no match the
+    /// original input is returned.
+    fn accept(self, history: &History) -> InputBuffer {
+        match self.matched(history) {
+            Some(line) => {
+                let mut buf = self.original;
+                buf.replace(line);
+                if let Some(idx) = line.find(&self.query) {
+                    buf.pos = line[..idx].chars().count();
+                }
+                buf
+            }
+            None => self.restore(),
+        }
+    }
+
+    /// The original input, which needs to be redrawn.
+    fn restore(self) -> InputBuffer {
+        let mut buf = self.original;
+        buf.dirty = true;
+        buf
+    }
+}
+
+/// The end of input key binding, `Ctrl+D`. On a non-empty input it deletes the character under
+/// the cursor, like `bash`.
+fn eof_event(event: &Event) -> bool {
+    *event
+        == Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('d'),
+        })
+}
+
+/// The reverse search key binding, `Ctrl+R`.
+fn search_event(event: &Event) -> bool {
+    *event
+        == Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('r'),
+        })
+}
+
+/// History key bindings, `Up` returns `Some(true)` and `Down` returns `Some(false)`.
+fn history_event(event: &Event) -> Option<bool> {
+    const NOMOD: KeyModifiers = KeyModifiers::empty();
+    match event {
+        Key(KeyEvent {
+            modifiers: NOMOD,
+            code: Up,
+        }) => Some(true),
+        Key(KeyEvent {
+            modifiers: NOMOD,
+            code: Down,
+        }) => Some(false),
+        _ => None,
+    }
+}
+
+/// Kill key bindings, like `bash`.
+fn kill_event(event: &Event) -> Option<Kill> {
+    match event {
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code,
+        }) => match code {
+            Char('w') | Backspace => Some(Kill::WordBack),
+            Delete => Some(Kill::WordForward),
+            Char('k') => Some(Kill::ToEnd),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+/// Yank key bindings, like `bash`. `Ctrl+Y` yanks and `Alt+Y` rotates, returning `Some(true)`.
+fn yank_event(event: &Event) -> Option<bool> {
+    match event {
+        Key(KeyEvent {
+            modifiers: KeyModifiers::CONTROL,
+            code: Char('y'),
+        }) => Some(false),
+        Key(KeyEvent {
+            modifiers: KeyModifiers::ALT,
+            code: Char('y'),
+        }) => Some(true),
+        _ => None,
+    }
+}
 
-    (buf, cmd)
+/// Character search key bindings, like `bash`.
+///
+/// `Ctrl+]` searches forward and `Ctrl+Alt+]` searches backward, for the next character typed.
+/// Returns `Some(true)` for a forward search.
+fn char_search_event(event: &Event) -> Option<bool> {
+    match event {
+        // Ctrl+] is reported as Ctrl+5 on some terminals
+        Key(KeyEvent {
+            modifiers,
+            code: Char(']'),
+        })
+        | Key(KeyEvent {
+            modifiers,
+            code: Char('5'),
+        }) if modifiers.contains(KeyModifiers::CONTROL) => {
+            Some(!modifiers.contains(KeyModifiers::ALT))
+        }
+        _ => None,
+    }
 }
 
-fn overwrite_text<T: fmt::Display + Clone>(
+fn overwrite_text<W: Write, T: fmt::Display + Clone>(
+    wtr: &mut W,
     initialx: u16,
     lines_covered: u16,
     text: T,
 ) -> xterm::Result<()> {
-    let mut stdout = stdout();
+    if render_mode() == RenderMode::Plain {
+        return Ok(()); // accepted input is echoed instead
+    }
+
     // still moves up if lines covered is zero, unsure if crossterm bug and might be changed
     if lines_covered > 0 {
         for _ in 0..lines_covered {
-            queue!(stdout, Clear(ClearType::CurrentLine), MoveUp(1))?;
+            queue!(wtr, Clear(ClearType::CurrentLine), MoveUp(1))?;
         }
     }
     queue!(
-        stdout,
+        wtr,
         MoveToColumn(initialx),
         Clear(ClearType::UntilNewLine),
         Print(text)
     )?;
 
-    stdout.flush().map_err(|e| xterm::ErrorKind::IoError(e))
+    wtr.flush().map_err(|e| xterm::ErrorKind::IoError(e))
+}
+
+/// Move the cursor from `from_up` rows above the last line of the input to `to_up` rows above,
+/// and to the column `col`.
+fn move_cursor<W: Write>(
+    wtr: &mut W,
+    from_up: usize,
+    to_up: usize,
+    col: usize,
+) -> xterm::Result<()> {
+    if render_mode() == RenderMode::Plain {
+        return Ok(());
+    }
+
+    if to_up > from_up {
+        wtr.queue(MoveUp((to_up - from_up) as u16))?;
+    } else if from_up > to_up {
+        wtr.queue(MoveDown((from_up - to_up) as u16))?;
+    }
+    wtr.queue(MoveToColumn(col as u16 + 1))?;
+
+    wtr.flush().map_err(xterm::ErrorKind::IoError)
+}
+
+/// The number of rows above the last line of the text, and the column, the character position
+/// `pos` is drawn at. The text has `len` characters and starts at the column `start`.
+///
+/// When the text fills its last line the terminal holds the cursor in the last column, so the end
+/// of the text is placed there.
+///
+/// Panics if width is zero.
+fn cursor_offset(start: usize, width: usize, len: usize, pos: usize) -> (usize, usize) {
+    let end = start + len;
+    let (rows, col) = (end / width, end % width);
+    let fills = len > 0 && col == 0;
+    let end_row = if fills { rows - 1 } else { rows };
+
+    if pos >= len && fills {
+        (0, width - 1)
+    } else {
+        let idx = start + pos.min(len);
+        (end_row - idx / width, idx % width)
+    }
 }
 
+/// Read events from the screen into the input buffer, editing and redrawing it, until one of the
+/// stop `events` is received.
+///
+/// `initial` is the cursor position where the buffer is drawn from, after the prompt. The edited
+/// buffer is returned with how reading finished.
 pub fn read_until(
     screen: &mut Screen,
     initial: (u16, u16),
-    mut buf: InputBuffer,
+    buf: InputBuffer,
     events: &[Event],
-) -> (InputBuffer, Event) {
-    let reader = &mut screen.0;
+) -> (InputBuffer, ReadOutcome) {
+    const ENTER: Event = Key(KeyEvent {
+        modifiers: KeyModifiers::empty(),
+        code: Enter,
+    });
+
+    let reader = &screen.events;
+    let cancel = &screen.cancel.rx;
+    let paste_detection = screen.paste_detection;
+    let editor = &mut screen.editor;
+    let wtr = &mut screen.wtr;
     let mut last = Event::Key(KeyEvent {
         modifiers: KeyModifiers::CONTROL,
         code: xterm::event::KeyCode::Char('c'),
     });
+    // a dirty buffer has not been drawn yet
+    let mut drawn_lines_covered = if buf.dirty {
+        0
+    } else {
+        text_lines_covered(initial.0 as usize, term_width_nofail(), &buf.buf)
+    };
+    // the cursor is drawn at the end of the text, unless moved to the buffer position
+    let mut cursor = (buf.ch_len(), 0);
+    // parking moves the cursor to the end of the text, where the next output is written from
+    let mut redraw = |buf: &mut InputBuffer, park: bool| {
+        let start = initial.0 as usize;
+        let width = term_width_nofail();
+        if buf.take_dirty() {
+            // text is overwritten from the last line it covers
+            move_cursor(wtr, cursor.1, 0, 0).ok();
+            overwrite_text(
+                wtr,
+                initial.0 + 1,
+                drawn_lines_covered.saturating_sub(1) as u16,
+                buf.display_text(),
+            )
+            .ok();
+            drawn_lines_covered = text_lines_covered(start, width, &buf.buf);
+            cursor = (buf.ch_len(), 0);
+        }
+
+        let pos = if park { buf.ch_len() } else { buf.pos };
+        if pos != cursor.0 {
+            let (up, col) = text_cursor_offset(start, width, &buf.buf, pos);
+            move_cursor(wtr, cursor.1, up, col).ok();
+            cursor = (pos, up);
+        }
+    };
+
+    editor.begin(buf);
+    redraw(&mut editor.buf, false);
+
+    // an event received while checking for a paste
+    let mut pending = None;
+    let mut eof = false;
 
     loop {
-        if let Ok(ev) = reader.recv() {
+        let ev = match pending.take() {
+            Some(ev) => Ok(ev),
+            None => crossbeam_channel::select! {
+                recv(reader) -> ev => ev,
+                recv(cancel) -> _ => {
+                    let mut buf = editor.take();
+                    redraw(&mut buf, true);
+                    return (buf, ReadOutcome::Cancelled);
+                }
+            },
+        };
+
+        if let Ok(ev) = ev {
             last = ev.clone();
+
+            if editor.handle_search(&ev) {
+                redraw(&mut editor.buf, false);
+                continue;
+            }
+
+            if eof_event(&ev) && editor.buf.ch_len() == 0 {
+                eof = true;
+                break;
+            }
+
             if events.contains(&ev) {
+                if ev == ENTER {
+                    if paste_detection {
+                        if let Ok(next) = reader.recv_timeout(PASTE_INTERVAL) {
+                            editor.buf.insert('\n');
+                            pending = Some(next);
+                            continue;
+                        }
+                    }
+                    if !editor.buf.submit_block() {
+                        redraw(&mut editor.buf, false);
+                        continue;
+                    }
+                }
                 break;
             }
 
-            let prev_lines_covered =
-                lines_covered(initial.0 as usize, term_width_nofail(), buf.ch_len());
-            let (newbuf, chg) = apply_event_to_buf(buf, ev);
-            if chg {
-                overwrite_text(
-                    initial.0 + 1,
-                    prev_lines_covered.saturating_sub(1) as u16,
-                    &newbuf,
-                )
-                .ok();
+            editor.edit(ev);
+
+            // batch the redraws while there are still events queued
+            if reader.is_empty() {
+                redraw(&mut editor.buf, false);
             }
-            buf = newbuf
         } else {
             break;
         }
     }
 
-    (buf, last)
-}
+    let mut buf = editor.take();
+    redraw(&mut buf, true);
 
-/// Returns the number of lines the written text accounts for
-pub fn write_output_chg(current_lines_covered: u16, change: OutputChange) -> io::Result<u16> {
-    use OutputChange::*;
-    let mut stdout = stdout();
-    match change {
-        CurrentLine(line) => {
-            for _ in 1..current_lines_covered {
-                queue!(stdout, Clear(ClearType::CurrentLine), MoveUp(1))
-                    .map_err(|e| map_xterm_err(e, "Clear a line"))?;
-            }
-            let mut stdout = erase_current_line(stdout)?;
-            queue!(stdout, Print(&line)).map_err(|e| map_xterm_err(e, "printing a line"))?;
-            stdout.flush()?;
-            Ok(lines_covered(0, term_width_nofail(), line.chars().count()) as u16)
-        }
-        NewLine => writeln!(&mut stdout, "").map(|_| 1),
-    }
+    let outcome = if eof {
+        ReadOutcome::Eof
+    } else {
+        ReadOutcome::Stopped(last)
+    };
+    (buf, outcome)
 }
 
-/// Resets position to start of line.
+/// Writes output changes to the terminal, keeping track of the number of lines the current output
+/// line covers.
+///
+/// If neither the drawn line nor its update wrap, only the changed tail of the line is redrawn.
+///
+/// If the writer is not a terminal, such as when stdout is redirected to a file or pipe, the
+/// changes are appended without any cursor movement.
+pub struct OutputCursor<W> {
+    wtr: W,
+    tty: bool,
+    lines_covered: u16,
+    /// The current line as drawn.
+    line: String,
+}
+
+impl OutputCursor<Stdout> {
+    /// Write to stdout.
+    pub fn new() -> Self {
+        let tty = atty::is(atty::Stream::Stdout);
+        OutputCursor::with_writer(stdout(), tty)
+    }
+}
+
+impl<W: Write> OutputCursor<W> {
+    /// Write to `wtr`, which is a terminal if `tty` is set.
+    pub fn with_writer(wtr: W, tty: bool) -> Self {
+        OutputCursor {
+            wtr,
+            tty,
+            lines_covered: 0,
+            line: String::new(),
+        }
+    }
+
+    /// Write the change. If writing fails the covered lines are reset.
+    pub fn write(&mut self, change: OutputChange) -> io::Result<()> {
+        let change = match change {
+            OutputChange::CurrentLine(line) => {
+                let prev = std::mem::replace(&mut self.line, line.clone());
+                let width = display_width(&line);
+                if self.lines_covered == 1 && lines_covered(0, term_width_nofail(), width) == 1 {
+                    DrawChange::patch(&prev, &line)
+                } else {
+                    DrawChange::CurrentLine(line)
+                }
+            }
+            OutputChange::NewLine => {
+                self.line.clear();
+                DrawChange::NewLine
+            }
+        };
+        self.draw(change)
+    }
+
+    /// Redraw the whole current line, such as after something was drawn over it.
+    pub fn redraw(&mut self) -> io::Result<()> {
+        self.draw(DrawChange::CurrentLine(self.line.clone()))
+    }
+
+    fn draw(&mut self, change: DrawChange) -> io::Result<()> {
+        let r = write_output_chg_to(
+            &mut self.wtr,
+            self.tty,
+            self.lines_covered,
+            term_height_nofail(),
+            change,
+        );
+        self.lines_covered = *r.as_ref().unwrap_or(&0);
+        r.map(|_| ())
+    }
+}
+
+const SPINNER_FRAMES: [char; 4] = ['|', '/', '-', '\\'];
+
+/// An animated compilation indicator, drawn in place of the compiler's progress lines.
+///
+/// Lines of compiler output are fed with [`update`](Spinner::update). Progress lines, such as
+/// `Compiling foo v0.1.0`, activate the spinner and set the crate name. The spinner is drawn on the
+/// current terminal line with [`render`](Spinner::render), each render advancing the frame, and
+/// [`finish`](Spinner::finish) clears the line so subsequent output is written over it.
+#[derive(Debug, Default)]
+pub struct Spinner {
+    frame: usize,
+    label: Option<String>,
+}
+
+impl Spinner {
+    /// A new, inactive, spinner.
+    pub fn new() -> Self {
+        Spinner::default()
+    }
+
+    /// The current frame.
+    pub fn frame(&self) -> char {
+        SPINNER_FRAMES[self.frame]
+    }
+
+    /// Move to the next frame, wrapping around to the first. Returns the new frame.
+    pub fn advance(&mut self) -> char {
+        self.frame = (self.frame + 1) % SPINNER_FRAMES.len();
+        self.frame()
+    }
+
+    /// A progress line has been received and the spinner has not finished.
+    pub fn is_active(&self) -> bool {
+        self.label.is_some()
+    }
+
+    /// Feed a line of compiler output. Returns if the line was a progress line, which activates
+    /// the spinner.
+    pub fn update(&mut self, line: &str) -> bool {
+        let mut words = line.split_whitespace();
+        match (words.next(), words.next()) {
+            (Some(status), Some(name)) if is_progress_status(status) => {
+                self.label = Some(format!("{} {}", status, name));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// The text drawn for the current frame, ie `| Compiling foo`.
+    pub fn text(&self) -> String {
+        format!(
+            "{} {}",
+            self.frame(),
+            self.label.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// Advance the frame and draw the spinner on the current line.
+    ///
+    /// The text is truncated to the terminal width so it never wraps.
+    pub fn render<W: Write>(&mut self, wtr: &mut W) -> io::Result<()> {
+        self.advance();
+        let text = self.text();
+        let text = truncate_to_width(&text, term_width_nofail().saturating_sub(1));
+        wtr.queue(Clear(ClearType::CurrentLine))
+            .and_then(|s| s.queue(MoveToColumn(0)))
+            .and_then(|s| s.queue(Print(text)))
+            .map_err(|e| map_xterm_err(e, "drawing the spinner"))?;
+        wtr.flush()
+    }
+
+    /// Clear the spinner's line and deactivate it.
+    pub fn finish<W: Write>(&mut self, wtr: &mut W) -> io::Result<()> {
+        self.label = None;
+        self.frame = 0;
+        wtr.queue(Clear(ClearType::CurrentLine))
+            .and_then(|s| s.queue(MoveToColumn(0)))
+            .map_err(|e| map_xterm_err(e, "clearing the spinner"))?;
+        wtr.flush()
+    }
+}
+
+/// Cargo's status words for progress lines.
+fn is_progress_status(status: &str) -> bool {
+    matches!(
+        status,
+        "Compiling" | "Checking" | "Updating" | "Downloading" | "Downloaded" | "Locking"
+    )
+}
+
+/// An output change as it is drawn to the terminal.
+#[derive(Debug, PartialEq)]
+enum DrawChange {
+    /// Redraw the current line.
+    CurrentLine(String),
+    /// Move to a new line.
+    NewLine,
+    /// Only the tail of the current line changed. The line is kept up to the display column
+    /// `from_col`, and `text` is written from there.
+    Patch { from_col: usize, text: String },
+}
+
+impl DrawChange {
+    /// The patch that changes the `old` line into the `new` line, starting at the end of their
+    /// common prefix.
+    ///
+    /// The column is the display width of the prefix, which does not end within an escape
+    /// sequence. The escape sequences of the prefix are repeated ahead of the text, so the tail is
+    /// drawn with the style it has in the whole line.
+    fn patch(old: &str, new: &str) -> Self {
+        use unicode_width::UnicodeWidthChar;
+
+        let common = old
+            .bytes()
+            .zip(new.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+
+        let mut idx = 0;
+        let mut from_col = 0;
+        let mut text = String::new();
+        while let Some(ch) = new[idx..].chars().next() {
+            let esc = escape_len(&new[idx..]);
+            let len = esc.unwrap_or_else(|| ch.len_utf8());
+            if idx + len > common {
+                break;
+            }
+            match esc {
+                Some(len) => text.push_str(&new[idx..idx + len]),
+                None => from_col += ch.width().unwrap_or(0),
+            }
+            idx += len;
+        }
+        text.push_str(&new[idx..]);
+
+        DrawChange::Patch { from_col, text }
+    }
+}
+
+/// Returns the number of lines the written text accounts for.
+///
+/// The current line is only redrawn in place if it fits in the terminal `height`. The start of a
+/// longer line has scrolled off the screen, and moving the cursor up would clobber the lines
+/// above it, so the update is appended on a new line instead.
+fn write_output_chg_to<W: Write>(
+    wtr: &mut W,
+    tty: bool,
+    current_lines_covered: u16,
+    height: u16,
+    change: DrawChange,
+) -> io::Result<u16> {
+    use DrawChange::*;
+
+    if !tty {
+        return match change {
+            CurrentLine(line) => write!(wtr, "{}", strip_escapes(&line)).map(|_| 1),
+            NewLine => writeln!(wtr).map(|_| 1),
+            Patch { text, .. } => write!(wtr, "{}", strip_escapes(&text)).map(|_| 1),
+        }
+        .and_then(|n| wtr.flush().map(|_| n));
+    }
+
+    match change {
+        CurrentLine(line) if current_lines_covered > height => {
+            writeln!(wtr)?;
+            wtr.queue(MoveToColumn(0))
+                .and_then(|s| s.queue(Print(&line)))
+                .map_err(|e| map_xterm_err(e, "printing a line"))?;
+            wtr.flush()?;
+            Ok(lines_covered(0, term_width_nofail(), line.chars().count()) as u16)
+        }
+        CurrentLine(line) => {
+            for _ in 1..current_lines_covered {
+                wtr.queue(Clear(ClearType::CurrentLine))
+                    .and_then(|s| s.queue(MoveUp(1)))
+                    .map_err(|e| map_xterm_err(e, "Clear a line"))?;
+            }
+            wtr.queue(Clear(ClearType::CurrentLine))
+                .and_then(|s| s.queue(MoveToColumn(0)))
+                .and_then(|s| s.queue(Print(&line)))
+                .map_err(|e| map_xterm_err(e, "printing a line"))?;
+            wtr.flush()?;
+            Ok(lines_covered(0, term_width_nofail(), line.chars().count()) as u16)
+        }
+        NewLine => writeln!(wtr, "").map(|_| 1),
+        Patch { from_col, text } => {
+            wtr.queue(MoveToColumn(from_col as u16 + 1))
+                .and_then(|s| s.queue(Clear(ClearType::UntilNewLine)))
+                .and_then(|s| s.queue(Print(&text)))
+                .map_err(|e| map_xterm_err(e, "patching a line"))?;
+            wtr.flush()?;
+            Ok(current_lines_covered)
+        }
+    }
+}
+
+/// Writes output changes as an append-only transcript, for [`RenderMode::Plain`].
+///
+/// A line is only written once it is complete, and has any escape sequences removed.
+#[derive(Default)]
+pub struct PlainTranscript {
+    line: String,
+}
+
+impl PlainTranscript {
+    pub fn write<W: Write>(&mut self, wtr: &mut W, change: OutputChange) -> io::Result<()> {
+        use OutputChange::*;
+        match change {
+            CurrentLine(line) => self.line = line,
+            NewLine => {
+                writeln!(wtr, "{}", strip_escapes(&self.line))?;
+                self.line.clear();
+            }
+        }
+        Ok(())
+    }
+
+    /// Write any incomplete line.
+    pub fn finish<W: Write>(self, wtr: &mut W) -> io::Result<()> {
+        if !self.line.is_empty() {
+            writeln!(wtr, "{}", strip_escapes(&self.line))?;
+        }
+        wtr.flush()
+    }
+}
+
+/// The length in bytes of the escape sequence `s` starts with, if it starts with one.
+fn escape_len(s: &str) -> Option<usize> {
+    let mut chars = s.char_indices();
+    if chars.next()?.1 != '\x1b' {
+        return None;
+    }
+    // CSI sequences end with a byte in the range @ to ~
+    let end = match chars.next() {
+        Some((_, '[')) => chars.find(|(_, c)| ('@'..='~').contains(c)),
+        next => next,
+    };
+    Some(end.map_or(s.len(), |(i, c)| i + c.len_utf8()))
+}
+
+/// The number of columns the text takes up, excluding escape sequences.
+fn display_width(s: &str) -> usize {
+    use unicode_width::UnicodeWidthStr;
+    strip_escapes(s).width()
+}
+
+/// Removes ANSI escape sequences, such as colouring, from `s`.
+pub fn strip_escapes(s: &str) -> String {
+    let mut stripped = String::with_capacity(s.len());
+    let mut chars = s.chars();
+    while let Some(ch) = chars.next() {
+        if ch == '\x1b' {
+            // CSI sequences end with a byte in the range @ to ~
+            if chars.next() == Some('[') {
+                chars.find(|c| ('@'..='~').contains(c));
+            }
+        } else {
+            stripped.push(ch);
+        }
+    }
+    stripped
+}
+
+/// Resets position to start of line.
 /// **Does not flush, should be called afterwards.**
 pub fn erase_current_line(mut stdout: Stdout) -> io::Result<Stdout> {
     queue!(stdout, Clear(ClearType::CurrentLine), MoveToColumn(0))
@@ -330,13 +2151,120 @@ pub fn erase_current_line(mut stdout: Stdout) -> io::Result<Stdout> {
         .map_err(|e| map_xterm_err(e, &line!().to_string()))
 }
 
+/// The number of terminal columns the prompt occupies before the input.
+///
+/// Colouring escape sequences are ignored, and only the last line of a multi-line prompt is
+/// counted, as that is the line the input starts on.
+///
+/// # Example
+/// ```rust
+/// use colored::*;
+/// use papyrus::run::prompt_width;
+///
+/// assert_eq!(prompt_width("[lib] papyrus=> "), 16);
+/// assert_eq!(prompt_width(&"[lib]".cyan().to_string()), 5);
+/// assert_eq!(prompt_width("line one\n> "), 2);
+/// ```
+pub fn prompt_width(prompt: &str) -> usize {
+    use unicode_width::UnicodeWidthStr;
+
+    let prompt = strip_escapes(prompt);
+    prompt.rsplit('\n').next().unwrap_or_default().width()
+}
+
 /// Determines the number of lines a text will cover, from the starting postion and a given cell
 /// width.
 /// Panics if width is zero.
 fn lines_covered(starting: usize, width: usize, ch_count: usize) -> usize {
+    wrapped_line_count(starting, width, ch_count)
+}
+
+/// A line of text which may contain newlines.
+struct LineSegment {
+    /// The column the line starts at.
+    col: usize,
+    /// The character index the line starts at.
+    idx: usize,
+    /// The number of characters, excluding the newline.
+    len: usize,
+    /// The number of terminal lines covered.
+    rows: usize,
+}
+
+/// Splits the text into its lines. The first line starts at the column `start`, lines after a
+/// newline start at column zero.
+fn line_segments(start: usize, width: usize, text: &[char]) -> Vec<LineSegment> {
+    let mut segments = Vec::new();
+    let mut idx = 0;
+    for line in text.split(|&c| c == '\n') {
+        let col = if idx == 0 { start } else { 0 };
+        segments.push(LineSegment {
+            col,
+            idx,
+            len: line.len(),
+            rows: lines_covered(col, width, line.len()),
+        });
+        idx += line.len() + 1;
+    }
+
+    // empty lines still cover a line when there are newlines
+    if segments.len() > 1 {
+        for s in &mut segments {
+            s.rows = s.rows.max(1);
+        }
+    }
+
+    segments
+}
+
+/// [`lines_covered`] for text which may contain newlines.
+fn text_lines_covered(start: usize, width: usize, text: &[char]) -> usize {
+    line_segments(start, width, text)
+        .iter()
+        .map(|s| s.rows)
+        .sum()
+}
+
+/// [`cursor_offset`] for text which may contain newlines.
+fn text_cursor_offset(start: usize, width: usize, text: &[char], pos: usize) -> (usize, usize) {
+    let segments = line_segments(start, width, text);
+    let line = segments
+        .iter()
+        .position(|s| pos <= s.idx + s.len)
+        .unwrap_or(segments.len() - 1);
+    let s = &segments[line];
+    let (up, col) = cursor_offset(s.col, width, s.len, pos.saturating_sub(s.idx));
+    let below: usize = segments[line + 1..].iter().map(|s| s.rows).sum();
+    (up + below, col)
+}
+
+/// Determines the number of terminal lines a text will cover when wrapped.
+///
+/// `starting` is the column the text starts at, `width` is the terminal width in cells, and
+/// `display_width` is the number of cells the text occupies. This is the same computation the REPL
+/// uses to position the input, so front-ends can use it to lay out their own elements
+/// consistently.
+///
+/// # Panics
+/// Panics if `width` is zero.
+///
+/// # Example
+/// ```rust
+/// use papyrus::run::wrapped_line_count;
+///
+/// // empty text covers no lines
+/// assert_eq!(wrapped_line_count(0, 80, 0), 0);
+/// // text that fits on the line
+/// assert_eq!(wrapped_line_count(0, 5, 5), 1);
+/// // text that wraps onto a second line
+/// assert_eq!(wrapped_line_count(0, 3, 5), 2);
+/// // starting part way along a line
+/// assert_eq!(wrapped_line_count(1, 5, 5), 2);
+/// ```
+pub fn wrapped_line_count(starting: usize, width: usize, display_width: usize) -> usize {
     assert!(width > 0, "width must be greater than zero");
 
-    let chars = ch_count;
+    let chars = display_width;
 
     if chars == 0 {
         return 0;
@@ -353,13 +2281,676 @@ fn lines_covered(starting: usize, width: usize, ch_count: usize) -> usize {
     }
 }
 
+/// Truncates `s` such that it displays within `max_cols` terminal columns.
+///
+/// The text is cut on character boundaries, using the display width of each character, so wide
+/// characters (such as CJK) count as two columns and multi-byte characters are never split.
+/// Zero-width characters following the cut are kept with the preceding character.
+///
+/// # Example
+/// ```rust
+/// use papyrus::run::truncate_to_width;
+///
+/// assert_eq!(truncate_to_width("Hello, world!", 5), "Hello");
+/// assert_eq!(truncate_to_width("héllo", 2), "hé");
+/// // wide characters take two columns
+/// assert_eq!(truncate_to_width("日本語", 5), "日本");
+/// ```
+pub fn truncate_to_width(s: &str, max_cols: usize) -> &str {
+    use unicode_width::UnicodeWidthChar;
+
+    let mut cols = 0;
+    for (idx, ch) in s.char_indices() {
+        cols += ch.width().unwrap_or(0);
+        if cols > max_cols {
+            return &s[..idx];
+        }
+    }
+
+    s
+}
+
 fn term_width_nofail() -> usize {
     crossterm::terminal::size().unwrap_or((80, 0)).0 as usize
 }
 
+/// The terminal height, unbounded if it cannot be determined.
+fn term_height_nofail() -> u16 {
+    match crossterm::terminal::size() {
+        Ok((_, h)) if h > 0 => h,
+        _ => u16::MAX,
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crossbeam_channel::unbounded;
+    use std::sync::{Arc, Mutex};
+
+    /// A writer whose bytes can be read after it is moved into a screen.
+    #[derive(Clone, Default)]
+    struct Capture(Arc<Mutex<Vec<u8>>>);
+
+    impl Capture {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_read_until_injected_events() {
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+
+        let (tx, rx) = unbounded();
+        let capture = Capture::default();
+        let mut screen = Screen::from_receiver(rx).with_writer(capture.clone());
+
+        for ch in "let a = 1;".chars() {
+            tx.send(key(Char(ch))).unwrap();
+        }
+        tx.send(key(Backspace)).unwrap();
+        tx.send(key(Enter)).unwrap();
+
+        let (buf, ev) = read_until(&mut screen, (0, 0), InputBuffer::new(), &[key(Enter)]);
+
+        assert_eq!(&buf.buffer(), "let a = 1");
+        assert_eq!(ev, ReadOutcome::Stopped(key(Enter)));
+        // the queued edits are drawn once, over the input line
+        assert_eq!(capture.text(), "\x1b[1G\x1b[1G\x1b[Klet a = 1");
+    }
+
+    #[test]
+    fn test_read_until_cancelled() {
+        use std::time::{Duration, Instant};
+
+        let (tx, rx) = unbounded();
+        let handle = CancelHandle::new();
+        let mut screen = Screen::from_receiver(rx)
+            .with_cancel_handle(handle.clone())
+            .with_writer(io::sink());
+
+        tx.send(Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code: Char('a'),
+        }))
+        .unwrap();
+
+        let jh = std::thread::spawn(move || {
+            let r = read_until(&mut screen, (0, 0), InputBuffer::new(), &[]);
+            drop(tx); // keep the event source alive while reading
+            r
+        });
+
+        std::thread::sleep(Duration::from_millis(50));
+        let start = Instant::now();
+        handle.cancel();
+        let (buf, outcome) = jh.join().unwrap();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert_eq!(outcome, ReadOutcome::Cancelled);
+        assert_eq!(&buf.buffer(), "a");
+    }
+
+    #[test]
+    fn test_read_until_eof() {
+        let key = |modifiers, code| Key(KeyEvent { modifiers, code });
+        let ctrl_d = key(KeyModifiers::CONTROL, Char('d'));
+        let enter = key(KeyModifiers::empty(), Enter);
+        let read = |input: &str, pos: usize, events: Vec<Event>| {
+            let (tx, rx) = unbounded();
+            for ev in events {
+                tx.send(ev).unwrap();
+            }
+            let mut buf = InputBuffer::new();
+            buf.insert_str(input);
+            buf.set_pos(pos);
+            let mut screen = Screen::from_receiver(rx).with_writer(io::sink());
+            let (buf, outcome) = read_until(&mut screen, (0, 0), buf, &[enter]);
+            (buf.buffer(), outcome)
+        };
+
+        assert_eq!(
+            read("", 0, vec![ctrl_d]),
+            ("".to_string(), ReadOutcome::Eof)
+        );
+
+        // deletes the character under the cursor
+        assert_eq!(
+            read("abc", 1, vec![ctrl_d, enter]),
+            ("ac".to_string(), ReadOutcome::Stopped(enter))
+        );
+        // and only ends the input once empty
+        assert_eq!(
+            read("a", 0, vec![ctrl_d, ctrl_d, enter]),
+            ("".to_string(), ReadOutcome::Eof)
+        );
+        // nothing to delete at the end
+        assert_eq!(
+            read("a", 1, vec![ctrl_d, enter]),
+            ("a".to_string(), ReadOutcome::Stopped(enter))
+        );
+    }
+
+    #[test]
+    fn test_bounded_event_buffer() {
+        let key = |ch| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code: Char(ch),
+            })
+        };
+
+        let (tx, rx) = bounded(4);
+        let producer = std::thread::spawn(move || {
+            for i in 0..100 {
+                assert!(buffer_event(&tx, Resize(i, i)));
+                if i % 10 == 0 {
+                    assert!(buffer_event(&tx, key('a')));
+                }
+            }
+        });
+
+        // slow consumer
+        let mut keys = 0;
+        while let Ok(ev) = rx.recv() {
+            assert!(rx.len() <= 4);
+            if ev == key('a') {
+                keys += 1;
+            }
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+
+        producer.join().unwrap();
+        assert_eq!(keys, 10); // key events are never dropped
+    }
+
+    #[test]
+    fn test_line_editor_actions() {
+        use LineEditorAction as A;
+
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+
+        let mut editor = LineEditor::new();
+
+        // typing
+        let actions: Vec<_> = "let a = ve"
+            .chars()
+            .map(|ch| editor.handle_event(key(Char(ch))))
+            .collect();
+        assert!(actions.iter().all(|a| a == &A::Redraw));
+        assert_eq!(editor.handle_event(key(Up)), A::None);
+
+        // completing
+        assert_eq!(editor.handle_event(key(Tab)), A::Complete);
+        let completions = vec!["vec!", "vector"].into_iter().map(|m| CItem {
+            matchstr: m.to_string(),
+            input_chpos: 8,
+        });
+        assert_eq!(editor.complete(completions), A::Redraw);
+        assert_eq!(&editor.line(), "let a = vec"); // common prefix
+        assert_eq!(editor.handle_event(key(Tab)), A::Redraw);
+        assert_eq!(&editor.line(), "let a = vec!");
+        assert_eq!(editor.handle_event(key(Tab)), A::Redraw);
+        assert_eq!(&editor.line(), "let a = vector");
+        assert_eq!(editor.handle_event(key(Tab)), A::Redraw);
+        assert_eq!(&editor.line(), "let a = vec!");
+
+        // moving
+        assert_eq!(editor.handle_event(key(Left)), A::Redraw);
+        assert_eq!(editor.position(), 11);
+
+        // submitting
+        assert_eq!(
+            editor.handle_event(key(Enter)),
+            A::Submit("let a = vec!".to_string())
+        );
+        assert_eq!(&editor.line(), "");
+        assert_eq!(editor.position(), 0);
+
+        // cancelling
+        assert_eq!(
+            editor.handle_event(Key(KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: Char('c'),
+            })),
+            A::Cancel
+        );
+    }
+
+    #[test]
+    fn test_complete_common_prefix() {
+        use LineEditorAction as A;
+
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+        let citems = |items: &[&str], input_chpos| {
+            items
+                .iter()
+                .map(|m| CItem {
+                    matchstr: m.to_string(),
+                    input_chpos,
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let candidates = ["format", "format_args", "formatter"];
+        assert_eq!(common_prefix(&citems(&candidates, 0)), "format");
+        assert_eq!(common_prefix(&citems(&["héllo", "hélp"], 0)), "hél");
+        assert_eq!(common_prefix(&citems(&["ab", "cd"], 0)), "");
+        assert_eq!(common_prefix(&[]), "");
+        let mut mixed = citems(&["abc"], 0);
+        mixed.extend(citems(&["abd"], 1));
+        assert_eq!(common_prefix(&mixed), "");
+
+        let mut editor = LineEditor::new();
+        "form".chars().for_each(|ch| {
+            editor.handle_event(key(Char(ch)));
+        });
+        assert_eq!(editor.handle_event(key(Tab)), A::Complete);
+        assert_eq!(editor.complete(citems(&candidates, 0)), A::Redraw);
+        assert_eq!(&editor.line(), "format");
+        assert_eq!(editor.candidates(), candidates);
+
+        // then cycles, skipping the candidate which is the prefix
+        editor.handle_event(key(Tab));
+        assert_eq!(&editor.line(), "format_args");
+        editor.handle_event(key(Tab));
+        assert_eq!(&editor.line(), "formatter");
+        editor.handle_event(key(Tab));
+        assert_eq!(&editor.line(), "format");
+
+        // the prefix is already typed, so the first candidate is written
+        let mut editor = LineEditor::new();
+        "format".chars().for_each(|ch| {
+            editor.handle_event(key(Char(ch)));
+        });
+        editor.handle_event(key(Tab));
+        editor.complete(citems(&["format_args", "formatter"], 0));
+        assert_eq!(&editor.line(), "format_args");
+        editor.handle_event(key(Tab));
+        assert_eq!(&editor.line(), "formatter");
+    }
+
+    #[test]
+    fn test_max_completion_candidates() {
+        use std::cell::Cell;
+
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+
+        let consumed = Cell::new(0);
+        let completions = (0..10_000).map(|i| {
+            consumed.set(consumed.get() + 1);
+            CItem {
+                matchstr: format!("item{}", i),
+                input_chpos: 0,
+            }
+        });
+
+        let mut editor = LineEditor::new().with_max_completions(50);
+        assert_eq!(
+            editor.handle_event(key(Char('i'))),
+            LineEditorAction::Redraw
+        );
+        assert_eq!(editor.handle_event(key(Tab)), LineEditorAction::Complete);
+        assert_eq!(editor.complete(completions), LineEditorAction::Redraw);
+        assert_eq!(editor.completer.completions.len(), 50);
+        assert_eq!(consumed.get(), 50);
+        assert_eq!(&editor.line(), "item"); // common prefix
+        editor.handle_event(key(Tab));
+        assert_eq!(&editor.line(), "item0");
+
+        // cycles within the cap, and the cap is kept once the line is submitted
+        for _ in 0..50 {
+            editor.handle_event(key(Tab));
+        }
+        assert_eq!(&editor.line(), "item0");
+        editor.handle_event(key(Enter));
+        editor.complete((0..100).map(|i| CItem {
+            matchstr: i.to_string(),
+            input_chpos: 0,
+        }));
+        assert_eq!(editor.completer.completions.len(), 50);
+
+        let mut writer = CompletionWriter::new();
+        writer.new_completions((0..1000).map(|i| CItem {
+            matchstr: i.to_string(),
+            input_chpos: 0,
+        }));
+        assert_eq!(writer.completions.len(), 1000); // uncapped by default
+    }
+
+    #[test]
+    fn test_plain_transcript() {
+        use crate as papyrus;
+
+        let dir = "target/testing/plain_transcript";
+        std::fs::create_dir_all(dir).unwrap();
+        let script = format!("{}/script.rs", dir);
+        std::fs::write(&script, "let a = 1;\na + 1\n").unwrap();
+
+        let mut repl = repl!();
+        repl.data.with_compilation_dir(dir).unwrap();
+        let rx = repl.output_listen();
+
+        let (mut repl, results) = repl.run_script(&script, &mut ());
+        assert!(results.is_ok());
+        repl.close_channel();
+
+        let mut transcript = PlainTranscript::default();
+        let mut output = Vec::new();
+        for chg in rx.iter() {
+            transcript.write(&mut output, chg).unwrap();
+        }
+        transcript.finish(&mut output).unwrap();
+        let output = String::from_utf8(output).unwrap();
+
+        assert!(!output.contains('\x1b'));
+        assert!(!output.contains('\r'));
+        assert_eq!(
+            output,
+            "[lib] papyrus=> let a = 1;\n[lib] papyrus.> a + 1\npapyrus [out0]: 2\n[lib] papyrus=> \n"
+        );
+    }
+
+    #[test]
+    fn test_line_editor_stash() {
+        use LineEditorAction as A;
+
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+
+        let mut editor = LineEditor::new();
+        editor.insert_str("fn a() {\n    let");
+        " x = 10".chars().for_each(|ch| {
+            editor.handle_event(key(Char(ch)));
+        });
+        editor.handle_event(key(Left));
+        editor.handle_event(key(Left));
+
+        let stash = editor.stash();
+        assert_eq!(&editor.line(), "");
+        assert_eq!(editor.position(), 0);
+
+        // run another command in the meantime
+        ":help".chars().for_each(|ch| {
+            editor.handle_event(key(Char(ch)));
+        });
+        assert_eq!(
+            editor.handle_event(key(Enter)),
+            A::Submit(":help".to_string())
+        );
+
+        editor.unstash(stash);
+        assert_eq!(&editor.line(), "fn a() {\n    let x = 10");
+        assert_eq!(editor.position(), 21);
+        assert_eq!(editor.handle_event(key(Char('2'))), A::Redraw);
+        assert_eq!(&editor.line(), "fn a() {\n    let x = 210");
+    }
+
+    #[test]
+    fn test_line_editor_history_and_kill() {
+        use LineEditorAction as A;
+
+        let key = |modifiers, code| Key(KeyEvent { modifiers, code });
+        let nomod = |code| key(KeyModifiers::empty(), code);
+        let ctrl = |c| key(KeyModifiers::CONTROL, Char(c));
+        let type_str = |editor: &mut LineEditor, s: &str| {
+            s.chars().for_each(|ch| {
+                editor.handle_event(nomod(Char(ch)));
+            })
+        };
+
+        let mut editor = LineEditor::new();
+        assert_eq!(editor.handle_event(ctrl('d')), A::Eof);
+
+        editor.push_history("let apple = 1;".to_string());
+        editor.push_history("apple + 1".to_string());
+
+        // history navigation returns to the draft
+        type_str(&mut editor, "dr");
+        assert_eq!(editor.handle_event(nomod(Up)), A::Redraw);
+        assert_eq!(&editor.line(), "apple + 1");
+        editor.handle_event(nomod(Down));
+        assert_eq!(&editor.line(), "dr");
+
+        // kill and yank
+        assert_eq!(editor.handle_event(ctrl('w')), A::Redraw);
+        assert_eq!(&editor.line(), "");
+        assert_eq!(editor.handle_event(ctrl('d')), A::Eof);
+        editor.handle_event(ctrl('y'));
+        assert_eq!(&editor.line(), "dr");
+
+        // reverse search, Enter accepts the match rather than submitting
+        editor.handle_event(ctrl('r'));
+        type_str(&mut editor, "let");
+        assert_eq!(&editor.line(), "(reverse-i-search)`let': let apple = 1;");
+        assert_eq!(editor.handle_event(nomod(Enter)), A::Redraw);
+        assert_eq!(&editor.line(), "let apple = 1;");
+        assert_eq!(
+            editor.handle_event(nomod(Enter)),
+            A::Submit("let apple = 1;".to_string())
+        );
+    }
+
+    #[test]
+    fn test_write_output_chg_not_tty() {
+        use colored::*;
+
+        let mut sink = Vec::new();
+        let mut covered = 0;
+        let changes = vec![
+            DrawChange::CurrentLine("[out0]".green().to_string()),
+            DrawChange::Patch {
+                from_col: 6,
+                text: ": 4".to_string(),
+            },
+            DrawChange::NewLine,
+            DrawChange::CurrentLine("a".repeat(200)),
+            DrawChange::NewLine,
+        ];
+        for chg in changes {
+            covered = write_output_chg_to(&mut sink, false, covered, 24, chg).unwrap();
+        }
+
+        let s = String::from_utf8(sink).unwrap();
+        assert!(!s.contains('\x1b'));
+        assert_eq!(s, format!("[out0]: 4\n{}\n", "a".repeat(200)));
+
+        // a terminal does get cursor movement
+        let mut sink = Vec::new();
+        write_output_chg_to(&mut sink, true, 2, 24, DrawChange::CurrentLine("a".into())).unwrap();
+        assert!(String::from_utf8(sink).unwrap().contains('\x1b'));
+    }
+
+    #[test]
+    fn test_write_output_chg_scrolled() {
+        let move_up = format!("{}", MoveUp(1));
+
+        // within the viewport the line is redrawn in place
+        let mut sink = Vec::new();
+        let covered =
+            write_output_chg_to(&mut sink, true, 3, 5, DrawChange::CurrentLine("a".into()))
+                .unwrap();
+        assert_eq!(covered, 1);
+        assert_eq!(
+            String::from_utf8(sink).unwrap().matches(&move_up).count(),
+            2
+        );
+
+        // the start of the line has scrolled off the screen, so the update is appended
+        let mut sink = Vec::new();
+        let covered =
+            write_output_chg_to(&mut sink, true, 8, 5, DrawChange::CurrentLine("a".into()))
+                .unwrap();
+        assert_eq!(covered, 1);
+        let s = String::from_utf8(sink).unwrap();
+        assert!(!s.contains(&move_up));
+        assert!(s.starts_with('\n'));
+        assert!(s.ends_with('a'));
+    }
+
+    #[test]
+    fn test_draw_patch() {
+        let patch = |from_col, text: &str| DrawChange::Patch {
+            from_col,
+            text: text.to_string(),
+        };
+
+        assert_eq!(
+            DrawChange::patch("progress: 10%", "progress: 11%"),
+            patch(11, "1%")
+        );
+        assert_eq!(DrawChange::patch("", "abc"), patch(0, "abc"));
+        assert_eq!(DrawChange::patch("abc", "abc"), patch(3, ""));
+        assert_eq!(DrawChange::patch("abcdef", "abc"), patch(3, ""));
+        // columns are display columns
+        assert_eq!(DrawChange::patch("€€1", "€€2"), patch(2, "2"));
+        assert_eq!(DrawChange::patch("日本1", "日本2"), patch(4, "2"));
+
+        // escape sequences take no columns, and are repeated ahead of the text
+        assert_eq!(
+            DrawChange::patch(
+                "\x1b[32mprogress\x1b[0m: 10%",
+                "\x1b[32mprogress\x1b[0m: 11%"
+            ),
+            patch(11, "\x1b[32m\x1b[0m1%")
+        );
+
+        // a prefix does not end within an escape sequence
+        let new = "\x1b[31ma\x1b[0m";
+        assert_eq!(DrawChange::patch("\x1b[32ma\x1b[0m", new), patch(0, new));
+    }
+
+    #[test]
+    fn test_output_cursor() {
+        let mut cursor = OutputCursor::with_writer(Vec::new(), true);
+        assert_eq!(cursor.lines_covered, 0);
+
+        cursor
+            .write(OutputChange::CurrentLine("abc".into()))
+            .unwrap();
+        assert_eq!(cursor.lines_covered, 1);
+        cursor
+            .write(OutputChange::CurrentLine(String::new()))
+            .unwrap();
+        assert_eq!(cursor.lines_covered, 0);
+        cursor
+            .write(OutputChange::CurrentLine("abc".into()))
+            .unwrap();
+        cursor.write(OutputChange::NewLine).unwrap();
+        assert_eq!(cursor.lines_covered, 1);
+        cursor
+            .write(OutputChange::CurrentLine("a".repeat(2000)))
+            .unwrap();
+        assert!(cursor.lines_covered > 1);
+        cursor
+            .write(OutputChange::CurrentLine("de".into()))
+            .unwrap();
+        assert_eq!(cursor.lines_covered, 1);
+    }
+
+    #[test]
+    fn test_strip_escapes() {
+        use colored::*;
+        assert_eq!(strip_escapes(&"papyrus".cyan().to_string()), "papyrus");
+        assert_eq!(strip_escapes("a\x1b[2Kb"), "ab");
+        assert_eq!(strip_escapes("no escapes"), "no escapes");
+    }
+
+    #[test]
+    fn test_custom_prompt_width() {
+        use crate as papyrus;
+        use crate::code::{Statement, StmtGrp};
+        use crate::repl::PromptContext;
+        use colored::*;
+
+        let mut repl = repl!();
+        repl.data.with_prompt(|ctx: &PromptContext| {
+            let p = format!("In [{}]: ", ctx.counter);
+            if ctx.colour {
+                p.green().to_string()
+            } else {
+                p
+            }
+        });
+        assert_eq!(repl.prompt(false), "In [0]: ");
+
+        repl.data
+            .mods_map
+            .get_mut(std::path::Path::new("lib"))
+            .unwrap()
+            .stmts
+            .push(StmtGrp(vec![Statement {
+                expr: "2+2".to_string(),
+                semi: false,
+            }]));
+        assert_eq!(repl.prompt(false), "In [1]: ");
+
+        let prompt = repl.prompt(true);
+        assert_eq!(prompt_width(&prompt), 8);
+
+        // 8 prompt columns and 10 input columns wrap on a 10 column terminal
+        assert_eq!(lines_covered(prompt_width(&prompt), 10, 10), 2);
+        assert_eq!(lines_covered(prompt_width(&prompt), 10, 2), 1);
+        assert_eq!(lines_covered(prompt_width(&prompt), 10, 3), 2);
+    }
+
+    #[test]
+    fn test_truncate_to_width() {
+        use unicode_width::UnicodeWidthStr;
+
+        assert_eq!(truncate_to_width("", 5), "");
+        assert_eq!(truncate_to_width("abc", 0), "");
+        assert_eq!(truncate_to_width("abc", 5), "abc");
+
+        // multibyte characters are not split
+        let s = truncate_to_width("añb€c", 4);
+        assert_eq!(s, "añb€");
+        assert_eq!(s.width(), 4);
+
+        // wide characters are two columns, and are not split if they straddle the limit
+        let s = truncate_to_width("a日本語", 4);
+        assert_eq!(s, "a日");
+        assert_eq!(s.width(), 3);
+        assert_eq!(truncate_to_width("日本語", 1), "");
+
+        // combining characters stay with their base character
+        assert_eq!(truncate_to_width("e\u{301}x", 1), "e\u{301}");
+    }
 
     #[test]
     fn test_input_movement() {
@@ -385,6 +2976,706 @@ mod tests {
         assert_eq!(input.pos, 0);
     }
 
+    #[test]
+    fn test_input_set_pos() {
+        let mut input = InputBuffer::new();
+        input.insert_str("héllo");
+        assert_eq!(input.pos(), 5);
+        input.take_dirty();
+
+        input.set_pos(1);
+        assert_eq!(input.pos(), 1);
+        assert_eq!(input.char_at(input.pos()), Some('é'));
+        assert_eq!(input.char_at(5), None);
+
+        // saturates at the end
+        input.set_pos(100);
+        assert_eq!(input.pos(), 5);
+        assert!(!input.take_dirty()); // only the cursor moves
+    }
+
+    #[test]
+    fn test_input_buffer_config() {
+        let mut input = InputBuffer::with_config(InputBufferConfig {
+            max_len: Some(4),
+            mask: Some('*'),
+            ..Default::default()
+        });
+
+        input.insert_str("secret");
+        assert_eq!(input.buffer(), "secr");
+        input.insert('!');
+        assert_eq!(input.buffer(), "secr");
+        assert_eq!(input.to_string(), "****");
+        assert_eq!(input.display_text(), "****");
+
+        // replacing is also capped
+        input.replace("passwords");
+        assert_eq!(input.buffer(), "pass");
+
+        assert_eq!(InputBuffer::new().config, InputBufferConfig::default());
+    }
+
+    #[test]
+    fn test_input_word_movement() {
+        let mut input = InputBuffer::new();
+
+        // empty buffer
+        assert_eq!(input.move_word_left(), 0);
+        assert_eq!(input.move_word_right(), 0);
+
+        input.insert_str("  let a = std::mem::take(&mut b.c);  ");
+        input.pos = 0;
+
+        let mut stops = Vec::new();
+        while input.move_word_right() > 0 {
+            stops.push(input.pos);
+        }
+        // let, a, =, std, ::, mem, ::, take, (&, mut, b, ., c, );
+        assert_eq!(
+            stops,
+            vec![5, 7, 9, 13, 15, 18, 20, 24, 26, 29, 31, 32, 33, 35, 37]
+        );
+        // trailing whitespace is skipped to the end
+        assert_eq!(input.pos, input.ch_len());
+
+        let mut stops = Vec::new();
+        while input.move_word_left() > 0 {
+            stops.push(input.pos);
+        }
+        assert_eq!(
+            stops,
+            vec![33, 32, 31, 30, 26, 24, 20, 18, 15, 13, 10, 8, 6, 2, 0]
+        );
+
+        // returns the number moved
+        input.pos = 2;
+        assert_eq!(input.move_word_right(), 3);
+        assert_eq!(input.move_word_left(), 3);
+        assert_eq!(input.move_word_left(), 2); // leading whitespace
+
+        // ctrl+arrow key bindings
+        let ctrl = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code,
+            })
+        };
+        let input = apply_event_to_buf(input, ctrl(Right));
+        assert_eq!(input.pos, 5);
+        let input = apply_event_to_buf(input, ctrl(Left));
+        assert_eq!(input.pos, 2);
+    }
+
+    #[test]
+    fn test_input_word_delete() {
+        let mut input = InputBuffer::new();
+
+        // no-op at the start
+        assert_eq!(input.delete_word_back(), 0);
+        assert_eq!(input.delete_word_forward(), 0);
+        assert!(!input.take_dirty());
+
+        input.insert_str("let x = some_long_name  ");
+        input.take_dirty();
+
+        // trailing whitespace goes with the word
+        assert_eq!(input.delete_word_back(), 16);
+        assert_eq!(&input.buffer(), "let x = ");
+        assert_eq!(input.pos, 8);
+        assert!(input.take_dirty());
+
+        assert_eq!(input.delete_word_back(), 2);
+        assert_eq!(&input.buffer(), "let x ");
+        assert_eq!(input.delete_word_back(), 2);
+        assert_eq!(input.delete_word_back(), 4);
+        assert_eq!(&input.buffer(), "");
+        assert_eq!(input.delete_word_back(), 0);
+
+        input.insert_str("foo.bar baz");
+        input.pos = 3;
+        assert_eq!(input.delete_word_forward(), 1);
+        assert_eq!(&input.buffer(), "foobar baz");
+        assert_eq!(input.pos, 3);
+        assert_eq!(input.delete_word_forward(), 3);
+        assert_eq!(input.delete_word_forward(), 4);
+        assert_eq!(&input.buffer(), "foo");
+        assert_eq!(input.delete_word_forward(), 0);
+
+        // key bindings
+        let ctrl = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code,
+            })
+        };
+        input.insert_str(" a b");
+        let input = apply_event_to_buf(input, ctrl(Char('w')));
+        assert_eq!(&input.buffer(), "foo a ");
+        let mut input = apply_event_to_buf(input, ctrl(Backspace));
+        assert_eq!(&input.buffer(), "foo ");
+        input.pos = 0;
+        let input = apply_event_to_buf(input, ctrl(Delete));
+        assert_eq!(&input.buffer(), " ");
+    }
+
+    #[test]
+    fn test_input_undo() {
+        let mut input = InputBuffer::new();
+        assert!(!input.undo());
+        assert!(!input.redo());
+
+        // typing is one undo group
+        "let a".chars().for_each(|c| input.insert(c));
+        input.move_pos_left(4);
+        input.insert('!'); // moving starts a new group
+        assert_eq!(&input.buffer(), "l!et a");
+        input.pos = 6;
+        input.insert_str(" = 1;");
+        input.delete_word_back();
+        assert_eq!(&input.buffer(), "l!et a = 1");
+        input.take_dirty();
+
+        assert!(input.undo());
+        assert_eq!(&input.buffer(), "l!et a = 1;");
+        assert_eq!(input.pos, 11);
+        assert!(input.take_dirty());
+        assert!(input.undo());
+        assert_eq!(&input.buffer(), "l!et a");
+        assert_eq!(input.pos, 6);
+        assert!(input.undo());
+        assert_eq!(&input.buffer(), "let a");
+        assert_eq!(input.pos, 1);
+
+        assert!(input.redo());
+        assert_eq!(&input.buffer(), "l!et a");
+        assert!(input.redo());
+        assert_eq!(&input.buffer(), "l!et a = 1;");
+
+        // an edit clears the redo states
+        input.backspace();
+        assert!(!input.redo());
+        assert!(input.undo());
+        assert!(input.undo());
+        assert!(input.undo());
+        assert!(input.undo());
+        assert_eq!(&input.buffer(), "");
+        assert!(!input.undo());
+
+        // capped
+        let mut input = InputBuffer::new();
+        for _ in 0..UNDO_LEN + 10 {
+            input.insert_str("a");
+        }
+        let mut undone = 0;
+        while input.undo() {
+            undone += 1;
+        }
+        assert_eq!(undone, UNDO_LEN);
+        assert_eq!(input.ch_len(), 10);
+
+        // key bindings
+        let key = |modifiers, code| Key(KeyEvent { modifiers, code });
+        let ctrl_shift = KeyModifiers::CONTROL | KeyModifiers::SHIFT;
+        let mut input = InputBuffer::new();
+        input.insert_str("foo bar");
+        let input = apply_event_to_buf(input, key(KeyModifiers::CONTROL, Char('w')));
+        assert_eq!(&input.buffer(), "foo ");
+        let input = apply_event_to_buf(input, key(KeyModifiers::CONTROL, Char('z')));
+        assert_eq!(&input.buffer(), "foo bar");
+        let input = apply_event_to_buf(input, key(ctrl_shift, Char('Z')));
+        assert_eq!(&input.buffer(), "foo ");
+    }
+
+    #[test]
+    fn test_home_end() {
+        let mut input = InputBuffer::new();
+        input.insert_str("let a = 1;");
+        input.take_dirty();
+
+        let key = |modifiers, code| Key(KeyEvent { modifiers, code });
+        let input = apply_event_to_buf(input, key(KeyModifiers::empty(), Home));
+        assert_eq!(input.pos, 0);
+        let input = apply_event_to_buf(input, key(KeyModifiers::empty(), End));
+        assert_eq!(input.pos, 10);
+        let input = apply_event_to_buf(input, key(KeyModifiers::CONTROL, Char('a')));
+        assert_eq!(input.pos, 0);
+        let mut input = apply_event_to_buf(input, key(KeyModifiers::CONTROL, Char('e')));
+        assert_eq!(input.pos, 10);
+        assert!(!input.take_dirty()); // only the cursor moves
+    }
+
+    #[test]
+    fn test_cursor_offset() {
+        // single line
+        assert_eq!(cursor_offset(4, 20, 10, 10), (0, 14));
+        assert_eq!(cursor_offset(4, 20, 10, 0), (0, 4));
+        // wrapped, the text covers 3 lines
+        assert_eq!(cursor_offset(4, 10, 20, 20), (0, 4));
+        assert_eq!(cursor_offset(4, 10, 20, 0), (2, 4));
+        assert_eq!(cursor_offset(4, 10, 20, 6), (1, 0));
+        // filling the last line
+        assert_eq!(cursor_offset(4, 10, 16, 16), (0, 9));
+        assert_eq!(cursor_offset(4, 10, 16, 15), (0, 9));
+        assert_eq!(cursor_offset(4, 10, 16, 5), (1, 9));
+        assert_eq!(cursor_offset(0, 10, 0, 0), (0, 0));
+    }
+
+    #[test]
+    fn test_read_until_history() {
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+        let read = |screen: &mut Screen, buf: &str, events: Vec<Event>| {
+            let mut input = InputBuffer::new();
+            input.insert_str(buf);
+            let (tx, rx) = unbounded();
+            screen.events = rx;
+            for ev in events {
+                tx.send(ev).unwrap();
+            }
+            drop(tx);
+            read_until(screen, (0, 0), input, &[]).0.buffer()
+        };
+
+        let mut screen = Screen::from_receiver(unbounded().1);
+        assert_eq!(read(&mut screen, "a", vec![key(Up), key(Down)]), "a");
+
+        screen.push_history("let a = 1;".to_string());
+        screen.push_history("a + 1".to_string());
+
+        assert_eq!(read(&mut screen, "draft", vec![key(Up)]), "a + 1");
+        assert_eq!(
+            read(&mut screen, "a + 1", vec![key(Up), key(Up)]),
+            "let a = 1;"
+        );
+        // the in-progress line is restored at the bottom
+        assert_eq!(
+            read(
+                &mut screen,
+                "let a = 1;",
+                vec![key(Down), key(Down), key(Down)]
+            ),
+            "draft"
+        );
+
+        screen.push_history("a * 2".to_string());
+        assert_eq!(read(&mut screen, "", vec![key(Up), key(Down)]), "");
+    }
+
+    #[test]
+    fn test_read_until_paste() {
+        let enter = Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code: Enter,
+        });
+        let read = |screen: &mut Screen, text: &str| {
+            let (tx, rx) = unbounded();
+            screen.events = rx;
+            for ev in text.split('\n').flat_map(line_events) {
+                tx.send(ev).unwrap();
+            }
+            let (buf, ev) = read_until(screen, (0, 0), InputBuffer::new(), &[enter]);
+            assert_eq!(ev, ReadOutcome::Stopped(enter));
+            buf.buffer()
+        };
+
+        // the first line is submitted without paste detection
+        let mut screen = Screen::from_receiver(unbounded().1);
+        assert_eq!(read(&mut screen, "let a = 1;\nlet b = 2;"), "let a = 1;");
+
+        // the pasted block is read until an Enter on a blank line
+        screen.paste_detection = true;
+        assert_eq!(
+            read(&mut screen, "fn a() {\n    1\n}\n"),
+            "fn a() {\n    1\n}"
+        );
+        assert_eq!(read(&mut screen, "a()"), "a()");
+    }
+
+    #[test]
+    fn test_input_submit_block() {
+        let mut input = InputBuffer::new();
+        input.insert_str("let a = 1;");
+        assert!(input.submit_block());
+        assert_eq!(&input.buffer(), "let a = 1;");
+
+        input.insert_str("\nlet b = 2;");
+        assert!(!input.submit_block());
+        assert_eq!(&input.buffer(), "let a = 1;\nlet b = 2;\n");
+        input.insert_str("  ");
+
+        // the blank line has to be at the end
+        input.set_pos(0);
+        assert!(!input.submit_block());
+        assert_eq!(input.buffer().lines().count(), 4);
+        input.backspace();
+
+        input.set_pos(100);
+        assert!(input.submit_block());
+        assert_eq!(&input.buffer(), "let a = 1;\nlet b = 2;");
+
+        // the delimiters have to balance
+        let mut input = InputBuffer::new();
+        input.insert_str("fn a() {\n");
+        assert!(!input.submit_block());
+        assert_eq!(&input.buffer(), "fn a() {\n\n");
+        input.insert('}');
+        assert!(!input.submit_block());
+        assert!(input.submit_block());
+        assert_eq!(&input.buffer(), "fn a() {\n\n}");
+    }
+
+    #[test]
+    fn test_text_layout() {
+        let text = |s: &str| s.chars().collect::<Vec<_>>();
+
+        // without newlines is the same as a single line
+        assert_eq!(text_lines_covered(4, 10, &text("")), 0);
+        assert_eq!(text_lines_covered(4, 10, &text("0123456789")), 2);
+        assert_eq!(text_cursor_offset(4, 10, &text("0123456789"), 0), (1, 4));
+
+        // lines after a newline start at column zero, and empty lines cover a line
+        let block = text("fn a() {\n\n0123456789ab\n}");
+        assert_eq!(text_lines_covered(4, 10, &block), 6);
+        assert_eq!(text_cursor_offset(4, 10, &block, 0), (5, 4));
+        assert_eq!(text_cursor_offset(4, 10, &block, 8), (4, 2));
+        assert_eq!(text_cursor_offset(4, 10, &block, 9), (3, 0));
+        assert_eq!(text_cursor_offset(4, 10, &block, 21), (1, 1));
+        assert_eq!(text_cursor_offset(4, 10, &block, 24), (0, 1));
+        assert_eq!(text_lines_covered(0, 10, &text("a\n")), 2);
+        assert_eq!(text_cursor_offset(0, 10, &text("a\n"), 2), (0, 0));
+    }
+
+    #[test]
+    fn test_read_until_reverse_search() {
+        let key = |modifiers, code| Key(KeyEvent { modifiers, code });
+        let ch = |c| key(KeyModifiers::empty(), Char(c));
+        let ctrl_r = key(KeyModifiers::CONTROL, Char('r'));
+        let enter = key(KeyModifiers::empty(), Enter);
+        let read = |screen: &mut Screen, buf: &str, events: Vec<Event>| {
+            let mut input = InputBuffer::new();
+            input.insert_str(buf);
+            let (tx, rx) = unbounded();
+            screen.events = rx;
+            for ev in events {
+                tx.send(ev).unwrap();
+            }
+            drop(tx);
+            let (buf, ev) = read_until(screen, (0, 0), input, &[enter]);
+            (buf.buffer(), buf.pos, ev)
+        };
+
+        let mut screen = Screen::from_receiver(unbounded().1);
+        screen.push_history("let apple = 1;".to_string());
+        screen.push_history("let banana = 2;".to_string());
+        screen.push_history("apple + banana".to_string());
+
+        // most recent match first, the cursor at the match
+        let (line, pos, ev) = read(&mut screen, "x", vec![ctrl_r, ch('b'), ch('a'), enter]);
+        assert_eq!(&line, "apple + banana");
+        assert_eq!(pos, 8);
+        assert_eq!(ev, ReadOutcome::Stopped(enter));
+
+        // cycle to older matches, narrowing keeps the match if it still matches
+        let (line, ..) = read(
+            &mut screen,
+            "",
+            vec![ctrl_r, ch('a'), ch('p'), ctrl_r, ch('p'), enter],
+        );
+        assert_eq!(&line, "let apple = 1;");
+
+        // failing to find keeps the last match
+        let (line, ..) = read(&mut screen, "", vec![ctrl_r, ch('b'), ch('z'), enter]);
+        assert_eq!(&line, "apple + banana");
+
+        // escape restores the original line
+        let esc = key(KeyModifiers::empty(), Esc);
+        let (line, pos, _) = read(&mut screen, "orig", vec![ctrl_r, ch('l'), esc]);
+        assert_eq!((line.as_str(), pos), ("orig", 4));
+
+        // other keys accept and are handled
+        let (line, ..) = read(
+            &mut screen,
+            "",
+            vec![ctrl_r, ch('l'), key(KeyModifiers::empty(), Left), ch('_')],
+        );
+        assert_eq!(&line, "ap_ple + banana");
+
+        // an unfinished search is restored
+        let (line, ..) = read(&mut screen, "orig", vec![ctrl_r, ch('l')]);
+        assert_eq!(&line, "orig");
+    }
+
+    #[test]
+    fn test_screen_raw_mode_unavailable() {
+        let fail = |msg: &'static str| {
+            move || -> xterm::Result<()> {
+                let e = io::Error::new(io::ErrorKind::Other, msg);
+                Err(xterm::ErrorKind::IoError(e))
+            }
+        };
+        let no_read = || -> xterm::Result<Event> { unreachable!("should not read events") };
+
+        // raw mode fails
+        let r = Screen::with_event_source(8, fail("no raw mode"), |_| Ok(false), no_read);
+        match r {
+            Err(ScreenError::RawModeUnavailable(e)) => assert_eq!(e.to_string(), "no raw mode"),
+            _ => panic!("expecting RawModeUnavailable"),
+        }
+
+        // event source fails
+        let r = Screen::with_event_source(
+            8,
+            || Ok(()),
+            move |_| fail("no events")().map(|_| false),
+            no_read,
+        );
+        match r {
+            Err(ScreenError::RawModeUnavailable(e)) => assert_eq!(e.to_string(), "no events"),
+            _ => panic!("expecting RawModeUnavailable"),
+        }
+
+        // a working source
+        let read = || {
+            Ok(Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code: Enter,
+            }))
+        };
+        // the source never stops sending enters, which would be read as a paste
+        let mut screen = Screen::with_event_source(8, || Ok(()), |_| Ok(true), read).unwrap();
+        screen.paste_detection = false;
+        assert!(screen.raw_mode());
+        let enter = Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code: Enter,
+        });
+        let (_, ev) = read_until(&mut screen, (0, 0), InputBuffer::new(), &[enter]);
+        assert_eq!(ev, ReadOutcome::Stopped(enter));
+    }
+
+    #[test]
+    fn test_line_events() {
+        let key = |code| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::empty(),
+                code,
+            })
+        };
+        assert_eq!(
+            line_events("a\tb").collect::<Vec<_>>(),
+            vec![key(Char('a')), key(Char('\t')), key(Char('b')), key(Enter)]
+        );
+    }
+
+    #[test]
+    fn test_kill_ring() {
+        let mut ring = KillRing::with_capacity(2);
+        assert_eq!(ring.yank(), None);
+        ring.rotate(); // no-op when empty
+        ring.append("a".to_string(), false);
+        assert_eq!(ring.yank(), Some("a"));
+
+        ring.push(String::new());
+        assert_eq!(ring.yank(), Some("a"));
+        ring.push("b".to_string());
+        ring.push("c".to_string()); // drops "a"
+        assert_eq!(ring.yank(), Some("c"));
+        ring.rotate();
+        assert_eq!(ring.yank(), Some("b"));
+        ring.rotate();
+        assert_eq!(ring.yank(), Some("c"));
+
+        ring.append("d".to_string(), false);
+        assert_eq!(ring.yank(), Some("cd"));
+        ring.append("e".to_string(), true);
+        assert_eq!(ring.yank(), Some("ecd"));
+    }
+
+    #[test]
+    fn test_read_until_kill_and_yank() {
+        let ctrl = |c| {
+            Key(KeyEvent {
+                modifiers: KeyModifiers::CONTROL,
+                code: Char(c),
+            })
+        };
+        let alt_y = Key(KeyEvent {
+            modifiers: KeyModifiers::ALT,
+            code: Char('y'),
+        });
+        let left = Key(KeyEvent {
+            modifiers: KeyModifiers::empty(),
+            code: Left,
+        });
+        let read = |screen: &mut Screen, buf: &str, events: Vec<Event>| {
+            let mut input = InputBuffer::new();
+            input.insert_str(buf);
+            let (tx, rx) = unbounded();
+            screen.events = rx;
+            for ev in events {
+                tx.send(ev).unwrap();
+            }
+            drop(tx);
+            read_until(screen, (0, 0), input, &[]).0.buffer()
+        };
+
+        let mut screen = Screen::from_receiver(unbounded().1);
+
+        // yanking an empty ring is a no-op
+        assert_eq!(read(&mut screen, "a b", vec![ctrl('y'), alt_y]), "a b");
+
+        // consecutive kills are joined
+        assert_eq!(
+            read(
+                &mut screen,
+                "let x = some_long_name",
+                vec![ctrl('w'), ctrl('w')]
+            ),
+            "let x "
+        );
+        assert_eq!(read(&mut screen, "", vec![ctrl('y')]), "= some_long_name");
+
+        assert_eq!(
+            read(
+                &mut screen,
+                "foo bar",
+                vec![ctrl('w'), ctrl('y'), ctrl('y')]
+            ),
+            "foo barbar"
+        );
+
+        // rotate replaces the yank with the older kill
+        assert_eq!(
+            read(&mut screen, "", vec![ctrl('y'), alt_y]),
+            "= some_long_name"
+        );
+        assert_eq!(read(&mut screen, "x", vec![alt_y]), "x");
+
+        // kill to end
+        assert_eq!(
+            read(
+                &mut screen,
+                "abc def",
+                vec![left, left, left, ctrl('k'), ctrl('y'), ctrl('y')]
+            ),
+            "abc defdef"
+        );
+    }
+
+    #[test]
+    fn test_spinner() {
+        let mut spinner = Spinner::new();
+        assert!(!spinner.is_active());
+        assert_eq!(spinner.frame(), '|');
+
+        let frames: Vec<_> = (0..5).map(|_| spinner.advance()).collect();
+        assert_eq!(frames, vec!['/', '-', '\\', '|', '/']);
+
+        assert!(!spinner.update("error[E0308]: mismatched types"));
+        assert!(!spinner.is_active());
+        assert!(spinner.update("   Compiling papyrus_mem_code v0.1.0 (/tmp/papyrus)"));
+        assert!(spinner.is_active());
+        assert_eq!(spinner.text(), "/ Compiling papyrus_mem_code");
+
+        let mut wtr = Vec::new();
+        spinner.render(&mut wtr).unwrap();
+        let drawn = String::from_utf8(wtr).unwrap();
+        assert!(drawn.ends_with("- Compiling papyrus_mem_code"));
+
+        let mut wtr = Vec::new();
+        spinner.finish(&mut wtr).unwrap();
+        let cleared = String::from_utf8(wtr).unwrap();
+        assert!(cleared.contains("\x1b[2K"));
+        assert_eq!(strip_escapes(&cleared), "");
+        assert!(!spinner.is_active());
+        assert_eq!(spinner.frame(), '|');
+    }
+
+    #[test]
+    fn test_input_take_dirty() {
+        let mut input = InputBuffer::new();
+        assert!(!input.take_dirty());
+
+        input.insert('a');
+        assert!(input.take_dirty());
+        assert!(!input.take_dirty());
+
+        // movement does not dirty
+        input.move_pos_left(1);
+        assert!(!input.take_dirty());
+
+        input.delete();
+        assert!(input.take_dirty());
+        assert!(!input.take_dirty());
+
+        // nothing to remove
+        input.backspace();
+        input.truncate(0);
+        assert!(!input.take_dirty());
+    }
+
+    #[test]
+    fn test_input_find_char() {
+        let mut input = InputBuffer::new();
+        input.insert_str("foo(a, b).bar(c)");
+        input.move_pos_left(100);
+
+        assert!(input.find_forward('('));
+        assert_eq!(input.pos, 3);
+        // finds the next occurrence, not the current one
+        assert!(input.find_forward('('));
+        assert_eq!(input.pos, 13);
+        assert!(!input.find_forward('('));
+        assert_eq!(input.pos, 13);
+        assert!(!input.find_forward('z'));
+        assert_eq!(input.pos, 13);
+
+        assert!(input.find_backward('.'));
+        assert_eq!(input.pos, 9);
+        assert!(!input.find_backward('.'));
+        assert_eq!(input.pos, 9);
+        assert!(input.find_backward('f'));
+        assert_eq!(input.pos, 0);
+        assert!(!input.find_backward('f'));
+        assert_eq!(input.pos, 0);
+
+        // at end of buffer
+        input.move_pos_right(100);
+        assert!(!input.find_forward(')'));
+        assert_eq!(input.pos, 16);
+        assert!(input.find_backward(')'));
+        assert_eq!(input.pos, 15);
+    }
+
+    #[test]
+    fn test_char_search_event() {
+        let key = |modifiers, ch| {
+            Key(KeyEvent {
+                modifiers,
+                code: Char(ch),
+            })
+        };
+
+        assert_eq!(
+            char_search_event(&key(KeyModifiers::CONTROL, ']')),
+            Some(true)
+        );
+        assert_eq!(
+            char_search_event(&key(KeyModifiers::CONTROL, '5')),
+            Some(true)
+        );
+        assert_eq!(
+            char_search_event(&key(KeyModifiers::CONTROL | KeyModifiers::ALT, ']')),
+            Some(false)
+        );
+        assert_eq!(char_search_event(&key(KeyModifiers::empty(), ']')), None);
+    }
+
     #[test]
     fn test_input_removing() {
         let mut input = InputBuffer::new();
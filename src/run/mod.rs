@@ -7,12 +7,22 @@ use kserd::{fmt::FormattingConfig, Kserd};
 use repl::{EvalResult, Evaluate, Print, Read, ReadResult};
 use std::io::{self, prelude::*};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+mod history;
 mod interface;
 
-use interface::{CItem, InputBuffer, Screen};
+use interface::PlainTranscript;
+
+pub use history::History;
+pub use interface::{
+    prompt_width, read_until, render_mode, set_render_mode, truncate_to_width, wrapped_line_count,
+    CItem, CancelHandle, InputBuffer, InputBufferConfig, LineEditor, LineEditorAction, ReadOutcome,
+    RenderMode, Screen, ScreenError, Spinner, EVENT_BUFFER_CAPACITY, PASTE_INTERVAL,
+};
 
 const CODE_COMPLETIONS: Option<usize> = Some(10);
+const SPINNER_INTERVAL: Duration = Duration::from_millis(100);
 
 #[cfg(feature = "racer-completion")]
 struct CacheWrapper(CodeCache);
@@ -39,7 +49,7 @@ pub fn fmt_based_on_terminal_width<D>(repl: &Repl<Print, D>) -> FormattingConfig
             } else {
                 std::cmp::max((width * 4) / 5, 120)
             }
-            .saturating_sub(repl.prompt(false).chars().count());
+            .saturating_sub(prompt_width(&repl.prompt(false)));
             fmt.width_limit = Some(width);
             fmt
         })
@@ -54,6 +64,10 @@ pub struct RunCallbacks<'a, D, T, U> {
     evalfn: Box<dyn FnMut(Repl<Evaluate, D>) -> EvalResult<D> + 'a>,
     fmtrfn: Option<T>,
     resultfn: Option<U>,
+    cancel: Option<CancelHandle>,
+    history: Option<History>,
+    max_completions: Option<usize>,
+    screen: Option<Screen>,
 }
 
 impl<'a, D>
@@ -70,6 +84,10 @@ impl<'a, D>
             evalfn: Box::new(move |repl| repl.eval(app_data)),
             fmtrfn: None,
             resultfn: None,
+            cancel: None,
+            history: None,
+            max_completions: None,
+            screen: None,
         }
     }
 
@@ -82,6 +100,10 @@ impl<'a, D>
             evalfn: Box::new(move |repl| repl.eval_async(&app_data).wait()),
             fmtrfn: None,
             resultfn: None,
+            cancel: None,
+            history: None,
+            max_completions: None,
+            screen: None,
         }
     }
 }
@@ -93,12 +115,22 @@ impl<'a, D, T, U> RunCallbacks<'a, D, T, U> {
         F: FnMut(&Repl<Print, D>) -> FormattingConfig,
     {
         let RunCallbacks {
-            evalfn, resultfn, ..
+            evalfn,
+            resultfn,
+            cancel,
+            history,
+            max_completions,
+            screen,
+            ..
         } = self;
         RunCallbacks {
             evalfn,
             fmtrfn: Some(f),
             resultfn,
+            cancel,
+            history,
+            max_completions,
+            screen,
         }
     }
 
@@ -110,13 +142,61 @@ impl<'a, D, T, U> RunCallbacks<'a, D, T, U> {
     where
         F: FnMut(usize, Kserd<'static>, &Repl<Read, D>),
     {
-        let RunCallbacks { evalfn, fmtrfn, .. } = self;
+        let RunCallbacks {
+            evalfn,
+            fmtrfn,
+            cancel,
+            history,
+            max_completions,
+            screen,
+            ..
+        } = self;
         RunCallbacks {
             evalfn,
             fmtrfn,
             resultfn: Some(f),
+            cancel,
+            history,
+            max_completions,
+            screen,
         }
     }
+
+    /// Stop the REPL when `handle` is cancelled, even while waiting on input.
+    ///
+    /// This can be used to shut down a REPL running on another thread. The output is returned as if
+    /// the REPL was interrupted.
+    pub fn with_cancel_handle(mut self, handle: CancelHandle) -> Self {
+        self.cancel = Some(handle);
+        self
+    }
+
+    /// Use `history` for the input history, rather than loading it from
+    /// [`History::default_path`].
+    ///
+    /// The history is saved when the REPL exits.
+    pub fn with_history(mut self, history: History) -> Self {
+        self.history = Some(history);
+        self
+    }
+
+    /// Keep at most `max` completion candidates when tabbing. By default all the candidates are
+    /// kept.
+    pub fn with_max_completions(mut self, max: usize) -> Self {
+        self.max_completions = Some(max);
+        self
+    }
+
+    /// Read input from `screen`, rather than a terminal screen with the default event buffer
+    /// capacity.
+    ///
+    /// This can be used to drive the REPL with [`Screen::from_receiver`], or to buffer more
+    /// terminal events with [`Screen::with_capacity`]. The cancel handle, history, and maximum
+    /// completions of the callbacks are applied to the screen.
+    pub fn with_screen(mut self, screen: Screen) -> Self {
+        self.screen = Some(screen);
+        self
+    }
 }
 
 /// Available with the `runnable` feature and when the REPL is in the `Read` state.
@@ -139,6 +219,11 @@ where
     FmtrFn: FnMut(&Repl<Print, D>) -> kserd::fmt::FormattingConfig,
     ResultFn: FnMut(usize, kserd::Kserd<'static>, &Repl<Read, D>),
 {
+    // redirected output should not contain cursor movement or colours
+    if !atty::is(atty::Stream::Stdout) {
+        set_render_mode(RenderMode::Plain);
+    }
+
     // set a custom panic handler to dump to a file
     // must be done as the screen captures the io streams and will
     // lose the panic message
@@ -160,7 +245,32 @@ where
         std::fs::write(filename, content).ok();
     }));
 
-    let mut screen = interface::Screen::new()?;
+    let mut screen = match runcb.screen.take().map(Ok).unwrap_or_else(Screen::new) {
+        Ok(screen) => screen,
+        Err(ScreenError::RawModeUnavailable(e)) => {
+            warn!(
+                "terminal raw mode is unavailable, reading lines of input: {}",
+                e
+            );
+            set_render_mode(RenderMode::Plain);
+            Screen::from_stdin_lines()?
+        }
+        Err(e) => return Err(e.into()),
+    };
+    if let Some(handle) = runcb.cancel.take() {
+        screen = screen.with_cancel_handle(handle);
+    }
+    let history = match runcb.history.take() {
+        Some(h) => h,
+        None => History::load(History::default_path()).unwrap_or_else(|e| {
+            error!("failed to load history: {}", e);
+            History::new()
+        }),
+    };
+    screen = screen.with_history(history);
+    if let Some(max) = runcb.max_completions {
+        screen = screen.with_max_completions(max);
+    }
 
     #[cfg(feature = "racer-completion")]
     let cache = {
@@ -177,14 +287,18 @@ where
     let cache = CacheWrapper;
 
     let mut reevaluate: Option<String> = None;
+    let mut indent = String::new();
 
     let output = loop {
-        io::stdout()
-            .execute(crossterm::style::Print(read.prompt(true)))
-            .ok();
+        let plain = render_mode() == RenderMode::Plain;
+
+        let prompt = read.prompt(!plain);
+        io::stdout().execute(crossterm::style::Print(&prompt)).ok();
 
         let mut input_buf = interface::InputBuffer::new();
 
+        input_buf.insert_str(&indent);
+
         if let Some(buf) = read.data.editing_src.take() {
             if reevaluate.is_none() {
                 input_buf.insert_str(&buf);
@@ -194,21 +308,40 @@ where
         if let Some(val) = reevaluate.take() {
             read.line_input(&val);
         } else {
-            if do_read(&mut read, &mut screen, input_buf, &cache)? {
+            if do_read(
+                &mut read,
+                &mut screen,
+                input_buf,
+                &cache,
+                prompt_width(&prompt),
+            )? {
                 break read.output().to_owned();
             }
         }
 
+        let line = read.input_buffer_line().to_owned();
+
         match read.read() {
-            ReadResult::Read(repl) => read = repl,
+            ReadResult::Read(repl) => {
+                read = repl;
+                indent = read
+                    .data
+                    .auto_indent
+                    .as_ref()
+                    .map(|i| crate::input::next_line_indent(&line, i))
+                    .unwrap_or_default();
+            }
             ReadResult::Eval(repl) => {
+                indent.clear();
                 match do_eval(repl, &mut runcb) {
                     Ok((repl, reeval)) => {
                         read = repl;
                         reevaluate = reeval;
 
                         // prep for next read
-                        interface::erase_current_line(io::stdout())?.flush()?;
+                        if !plain {
+                            interface::erase_current_line(io::stdout())?.flush()?;
+                        }
                     }
                     Err(r) => break r.output().to_owned(),
                 }
@@ -216,6 +349,10 @@ where
         }
     };
 
+    if let Err(e) = screen.history().save() {
+        error!("failed to save history: {}", e);
+    }
+
     let _ = std::panic::take_hook(); // remove the previous set_hook
     Ok(output)
 }
@@ -265,6 +402,7 @@ fn do_read<D>(
     screen: &mut Screen,
     buf: InputBuffer,
     cache: &CacheWrapper,
+    prompt_width: usize,
 ) -> io::Result<bool> {
     use crossterm::event::{Event::*, KeyCode::*, KeyEvent, KeyModifiers};
     const ENTER: Event = Key(KeyEvent {
@@ -281,13 +419,17 @@ fn do_read<D>(
     });
     const STOPEVENTS: &[Event] = &[ENTER, TAB, BREAK];
 
-    crossterm::terminal::enable_raw_mode().map_err(|e| map_xterm_err(e, "enabling raw mode"))?;
+    let raw_mode = screen.raw_mode();
+    if raw_mode {
+        crossterm::terminal::enable_raw_mode()
+            .map_err(|e| map_xterm_err(e, "enabling raw mode"))?;
+    }
 
     let mut i = Some(buf);
 
-    let initial = crossterm::cursor::position().unwrap_or((0, 0));
-
-    let mut completion_writer = interface::CompletionWriter::new();
+    // the input starts after the prompt
+    let row = crossterm::cursor::position().map(|x| x.1).unwrap_or(0);
+    let initial = (prompt_width as u16, row);
 
     let rdata = &repl.data;
     let treecmpltr = TreeCompleter::build(&rdata.cmdtree);
@@ -296,25 +438,36 @@ fn do_read<D>(
     let codecmpltr = CodeCompleter::build(rdata);
 
     loop {
-        let (mut input, ev) = interface::read_until(screen, initial, i.take().unwrap(), STOPEVENTS);
+        let (mut input, ev) = read_until(screen, initial, i.take().unwrap(), STOPEVENTS);
+        let ev = match ev {
+            ReadOutcome::Stopped(ev) => ev,
+            ReadOutcome::Cancelled | ReadOutcome::Eof => BREAK,
+        };
 
         if ev == ENTER {
             repl.line_input(&input.buffer());
-            write!(&mut io::stdout(), "\n\r")?;
-            crossterm::terminal::disable_raw_mode()
-                .map_err(|e| map_xterm_err(e, "disabling raw mode"))?;
+            screen.push_history(input.buffer());
+            if render_mode() == RenderMode::Plain {
+                writeln!(&mut io::stdout(), "{}", input)?;
+            } else {
+                write!(&mut io::stdout(), "\n\r")?;
+            }
+            if raw_mode {
+                crossterm::terminal::disable_raw_mode()
+                    .map_err(|e| map_xterm_err(e, "disabling raw mode"))?;
+            }
             break Ok(false);
         } else if ev == TAB {
             let line = input.buffer();
-            if completion_writer.is_same_input(&line) {
-                completion_writer.next_completion();
-            } else {
-                let f = |start| input.ch_len().saturating_sub(line[start..].chars().count());
+            let line = line.as_str();
+            screen.complete(initial, &mut input, || {
+                let len = line.chars().count();
+                let f = |start| len.saturating_sub(line[start..].chars().count());
 
-                let tree_chpos = f(TreeCompleter::word_break(&line));
-                let mods_chpos = f(ModulesCompleter::word_break(&line));
+                let tree_chpos = f(TreeCompleter::word_break(line));
+                let mods_chpos = f(ModulesCompleter::word_break(line));
                 #[cfg(feature = "racer-completion")]
-                let code_chpos = f(CodeCompleter::word_break(&line));
+                let code_chpos = f(CodeCompleter::word_break(line));
 
                 let completions = if line.starts_with(crate::CMD_PREFIX) {
                     Box::new(std::iter::empty()) as Box<dyn Iterator<Item = CItem>>
@@ -331,17 +484,15 @@ fn do_read<D>(
                     Box::new(c) as Box<dyn Iterator<Item = CItem>>
                 };
 
-                let completions = completions
-                    .chain(complete_cmdtree(&treecmpltr, &line, tree_chpos))
-                    .chain(complete_mods(&modscmpltr, &line, mods_chpos));
-
-                completion_writer.new_completions(completions);
-            }
-
-            completion_writer.overwrite_completion(initial, &mut input)?;
+                completions
+                    .chain(complete_cmdtree(&treecmpltr, line, tree_chpos))
+                    .chain(complete_mods(&modscmpltr, line, mods_chpos))
+            })?;
         } else if ev == BREAK {
-            crossterm::terminal::disable_raw_mode()
-                .map_err(|e| map_xterm_err(e, "disabling raw mode"))?;
+            if raw_mode {
+                crossterm::terminal::disable_raw_mode()
+                    .map_err(|e| map_xterm_err(e, "disabling raw mode"))?;
+            }
             break Ok(true);
         }
 
@@ -395,11 +546,58 @@ where
     ResultFn: FnMut(usize, kserd::Kserd<'static>, &Repl<Read, D>),
 {
     let rx = repl.output_listen();
+    // compiler progress is shown with the spinner rather than written
+    let progress = match render_mode() {
+        RenderMode::Plain => crossbeam_channel::never(),
+        _ => repl.compile_listen(),
+    };
 
     let jh = std::thread::spawn(move || {
-        let mut covered_lines = 0;
-        for chg in rx.iter() {
-            covered_lines = interface::write_output_chg(covered_lines, chg).unwrap_or(0);
+        if render_mode() == RenderMode::Plain {
+            let mut transcript = PlainTranscript::default();
+            let mut stdout = io::stdout();
+            for chg in rx.iter() {
+                transcript.write(&mut stdout, chg).ok();
+            }
+            transcript.finish(&mut stdout).ok();
+            return;
+        }
+
+        let mut cursor = interface::OutputCursor::new();
+        let mut spinner = interface::Spinner::new();
+        let mut progress = progress;
+        loop {
+            let chg = crossbeam_channel::select! {
+                recv(rx) -> chg => match chg {
+                    Ok(chg) => chg,
+                    Err(_) => break,
+                },
+                recv(progress) -> line => {
+                    match line {
+                        Ok(line) => {
+                            if spinner.update(&line) {
+                                spinner.render(&mut io::stdout()).ok();
+                            }
+                        }
+                        Err(_) => progress = crossbeam_channel::never(),
+                    }
+                    continue;
+                },
+                default(SPINNER_INTERVAL) => {
+                    if spinner.is_active() {
+                        spinner.render(&mut io::stdout()).ok();
+                    }
+                    continue;
+                },
+            };
+
+            if spinner.is_active() {
+                spinner.finish(&mut io::stdout()).ok();
+                // restore the line the spinner was drawn over
+                cursor.redraw().ok();
+            }
+
+            cursor.write(chg).ok();
         }
     });
 